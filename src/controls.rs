@@ -0,0 +1,163 @@
+//! Turning the keys a player presses and holds into actions on their game, repeating moves and
+//! soft drops for as long as their keys are held.
+
+use game::Game;
+use settings::{Action, Settings};
+use tetris::game_state::TimedGameState;
+use tetris::versus::VersusMatch;
+
+/// A game the keys of a player control: a single player game, or one side of a versus match.
+pub trait Controlled {
+    /// Makes the given action on the falling tetromino. Returns `true` if the tetromino moved, for
+    /// moves and soft drops.
+    fn apply_action(&mut self, action: Action) -> bool;
+
+    /// The number of milliseconds it takes the falling tetromino to fall by one row at the
+    /// current level.
+    fn fall_interval(&self) -> u32;
+}
+
+impl Controlled for Game {
+    fn apply_action(&mut self, action: Action) -> bool {
+        apply_action(action, self.timed_game_state_mut())
+    }
+
+    fn fall_interval(&self) -> u32 {
+        Game::fall_interval(self)
+    }
+}
+
+/// The game of the player with the given index, 0 or 1, in a versus match.
+pub struct VersusPlayer<'a> {
+    pub versus_match: &'a mut VersusMatch,
+    pub index: usize
+}
+
+impl<'a> Controlled for VersusPlayer<'a> {
+    fn apply_action(&mut self, action: Action) -> bool {
+        self.versus_match.update_player(self.index, |state| apply_action(action, state)).unwrap_or(false)
+    }
+
+    fn fall_interval(&self) -> u32 {
+        self.versus_match.player(self.index).fall_interval()
+    }
+}
+
+/// The keys held by a player, and when the actions they make repeat next.
+pub struct Controls {
+    pub settings: Settings,
+    /// The codes of the keys that are held, with the action each one makes.
+    keys_down: Vec<(String, Action)>,
+    /// The number of milliseconds until the held move repeats.
+    time_to_repeat: u32,
+    /// The number of milliseconds until the held soft drop moves the tetromino down again.
+    time_to_soft_drop: u32
+}
+
+impl Controls {
+
+    pub fn new(settings: Settings) -> Self {
+        Controls {
+            settings,
+            keys_down: Vec::new(),
+            time_to_repeat: 0,
+            time_to_soft_drop: 0
+        }
+    }
+
+    /// Handles the key with the given code being pressed. Returns `true` if it makes an action.
+    pub fn key_down<G: Controlled>(&mut self, code: &str, game: &mut G) -> bool {
+        let action = match self.settings.action_for_key(code) {
+            Some(action) => action,
+            None => return false,
+        };
+        if self.keys_down.iter().any(|&(ref key, _)| key == code) {
+            return true;
+        }
+        self.keys_down.push((String::from(code), action));
+
+        match action {
+            Action::MoveLeft | Action::MoveRight => self.time_to_repeat = self.settings.das,
+            Action::SoftDrop => self.time_to_soft_drop = self.soft_drop_interval(game),
+            _ => {}
+        }
+        game.apply_action(action);
+        true
+    }
+
+    /// Handles the key with the given code being released.
+    pub fn key_up(&mut self, code: &str) {
+        self.keys_down.retain(|&(ref key, _)| key != code);
+    }
+
+    /// Forgets the keys held, as when the page loses focus and does not hear them being released.
+    pub fn release_all(&mut self) {
+        self.keys_down.clear();
+    }
+
+    /// Lets the given number of milliseconds pass, repeating the held move and soft drop when
+    /// they are due.
+    pub fn advance_time<G: Controlled>(&mut self, game: &mut G, t: u32) {
+        // The most recently pressed direction wins when both are held.
+        let held_move = self.keys_down.iter().rev()
+            .map(|&(_, action)| action)
+            .find(|&action| action == Action::MoveLeft || action == Action::MoveRight);
+        if let Some(action) = held_move {
+            self.repeat_move(action, game, t);
+        }
+
+        if self.keys_down.iter().any(|&(_, action)| action == Action::SoftDrop) {
+            self.repeat_soft_drop(game, t);
+        }
+    }
+
+    /// Repeats the given move as often as it is due in `t` milliseconds.
+    fn repeat_move<G: Controlled>(&mut self, action: Action, game: &mut G, t: u32) {
+        let mut t = t;
+        while t >= self.time_to_repeat {
+            t -= self.time_to_repeat;
+            if self.settings.arr == 0 {
+                // Stay charged, so that the next tetromino moves all the way too.
+                while game.apply_action(action) {}
+                self.time_to_repeat = 0;
+                return;
+            }
+            game.apply_action(action);
+            self.time_to_repeat = self.settings.arr;
+        }
+        self.time_to_repeat -= t;
+    }
+
+    /// Moves the tetromino down as often as soft drop is due in `t` milliseconds.
+    fn repeat_soft_drop<G: Controlled>(&mut self, game: &mut G, t: u32) {
+        let mut t = t;
+        while t >= self.time_to_soft_drop {
+            t -= self.time_to_soft_drop;
+            game.apply_action(Action::SoftDrop);
+            self.time_to_soft_drop = self.soft_drop_interval(game);
+        }
+        self.time_to_soft_drop -= t;
+    }
+
+    /// Returns the number of milliseconds between two rows of soft drop.
+    fn soft_drop_interval<G: Controlled>(&self, game: &G) -> u32 {
+        (game.fall_interval() / self.settings.soft_drop_factor.max(1)).max(1)
+    }
+}
+
+/// Makes the given action on the game. Returns `true` if the tetromino moved, for moves and soft
+/// drops, which move it by one row.
+fn apply_action(action: Action, state: &mut TimedGameState) -> bool {
+    match action {
+        Action::MoveLeft => state.move_left(),
+        Action::MoveRight => state.move_right(),
+        Action::SoftDrop => state.soft_drop(),
+        Action::HardDrop => state.hard_drop(),
+        Action::RotateLeft => state.rotate_left(),
+        Action::RotateRight => state.rotate_right(),
+        Action::Hold => {
+            state.hold();
+            true
+        },
+    }
+}
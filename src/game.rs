@@ -0,0 +1,353 @@
+//! A game that JavaScript can create and control, so that a page can run several games or draw
+//! its own interface. The page that `main` builds plays its single player games through it too.
+
+use wasm_bindgen::prelude::*;
+
+use serde_json;
+use tetris;
+use tetris::config::{GameConfig, Randomizer, RotationSystem, MIN_MATRIX_HEIGHT, MIN_MATRIX_WIDTH};
+use tetris::game_mode::{GameEnd, GameMode};
+use tetris::game_state::{GameEvent, TimedGameState};
+use tetris::placement::Input;
+use tetris::view::{Square, ViewModel};
+
+/// Added to the code of a square of the falling tetromino in `Game::squares`.
+const FALLING_FLAG: u8 = 16;
+/// Added to the code of a square of the ghost of the falling tetromino in `Game::squares`.
+const GHOST_FLAG: u8 = 32;
+
+/// The settings a `Game` is created with. They start out as those of an endless game with the
+/// usual matrix and preview, where the tetrominoes come in a different order every time.
+#[wasm_bindgen]
+#[derive(Debug, Clone)]
+pub struct GameOptions {
+    config: GameConfig,
+    mode: GameMode
+}
+
+#[wasm_bindgen]
+impl GameOptions {
+
+    #[wasm_bindgen(constructor)]
+    pub fn new() -> GameOptions {
+        GameOptions { config: GameConfig::default(), mode: GameMode::Endless }
+    }
+
+    /// Plays the mode with the given name, `endless`, `ultra`, `marathon`, `dig` or `survival`.
+    pub fn set_mode(&mut self, mode: &str) -> Result<(), JsValue> {
+        self.mode = mode_from_name(mode).ok_or_else(|| JsValue::from_str(&format!("unknown mode {}", mode)))?;
+        Ok(())
+    }
+
+    /// Makes the tetrominoes and the garbage always come the same way for the same seed.
+    pub fn set_seed(&mut self, seed: u32) {
+        self.config.seed = Some(u64::from(seed));
+    }
+
+    /// Shows the given number of tetrominoes in the next preview.
+    pub fn set_preview_length(&mut self, preview_length: usize) {
+        self.config.preview_length = preview_length;
+    }
+
+    /// Plays on a matrix with the given number of columns and rows, both visible and hidden. The
+    /// matrix must be at least 4 by 4.
+    pub fn set_matrix_size(&mut self, width: usize, height: usize) -> Result<(), JsValue> {
+        if width < MIN_MATRIX_WIDTH || height < MIN_MATRIX_HEIGHT {
+            return Err(JsValue::from_str(&format!("the matrix must be at least {} by {}", MIN_MATRIX_WIDTH, MIN_MATRIX_HEIGHT)));
+        }
+        self.config.matrix_width = width;
+        self.config.matrix_height = height;
+        Ok(())
+    }
+
+    /// Decides the order of the tetrominoes with the randomizer with the given name, `bag` for
+    /// sets of 7 or `random` for any type at any time.
+    pub fn set_randomizer(&mut self, randomizer: &str) -> Result<(), JsValue> {
+        self.config.randomizer = match randomizer {
+            "bag" => Randomizer::SevenBag,
+            "random" => Randomizer::Random,
+            _ => return Err(JsValue::from_str(&format!("unknown randomizer {}", randomizer))),
+        };
+        Ok(())
+    }
+
+    /// Rotates the tetrominoes with the rotation system with the given name, `srs` or
+    /// `no-kicks`.
+    pub fn set_rotation_system(&mut self, rotation_system: &str) -> Result<(), JsValue> {
+        self.config.rotation_system = match rotation_system {
+            "srs" => RotationSystem::Srs,
+            "no-kicks" => RotationSystem::NoKicks,
+            _ => return Err(JsValue::from_str(&format!("unknown rotation system {}", rotation_system))),
+        };
+        Ok(())
+    }
+}
+
+impl Default for GameOptions {
+    fn default() -> Self {
+        GameOptions::new()
+    }
+}
+
+/// A single player game, played in time that the page lets pass with `advance_time`. Actions do
+/// nothing once the game has ended.
+#[wasm_bindgen]
+pub struct Game {
+    state: TimedGameState
+}
+
+#[wasm_bindgen]
+impl Game {
+
+    /// Creates an endless game where the tetrominoes come in a different order every time.
+    #[wasm_bindgen(constructor)]
+    pub fn new() -> Game {
+        Game::with_options(&GameOptions::new())
+    }
+
+    /// Creates an endless game where the tetrominoes always come in the same order for the same
+    /// seed.
+    pub fn with_seed(seed: u32) -> Game {
+        let mut options = GameOptions::new();
+        options.set_seed(seed);
+        Game::with_options(&options)
+    }
+
+    /// Creates a game of the given mode, `endless`, `ultra`, `marathon`, `dig` or `survival`,
+    /// where the tetrominoes always come in the same order for the same seed.
+    pub fn with_mode(mode: &str, seed: u32) -> Result<Game, JsValue> {
+        let mut options = GameOptions::new();
+        options.set_mode(mode)?;
+        options.set_seed(seed);
+        Ok(Game::with_options(&options))
+    }
+
+    /// Creates a game with the given settings.
+    pub fn with_options(options: &GameOptions) -> Game {
+        let game_state = tetris::game_state::GameState::with_config(options.config.clone());
+        Game { state: TimedGameState::with_game_state(game_state, options.mode.clone()) }
+    }
+
+    /// Creates an endless game starting from the board of the first page of the given fumen.
+    pub fn from_fumen(fumen: &str) -> Result<Game, JsValue> {
+        Game::from_fumen_with_options(fumen, &GameOptions::new())
+    }
+
+    /// Creates a game with the given settings, starting from the board of the first page of the
+    /// given fumen.
+    pub fn from_fumen_with_options(fumen: &str, options: &GameOptions) -> Result<Game, JsValue> {
+        let pages = tetris::fumen::decode(fumen).map_err(|e| JsValue::from_str(&e.to_string()))?;
+        let page = pages.first().ok_or_else(|| JsValue::from_str("fumen has no pages"))?;
+        let game_state = tetris::builder::GameStateBuilder::new()
+            .config(options.config.clone())
+            .fumen_page(page)
+            .build()
+            .map_err(|e| JsValue::from_str(&e.to_string()))?;
+        Ok(Game { state: TimedGameState::with_game_state(game_state, options.mode.clone()) })
+    }
+
+    // Actions
+
+    /// Moves the falling tetromino to the left. Returns `true` if it moved.
+    pub fn move_left(&mut self) -> bool {
+        self.state.move_left()
+    }
+
+    /// Moves the falling tetromino to the right. Returns `true` if it moved.
+    pub fn move_right(&mut self) -> bool {
+        self.state.move_right()
+    }
+
+    /// Rotates the falling tetromino counter-clockwise. Returns `true` if it rotated.
+    pub fn rotate_left(&mut self) -> bool {
+        self.state.rotate_left()
+    }
+
+    /// Rotates the falling tetromino clockwise. Returns `true` if it rotated.
+    pub fn rotate_right(&mut self) -> bool {
+        self.state.rotate_right()
+    }
+
+    /// Moves the falling tetromino down by one row. Returns `true` if it moved.
+    pub fn soft_drop(&mut self) -> bool {
+        self.state.soft_drop()
+    }
+
+    /// Drops the falling tetromino to the ground and locks it. Returns `false` if this ended the
+    /// game.
+    pub fn hard_drop(&mut self) -> bool {
+        self.state.hard_drop()
+    }
+
+    /// Holds the falling tetromino, if it can be held.
+    pub fn hold(&mut self) {
+        self.state.hold()
+    }
+
+    /// Lets the given number of milliseconds pass. Returns `false` if the game has ended.
+    pub fn advance_time(&mut self, ms: u32) -> bool {
+        self.state.advance_time(ms)
+    }
+
+    /// The number of milliseconds it takes the falling tetromino to fall by one row at the
+    /// current level.
+    pub fn fall_interval(&self) -> u32 {
+        self.state.fall_interval()
+    }
+
+    // State
+
+    /// The number of columns of the matrix.
+    pub fn width(&self) -> usize {
+        self.state.game_state().matrix_width()
+    }
+
+    /// The number of rows of the matrix, both visible and hidden.
+    pub fn height(&self) -> usize {
+        self.state.game_state().matrix_height()
+    }
+
+    /// The number of rows at the bottom of the matrix that are visible.
+    pub fn visible_height(&self) -> usize {
+        self.state.game_state().config().visible_height()
+    }
+
+    /// Returns a code for each square of the matrix, row by row from the bottom, each row from
+    /// left to right. The code is 0 for an empty square, 1 to 7 for a square of a tetromino of
+    /// type I, O, T, J, L, S or Z, 8 for garbage and 9 for an item block. 16 is added for a
+    /// square of the falling tetromino, and 32 for a square of its ghost.
+    pub fn squares(&self) -> Vec<u8> {
+        let view = ViewModel::new(&self.state);
+        let mut squares = Vec::with_capacity(view.width * view.height);
+        for row in 0..view.height {
+            for col in 0..view.width {
+                squares.push(match *view.square(col, row) {
+                    Square::Empty => 0,
+                    Square::Locked(tetris::Cell::Garbage) => 8,
+                    Square::Locked(tetris::Cell::Item) => 9,
+                    Square::Locked(ref cell) => cell.tetromino_type().map_or(0, tetromino_type_code),
+                    Square::Falling(ref tt) => FALLING_FLAG + tetromino_type_code(tt),
+                    Square::Ghost(ref tt) => GHOST_FLAG + tetromino_type_code(tt),
+                });
+            }
+        }
+        squares
+    }
+
+    /// The letters of the tetrominoes that come next, the first being the next one, such as
+    /// `TIOSZ`.
+    pub fn preview(&self) -> String {
+        self.state.next_preview().iter().map(|tt| tt.to_char()).collect()
+    }
+
+    /// The letter of the held tetromino, or an empty string if none is held.
+    pub fn hold_piece(&self) -> String {
+        self.state.held().as_ref().map_or(String::new(), |tt| tt.to_char().to_string())
+    }
+
+    /// Whether the falling tetromino can be held.
+    pub fn can_hold(&self) -> bool {
+        self.state.can_hold()
+    }
+
+    /// The number of points scored so far.
+    pub fn score(&self) -> u32 {
+        self.state.score()
+    }
+
+    /// The current level.
+    pub fn level(&self) -> u32 {
+        self.state.level()
+    }
+
+    /// The total number of lines cleared so far.
+    pub fn lines_cleared(&self) -> usize {
+        self.state.game_state().lines_cleared()
+    }
+
+    /// The total number of tetrominoes locked so far.
+    pub fn pieces_placed(&self) -> usize {
+        self.state.game_state().pieces_placed()
+    }
+
+    /// The number of milliseconds that have passed since the game started.
+    pub fn elapsed(&self) -> u32 {
+        self.state.elapsed()
+    }
+
+    /// Whether the game has ended.
+    pub fn is_over(&self) -> bool {
+        self.state.end().is_some()
+    }
+
+    /// Why the game ended, `topped out`, `time up` or `completed`, or an empty string if it is
+    /// still going on.
+    pub fn end_reason(&self) -> String {
+        String::from(match self.state.end() {
+            Some(&GameEnd::ToppedOut) => "topped out",
+            Some(&GameEnd::TimeUp) => "time up",
+            Some(&GameEnd::Completed) => "completed",
+            None => "",
+        })
+    }
+
+    /// Returns everything there is to draw of the game as JSON: the squares of the matrix by
+    /// column and then row, the next preview, the hold and the numbers shown next to the matrix.
+    pub fn view_json(&self) -> String {
+        serde_json::to_string(&ViewModel::new(&self.state)).unwrap_or_default()
+    }
+
+    /// Returns the line clears and perfect clears since this was last called, as a JSON array.
+    pub fn take_events_json(&mut self) -> String {
+        serde_json::to_string(&self.state.take_events()).unwrap_or_default()
+    }
+}
+
+impl Game {
+
+    /// Returns the underlying `TimedGameState`, for drawing the game.
+    pub(crate) fn timed_game_state(&self) -> &TimedGameState {
+        &self.state
+    }
+
+    /// Returns the underlying `TimedGameState`, for playing the game.
+    pub(crate) fn timed_game_state_mut(&mut self) -> &mut TimedGameState {
+        &mut self.state
+    }
+
+    /// Makes the given input. Returns `false` if it ended the game.
+    pub(crate) fn apply_input(&mut self, input: Input) -> bool {
+        self.state.apply_input(input)
+    }
+
+    /// Removes and returns the events that happened since this was last called.
+    pub(crate) fn take_events(&mut self) -> Vec<GameEvent> {
+        self.state.take_events()
+    }
+}
+
+/// Returns the mode with the given name, `endless`, `ultra`, `marathon`, `dig` or `survival`, with
+/// its default settings, or `None` if there is no mode with that name.
+pub fn mode_from_name(name: &str) -> Option<GameMode> {
+    match name {
+        "endless" => Some(GameMode::Endless),
+        "ultra" => Some(GameMode::ultra()),
+        "marathon" => Some(GameMode::marathon(1)),
+        "dig" => Some(GameMode::dig()),
+        "survival" => Some(GameMode::survival()),
+        _ => None,
+    }
+}
+
+/// Returns the code of the given tetromino type in `Game::squares`.
+fn tetromino_type_code(tt: &tetris::TetrominoType) -> u8 {
+    match *tt {
+        tetris::TetrominoType::I => 1,
+        tetris::TetrominoType::O => 2,
+        tetris::TetrominoType::T => 3,
+        tetris::TetrominoType::J => 4,
+        tetris::TetrominoType::L => 5,
+        tetris::TetrominoType::S => 6,
+        tetris::TetrominoType::Z => 7,
+    }
+}
@@ -1,15 +1,28 @@
 extern crate cfg_if;
 extern crate wasm_bindgen;
 extern crate web_sys;
+#[macro_use] extern crate serde;
+extern crate serde_json;
 extern crate tetris;
 
+mod controls;
+mod game;
+mod settings;
 mod utils;
 
+pub use game::{Game, GameOptions};
+
 use cfg_if::cfg_if;
 use wasm_bindgen::prelude::*;
 use wasm_bindgen::JsCast;
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::rc::Rc;
 use std::sync::{Arc,Mutex};
 
+use controls::{Controls, VersusPlayer};
+use settings::{Action, Settings, MAX_DELAY, MAX_PREVIEW_LENGTH, MAX_SOFT_DROP_FACTOR, MIN_SOFT_DROP_FACTOR, PAUSE_KEYS, RESTART_KEYS};
+
 cfg_if! {
     // When the `wee_alloc` feature is enabled, use `wee_alloc` as the global
     // allocator.
@@ -29,13 +42,14 @@ pub fn main() -> Result<(), JsValue> {
     let svg = document.create_element_ns(Some("http://www.w3.org/2000/svg"), "svg")?
         .dyn_into::<web_sys::SvgsvgElement>()?;
 
-    let gui_width = 800;
-    let gui_height = 800;
+    let settings = Settings::load(&window);
+    let game = initial_game(&window, settings.preview_length);
 
-    let matrix_left_px = 250;
-    let matrix_top_px = 70;
-    let matrix_width_px = 300;
-    let matrix_height_px = 660;
+    let gui_width = match game {
+        PageGame::Single(_) => PLAYER_WIDTH_PX,
+        PageGame::Versus(_) => 2 * PLAYER_WIDTH_PX,
+    };
+    let gui_height = 800;
 
     (svg.as_ref() as &web_sys::Element)
         .set_attribute("viewBox", &format!("0 0 {} {}", gui_width, gui_height))?;
@@ -58,6 +72,360 @@ pub fn main() -> Result<(), JsValue> {
 //
 //    (svg.as_ref() as &web_sys::Node).append_child(background_rect.as_ref())?;
 
+    match game {
+        PageGame::Single(_) => draw_background(&svg, &document, 0)?,
+        PageGame::Versus(_) => {
+            draw_background(&svg, &document, 0)?;
+            draw_background(&svg, &document, PLAYER_WIDTH_PX)?;
+        }
+    }
+
+    let placed_squares_g = document.create_element_ns(Some("http://www.w3.org/2000/svg"), "g")?
+        .dyn_into::<web_sys::SvggElement>()?;
+    (svg.as_ref() as &web_sys::Node).append_child(placed_squares_g.as_ref())?;
+
+    let mut overlay = Overlay::new(&svg, &document, gui_width, gui_height)?;
+
+    (body.as_ref() as &web_sys::Node).append_child(svg.as_ref())?;
+
+    let is_single = match game {
+        PageGame::Single(_) => true,
+        PageGame::Versus(_) => false,
+    };
+    let session = Arc::new(Mutex::new(Session { game, paused: false }));
+    let session_clone = session.clone();
+
+    let autoplay = Arc::new(Mutex::new(Autoplay::new()));
+    let autoplay_clone = autoplay.clone();
+
+    // Each player has their own keys, held and repeated apart from those of the other player.
+    let player_settings = if is_single { vec![settings] } else { settings.versus_players().to_vec() };
+    let controls = Arc::new(Mutex::new(player_settings.into_iter().map(Controls::new).collect::<Vec<_>>()));
+    let frame_controls = controls.clone();
+
+    // The bot can only take over a single player game, and the keys of a versus match are fixed.
+    if is_single {
+        add_autoplay_button(&document, &body, autoplay.clone())?;
+        add_settings_panel(&document, &body, controls.clone(), session.clone())?;
+    }
+
+    let player_count = if is_single { 1 } else { 2 };
+    let mut player_views = Vec::new();
+    for player in 0..player_count {
+        player_views.push(PlayerView::new(player * PLAYER_WIDTH_PX, &placed_squares_g, &document)?);
+    }
+
+    let mut frame_clock = FrameClock::new(&window);
+
+    // The closure asks for the next animation frame to call itself again, so it needs a handle to
+    // itself, which is filled in once it exists.
+    let frame_callback: Rc<RefCell<Option<Closure<FnMut()>>>> = Rc::new(RefCell::new(None));
+    let next_frame_callback = frame_callback.clone();
+
+    *frame_callback.borrow_mut() = Some(Closure::wrap(Box::new(move || {
+        let tick = frame_clock.tick();
+        let mut session = session.lock().unwrap();
+        // A gap that long means the page was not being looked at, so the game waits for the
+        // player to come back.
+        if tick.is_none() {
+            session.pause();
+            for controls in frame_controls.lock().unwrap().iter_mut() {
+                controls.release_all();
+            }
+        }
+        // Time stands still while the game is paused or over.
+        let running = session.is_running();
+        let t = tick.unwrap_or(0);
+        let t = if running { t } else { 0 };
+        overlay.draw(session.overlay_lines());
+
+        match session.game {
+            PageGame::Single(ref mut game) => {
+                if running {
+                    frame_controls.lock().unwrap()[0].advance_time(game, t);
+                }
+                autoplay.lock().unwrap().advance_time(game, t);
+                game.advance_time(t);
+                let events = game.take_events();
+                player_views[0].draw(tetris::view::ViewModel::new(game.timed_game_state()), &events, t);
+            },
+            PageGame::Versus(ref mut versus_match) => {
+                if running {
+                    for (index, controls) in frame_controls.lock().unwrap().iter_mut().enumerate() {
+                        controls.advance_time(&mut VersusPlayer { versus_match: &mut *versus_match, index }, t);
+                    }
+                }
+                versus_match.advance_time(t);
+                for (index, player_view) in player_views.iter_mut().enumerate() {
+                    let events = versus_match.take_events(index);
+                    player_view.draw(tetris::view::ViewModel::new(versus_match.player(index)), &events, t);
+                }
+            },
+        }
+
+        request_animation_frame(next_frame_callback.borrow().as_ref().unwrap());
+    }) as Box<FnMut()>));
+    request_animation_frame(frame_callback.borrow().as_ref().unwrap());
+
+    let session = session_clone;
+    let released_controls = controls.clone();
+
+    // Pause when the player switches to another window or tab, where the keys they release are
+    // not heard.
+    let blurred_session = session.clone();
+    let blurred_controls = controls.clone();
+    let a = Closure::wrap(Box::new(move || {
+        blurred_session.lock().unwrap().pause();
+        for controls in blurred_controls.lock().unwrap().iter_mut() {
+            controls.release_all();
+        }
+    }) as Box<FnMut()>);
+    (window.as_ref() as &web_sys::EventTarget).add_event_listener_with_callback("blur", a.as_ref().unchecked_ref())?;
+    a.forget();
+
+    // Also pause when the tab is hidden, which does not always take the focus away.
+    let hidden_session = session.clone();
+    let hidden_controls = controls.clone();
+    let a = Closure::wrap(Box::new(move || {
+        let document = web_sys::window().and_then(|window| window.document());
+        if document.is_some_and(|document| document.hidden()) {
+            hidden_session.lock().unwrap().pause();
+            for controls in hidden_controls.lock().unwrap().iter_mut() {
+                controls.release_all();
+            }
+        }
+    }) as Box<FnMut()>);
+    (document.as_ref() as &web_sys::EventTarget).add_event_listener_with_callback("visibilitychange", a.as_ref().unchecked_ref())?;
+    a.forget();
+
+    let a = Closure::wrap(Box::new(move |event: web_sys::KeyboardEvent| {
+        let window = web_sys::window().expect("no global window");
+
+        if event.repeat() || is_typing(&event) {
+            return;
+        }
+
+        web_sys::console::log_1(&format!("keydown with code={}", event.code()).into());
+        let mut session = session.lock().unwrap();
+        let mut controls = controls.lock().unwrap();
+        match event.code().as_str() {
+            code if PAUSE_KEYS.contains(&code) => {
+                session.toggle_pause();
+                return;
+            },
+            code if RESTART_KEYS.contains(&code) && !session.is_running() => {
+                session.restart(&window, controls[0].settings.preview_length);
+                autoplay_clone.lock().unwrap().reset();
+                return;
+            },
+            _ => {}
+        }
+        if !session.is_running() {
+            return;
+        }
+
+        match session.game {
+            PageGame::Single(ref mut game) => {
+                controls[0].key_down(&event.code(), game);
+            },
+            PageGame::Versus(ref mut versus_match) => {
+                for (index, controls) in controls.iter_mut().enumerate() {
+                    if controls.key_down(&event.code(), &mut VersusPlayer { versus_match: &mut *versus_match, index }) {
+                        break;
+                    }
+                }
+            },
+        }
+    }) as Box<FnMut(_)>);
+    (document.as_ref() as &web_sys::EventTarget).add_event_listener_with_callback("keydown", a.as_ref().unchecked_ref())?;
+    a.forget();
+
+    let a = Closure::wrap(Box::new(move |event: web_sys::KeyboardEvent| {
+        for controls in released_controls.lock().unwrap().iter_mut() {
+            controls.key_up(&event.code());
+        }
+    }) as Box<FnMut(_)>);
+    (document.as_ref() as &web_sys::EventTarget).add_event_listener_with_callback("keyup", a.as_ref().unchecked_ref())?;
+    a.forget();
+
+    Ok(())
+}
+
+/// Adds a button that turns the bot on and off.
+fn add_autoplay_button(document: &web_sys::Document, body: &web_sys::HtmlElement, autoplay: Arc<Mutex<Autoplay>>) -> Result<(), JsValue> {
+    let autoplay_button = document.create_element("button")?
+        .dyn_into::<web_sys::HtmlElement>()?;
+    (autoplay_button.as_ref() as &web_sys::Node).set_text_content(Some("Watch the bot play"));
+    autoplay_button.style().set_property("position", "absolute")?;
+    autoplay_button.style().set_property("top", "10px")?;
+    autoplay_button.style().set_property("left", "10px")?;
+    (body.as_ref() as &web_sys::Node).append_child(autoplay_button.as_ref())?;
+
+    let autoplay_button_clone = autoplay_button.clone();
+    let a = Closure::wrap(Box::new(move || {
+        let mut autoplay = autoplay.lock().unwrap();
+        autoplay.toggle();
+        (autoplay_button_clone.as_ref() as &web_sys::Node).set_text_content(Some(
+            if autoplay.enabled { "Stop the bot" } else { "Watch the bot play" }
+        ));
+        // Don't keep focus, so that the space bar hard drops instead of clicking the button.
+        let _ = autoplay_button_clone.blur();
+    }) as Box<FnMut()>);
+    (autoplay_button.as_ref() as &web_sys::EventTarget).add_event_listener_with_callback("click", a.as_ref().unchecked_ref())?;
+    a.forget();
+
+    Ok(())
+}
+
+/// Returns `true` if the key was pressed while typing in a text field, such as those of the
+/// settings panel, rather than to play.
+fn is_typing(event: &web_sys::KeyboardEvent) -> bool {
+    event.target().map_or(false, |target| target.dyn_into::<web_sys::HtmlInputElement>().is_ok())
+}
+
+/// Adds a button that opens a panel for changing the keys and handling of a single player game.
+/// Saved settings are kept in local storage and loaded the next time the page is opened.
+fn add_settings_panel(document: &web_sys::Document, body: &web_sys::HtmlElement, controls: Arc<Mutex<Vec<Controls>>>, session: Arc<Mutex<Session>>) -> Result<(), JsValue> {
+    let settings_button = document.create_element("button")?
+        .dyn_into::<web_sys::HtmlElement>()?;
+    (settings_button.as_ref() as &web_sys::Node).set_text_content(Some("Settings"));
+    settings_button.style().set_property("position", "absolute")?;
+    settings_button.style().set_property("top", "10px")?;
+    settings_button.style().set_property("left", "170px")?;
+    (body.as_ref() as &web_sys::Node).append_child(settings_button.as_ref())?;
+
+    let panel = document.create_element("div")?
+        .dyn_into::<web_sys::HtmlElement>()?;
+    panel.style().set_property("display", "none")?;
+    panel.style().set_property("position", "absolute")?;
+    panel.style().set_property("top", "40px")?;
+    panel.style().set_property("left", "10px")?;
+    panel.style().set_property("padding", "10px")?;
+    panel.style().set_property("background", "#f0f0f0")?;
+    panel.style().set_property("border", "3px solid #606060")?;
+    panel.style().set_property("font-family", "sans-serif")?;
+    (body.as_ref() as &web_sys::Node).append_child(panel.as_ref())?;
+
+    let settings = controls.lock().unwrap()[0].settings.clone();
+    let mut binding_inputs = Vec::new();
+    for &action in Action::ALL.iter() {
+        let input = add_setting_input(document, &panel, action.label(), &settings.keys_for(action).join(", "))?;
+        binding_inputs.push((action, input));
+    }
+    let das_input = add_setting_input(document, &panel, "DAS (ms)", &settings.das.to_string())?;
+    let arr_input = add_setting_input(document, &panel, "ARR (ms)", &settings.arr.to_string())?;
+    let soft_drop_factor_input = add_setting_input(document, &panel, "Soft drop factor", &settings.soft_drop_factor.to_string())?;
+    let preview_length_input = add_setting_input(document, &panel, "Preview length", &settings.preview_length.to_string())?;
+
+    let hint = document.create_element("p")?;
+    (hint.as_ref() as &web_sys::Node).set_text_content(Some(&format!(
+        "Keys are named as in KeyboardEvent.code, such as ArrowLeft, KeyZ or Space, separated by commas. \
+         Each key can only be used for one action, and Escape, P, Enter and R are kept for pausing and restarting. \
+         The preview shows at most {} tetrominoes, as many as fit next to the matrix, and its length applies from the next game.",
+        MAX_PREVIEW_LENGTH
+    )));
+    (panel.as_ref() as &web_sys::Node).append_child(hint.as_ref())?;
+
+    let save_button = document.create_element("button")?
+        .dyn_into::<web_sys::HtmlElement>()?;
+    (save_button.as_ref() as &web_sys::Node).set_text_content(Some("Save"));
+    (panel.as_ref() as &web_sys::Node).append_child(save_button.as_ref())?;
+
+    let message = document.create_element("span")?;
+    (message.as_ref() as &web_sys::Element).set_attribute("style", "margin-left: 10px")?;
+    (panel.as_ref() as &web_sys::Node).append_child(message.as_ref())?;
+
+    let panel_clone = panel.clone();
+    let settings_button_clone = settings_button.clone();
+    let a = Closure::wrap(Box::new(move || {
+        let opening = panel_clone.style().get_property_value("display").unwrap_or_default() == "none";
+        panel_clone.style().set_property("display", if opening { "block" } else { "none" }).unwrap();
+        (settings_button_clone.as_ref() as &web_sys::Node).set_text_content(Some(
+            if opening { "Close settings" } else { "Settings" }
+        ));
+        // Nothing should happen in the game while the player is busy with the settings.
+        if opening {
+            session.lock().unwrap().pause();
+        }
+        let _ = settings_button_clone.blur();
+    }) as Box<FnMut()>);
+    (settings_button.as_ref() as &web_sys::EventTarget).add_event_listener_with_callback("click", a.as_ref().unchecked_ref())?;
+    a.forget();
+
+    let a = Closure::wrap(Box::new(move || {
+        let window = web_sys::window().expect("no global window");
+        let mut controls = controls.lock().unwrap();
+
+        let mut settings = controls[0].settings.clone();
+        for &(action, ref input) in &binding_inputs {
+            let keys = input.value().split(',').map(str::trim).filter(|key| !key.is_empty()).map(String::from).collect();
+            settings.set_keys(action, keys);
+        }
+        let numbers = parse_setting(&das_input, "DAS", 0, MAX_DELAY)
+            .and_then(|das| parse_setting(&arr_input, "ARR", 0, MAX_DELAY).map(|arr| (das, arr)))
+            .and_then(|numbers| parse_setting(&soft_drop_factor_input, "The soft drop factor", MIN_SOFT_DROP_FACTOR, MAX_SOFT_DROP_FACTOR).map(|factor| (numbers, factor)))
+            .and_then(|numbers| parse_setting(&preview_length_input, "The preview length", 0, MAX_PREVIEW_LENGTH as u32).map(|length| (numbers, length)));
+
+        let text = match settings.check_keys().and(numbers) {
+            Ok((((das, arr), soft_drop_factor), preview_length)) => {
+                settings.das = das;
+                settings.arr = arr;
+                settings.soft_drop_factor = soft_drop_factor;
+                settings.preview_length = preview_length as usize;
+                let saved = settings.save(&window);
+                controls[0].settings = settings;
+                match saved {
+                    Ok(()) => String::from("Saved"),
+                    Err(_) => String::from("Applied, but could not be saved for next time"),
+                }
+            },
+            Err(e) => e,
+        };
+        (message.as_ref() as &web_sys::Node).set_text_content(Some(&text));
+    }) as Box<FnMut()>);
+    (save_button.as_ref() as &web_sys::EventTarget).add_event_listener_with_callback("click", a.as_ref().unchecked_ref())?;
+    a.forget();
+
+    Ok(())
+}
+
+/// Adds a labelled text field to the settings panel, holding the given value.
+fn add_setting_input(document: &web_sys::Document, panel: &web_sys::HtmlElement, label: &str, value: &str) -> Result<web_sys::HtmlInputElement, JsValue> {
+    let row = document.create_element("div")?;
+    (row.as_ref() as &web_sys::Element).set_attribute("style", "margin-bottom: 5px")?;
+
+    let label_element = document.create_element("label")?;
+    (label_element.as_ref() as &web_sys::Element).set_attribute("style", "display: inline-block; width: 150px")?;
+    (label_element.as_ref() as &web_sys::Node).set_text_content(Some(label));
+    (row.as_ref() as &web_sys::Node).append_child(label_element.as_ref())?;
+
+    let input = document.create_element("input")?
+        .dyn_into::<web_sys::HtmlInputElement>()?;
+    input.set_value(value);
+    (row.as_ref() as &web_sys::Node).append_child(input.as_ref())?;
+
+    (panel.as_ref() as &web_sys::Node).append_child(row.as_ref())?;
+
+    Ok(input)
+}
+
+/// Reads a whole number from `min` to `max` from the given field, or describes why it is not one,
+/// naming the setting `name`.
+fn parse_setting(input: &web_sys::HtmlInputElement, name: &str, min: u32, max: u32) -> Result<u32, String> {
+    input.value().trim().parse().ok()
+        .filter(|&value| value >= min && value <= max)
+        .ok_or_else(|| format!("{} must be a whole number from {} to {}", name, min, max))
+}
+
+/// Draws the parts of a player's side of the page that never change: the matrix with its grid,
+/// and the boxes of the next preview and the hold. `left_px` is where the player's side starts.
+fn draw_background(svg: &web_sys::SvgsvgElement, document: &web_sys::Document, left_px: usize) -> Result<(), JsValue> {
+
+    let matrix_left_px = left_px + MATRIX_LEFT_PX;
+    let matrix_top_px = 70;
+    let matrix_width_px = 300;
+    let matrix_height_px = 660;
+
     let matrix_background_rect = document.create_element_ns(Some("http://www.w3.org/2000/svg"), "rect")?
         .dyn_into::<web_sys::SvgRectElement>()?;
 
@@ -106,7 +474,7 @@ pub fn main() -> Result<(), JsValue> {
     let next_preview_background_rect = document.create_element_ns(Some("http://www.w3.org/2000/svg"), "rect")?
         .dyn_into::<web_sys::SvgRectElement>()?;
 
-    (next_preview_background_rect.as_ref() as &web_sys::Element).set_attribute("x", "600")?;
+    (next_preview_background_rect.as_ref() as &web_sys::Element).set_attribute("x", &(left_px + 600).to_string())?;
     (next_preview_background_rect.as_ref() as &web_sys::Element).set_attribute("y", "100")?;
     (next_preview_background_rect.as_ref() as &web_sys::Element).set_attribute("width", "150")?;
     (next_preview_background_rect.as_ref() as &web_sys::Element).set_attribute("height", "500")?;
@@ -119,7 +487,7 @@ pub fn main() -> Result<(), JsValue> {
     let next_piece_highlight_rect = document.create_element_ns(Some("http://www.w3.org/2000/svg"), "rect")?
         .dyn_into::<web_sys::SvgRectElement>()?;
 
-    (next_piece_highlight_rect.as_ref() as &web_sys::Element).set_attribute("x", "600")?;
+    (next_piece_highlight_rect.as_ref() as &web_sys::Element).set_attribute("x", &(left_px + 600).to_string())?;
     (next_piece_highlight_rect.as_ref() as &web_sys::Element).set_attribute("y", "100")?;
     (next_piece_highlight_rect.as_ref() as &web_sys::Element).set_attribute("width", "150")?;
     (next_piece_highlight_rect.as_ref() as &web_sys::Element).set_attribute("height", "100")?;
@@ -132,7 +500,7 @@ pub fn main() -> Result<(), JsValue> {
     let hold_piece_background_rect = document.create_element_ns(Some("http://www.w3.org/2000/svg"), "rect")?
         .dyn_into::<web_sys::SvgRectElement>()?;
 
-    (hold_piece_background_rect.as_ref() as &web_sys::Element).set_attribute("x", "50")?;
+    (hold_piece_background_rect.as_ref() as &web_sys::Element).set_attribute("x", &(left_px + 50).to_string())?;
     (hold_piece_background_rect.as_ref() as &web_sys::Element).set_attribute("y", "100")?;
     (hold_piece_background_rect.as_ref() as &web_sys::Element).set_attribute("width", "150")?;
     (hold_piece_background_rect.as_ref() as &web_sys::Element).set_attribute("height", "100")?;
@@ -142,111 +510,698 @@ pub fn main() -> Result<(), JsValue> {
 
     (svg.as_ref() as &web_sys::Node).append_child(hold_piece_background_rect.as_ref())?;
 
-    let placed_squares_g = document.create_element_ns(Some("http://www.w3.org/2000/svg"), "g")?
-        .dyn_into::<web_sys::SvggElement>()?;
-    (svg.as_ref() as &web_sys::Node).append_child(placed_squares_g.as_ref())?;
+    Ok(())
+}
 
-    (body.as_ref() as &web_sys::Node).append_child(svg.as_ref())?;
+/// The SVG elements showing a player's game. They are kept from one frame to the next, and only
+/// the ones showing something that changed are updated.
+struct PlayerView {
+    /// The squares of the matrix, indexed by column and then row.
+    squares: Vec<Vec<web_sys::SvgRectElement>>,
+    /// One box for each tetromino of the next preview.
+    preview: Vec<PieceBox>,
+    hold: PieceBox,
+    /// A bar next to the matrix, as high as the rows of incoming garbage.
+    garbage_meter: web_sys::SvgRectElement,
+    hud: Hud,
+    /// What was drawn in the last frame, or `None` before the first one.
+    last_view: Option<tetris::view::ViewModel>
+}
 
-    let timed_game_state = Arc::new(Mutex::new(tetris::game_state::TimedGameState::new()));
-    let timed_game_state_clone = timed_game_state.clone();
+impl PlayerView {
 
+    /// Creates the elements of a player's game, all empty. `left_px` is where the player's side
+    /// starts.
+    fn new(left_px: usize, placed_squares_g: &web_sys::SvggElement, document: &web_sys::Document) -> Result<Self, JsValue> {
 
-    let a = Closure::wrap(Box::new(move || {
-        let window = web_sys::window().expect("no global window");
-        let document = window.document().expect("should have a document on window");
-        let mut timed_game_state = timed_game_state.lock().unwrap();
-        timed_game_state.advance_time(40);
+        let mut squares = Vec::new();
+        for col in 0..tetris::game_state::MATRIX_WIDTH {
+            let mut column = Vec::new();
+            for row in 0..tetris::game_state::MATRIX_HEIGHT {
+                column.push(create_square(left_px + MATRIX_LEFT_PX + col * 30 + STROKE_WIDTH, 700 - row * 30 + STROKE_WIDTH, placed_squares_g, document)?);
+            }
+            squares.push(column);
+        }
 
-        // empty existing children
-        while let Some(child) = (placed_squares_g.as_ref() as &web_sys::Node).first_child() {
-            (placed_squares_g.as_ref() as &web_sys::Node).remove_child(&child).unwrap();
+        let mut preview = Vec::new();
+        for i in 0..MAX_PREVIEW_LENGTH {
+            preview.push(PieceBox::new(left_px + 600, 100 + i * 100, placed_squares_g, document)?);
         }
 
-        let placed_squares = timed_game_state.placed_squares();
-        for (col, placed_squares_row) in placed_squares.iter().enumerate() {
-            for (row, placed_square) in placed_squares_row.iter().enumerate() {
-                if let Some(placed_square_type) = placed_square {
+        let hold = PieceBox::new(left_px + 50, 100, placed_squares_g, document)?;
 
-                    place_square_on_matrix(placed_square_type, col, row, &placed_squares_g, &document);
-                }
+        let garbage_meter = document.create_element_ns(Some("http://www.w3.org/2000/svg"), "rect")?
+            .dyn_into::<web_sys::SvgRectElement>()?;
+        (garbage_meter.as_ref() as &web_sys::Element).set_attribute("x", &(left_px + MATRIX_LEFT_PX - 15).to_string())?;
+        (garbage_meter.as_ref() as &web_sys::Element).set_attribute("y", "730")?;
+        (garbage_meter.as_ref() as &web_sys::Element).set_attribute("width", "10")?;
+        (garbage_meter.as_ref() as &web_sys::Element).set_attribute("height", "0")?;
+        (garbage_meter.as_ref() as &web_sys::Element).set_attribute("fill", "#ff4040")?;
+        (placed_squares_g.as_ref() as &web_sys::Node).append_child(garbage_meter.as_ref())?;
+
+        let hud = Hud::new(left_px, placed_squares_g, document)?;
+
+        Ok(PlayerView { squares, preview, hold, garbage_meter, hud, last_view: None })
+    }
+
+    /// Updates the elements that show something that changed since the last frame, which was `t`
+    /// milliseconds ago, so that they show the given view, and announces the line clears among
+    /// the given events.
+    fn draw(&mut self, view: tetris::view::ViewModel, events: &[tetris::game_state::GameEvent], t: u32) {
+
+        let diff = view.changes_since(self.last_view.as_ref());
+
+        for (col, row, square) in diff.squares {
+            if let Some(rect) = self.squares.get(col).and_then(|column| column.get(row)) {
+                let colors = match square {
+                    tetris::view::Square::Locked(ref cell) => cell_to_colors(cell),
+                    tetris::view::Square::Falling(ref tt) => tetromino_type_to_colors(tt),
+                    tetris::view::Square::Ghost(ref tt) => (tetromino_type_to_colors(tt).0, "none"),
+                    tetris::view::Square::Empty => ("none", "none"),
+                };
+                set_square_colors(rect, colors);
             }
         }
 
-        for (col, row) in timed_game_state.falling_tetromino().minoes() {
-            place_square_on_matrix(timed_game_state.falling_tetromino().ttype(), col as usize, row as usize, &placed_squares_g, &document);
+        if let Some(preview) = diff.preview {
+            for (i, piece_box) in self.preview.iter().enumerate() {
+                let piece = preview.get(i);
+                piece_box.draw(piece, piece.map_or(("none", "none"), |piece| tetromino_type_to_colors(&piece.ttype)));
+            }
         }
 
-        for (i, preview_piece_type) in timed_game_state.next_preview().iter().enumerate() {
-            place_square(preview_piece_type, 600 + 75 - 15, 100 + 50 - 15 + i * 100, &placed_squares_g, &document);
+        if diff.hold.is_some() || diff.hold_available.is_some() {
+            let piece = view.hold.as_ref();
+            let colors = match piece {
+                Some(_) if !view.hold_available => UNAVAILABLE_HOLD_COLORS,
+                Some(piece) => tetromino_type_to_colors(&piece.ttype),
+                None => ("none", "none"),
+            };
+            self.hold.draw(piece, colors);
         }
 
-        if let Some(held_type) = timed_game_state.held() {
-            place_square(held_type, 125 - 15, 100 + 50 - 15, &placed_squares_g, &document);
+        if let Some(timers) = diff.timers {
+            let pending_rows = (timers.pending_garbage as usize).min(view.height);
+            (self.garbage_meter.as_ref() as &web_sys::Element).set_attribute("y", &(730 - pending_rows * 30).to_string()).unwrap();
+            (self.garbage_meter.as_ref() as &web_sys::Element).set_attribute("height", &(pending_rows * 30).to_string()).unwrap();
+            self.hud.draw(&timers);
         }
 
-    }) as Box<FnMut()>);
-    window.set_interval_with_callback_and_timeout_and_arguments_0(
-        a.as_ref().unchecked_ref(),
-        40
-    )?;
-    a.forget();
+        self.hud.announce(events, t);
+
+        self.last_view = Some(view);
+    }
+}
 
-    let timed_game_state = timed_game_state_clone;
+/// The game being played on the page.
+enum PageGame {
+    Single(Game),
+    Versus(tetris::versus::VersusMatch),
+}
 
-    let a = Closure::wrap(Box::new(move |event: web_sys::KeyboardEvent| {
-//        let window = web_sys::window().expect("no global window");
+impl PageGame {
 
-        if event.repeat() {
+    /// Returns `true` if the game has ended.
+    fn is_over(&self) -> bool {
+        match *self {
+            PageGame::Single(ref game) => game.is_over(),
+            PageGame::Versus(ref versus_match) => versus_match.result().is_some(),
+        }
+    }
+
+    /// Describes how the game ended, one line at a time, or returns nothing if it is still going
+    /// on.
+    fn summary(&self) -> Vec<String> {
+        match *self {
+            PageGame::Single(ref game) => match game.timed_game_state().result() {
+                Some(result) => {
+                    let pps = if result.time > 0 { result.pieces_placed as f64 * 1000.0 / f64::from(result.time) } else { 0.0 };
+                    let mut lines: Vec<String> = result.to_string().lines().map(String::from).collect();
+                    lines.push(format!("pps:    {:.2}", pps));
+                    lines
+                },
+                None => Vec::new(),
+            },
+            PageGame::Versus(ref versus_match) => match versus_match.result() {
+                Some(result) => {
+                    let mut lines = vec![match result {
+                        tetris::versus::VersusResult::Winner(index) => format!("PLAYER {} WINS", index + 1),
+                        tetris::versus::VersusResult::Draw => String::from("DRAW"),
+                    }];
+                    for index in 0..2 {
+                        let player = versus_match.player(index);
+                        lines.push(format!("player {}: {} lines, {} points", index + 1, player.game_state().lines_cleared(), player.score()));
+                    }
+                    lines
+                },
+                None => Vec::new(),
+            },
+        }
+    }
+}
+
+/// The game being played on the page, and whether the player paused it.
+struct Session {
+    game: PageGame,
+    paused: bool
+}
+
+impl Session {
+
+    /// Returns `true` if time passes in the game and it takes inputs: it is neither paused nor
+    /// over.
+    fn is_running(&self) -> bool {
+        !self.paused && !self.game.is_over()
+    }
+
+    /// Pauses the game, unless it is over.
+    fn pause(&mut self) {
+        if !self.game.is_over() {
+            self.paused = true;
+        }
+    }
+
+    /// Pauses the game if it is running, or resumes it if it is paused.
+    fn toggle_pause(&mut self) {
+        if self.paused {
+            self.paused = false;
+        } else {
+            self.pause();
+        }
+    }
+
+    /// Replaces the game with a new one, as it was when the page loaded.
+    fn restart(&mut self, window: &web_sys::Window, preview_length: usize) {
+        self.game = initial_game(window, preview_length);
+        self.paused = false;
+    }
+
+    /// Returns the lines of text to show over the game, or `None` if it is running and nothing
+    /// should cover it.
+    fn overlay_lines(&self) -> Option<Vec<String>> {
+        if self.game.is_over() {
+            let mut lines = self.game.summary();
+            lines.push(String::new());
+            lines.push(String::from("Press R or Enter to play again"));
+            Some(lines)
+        } else if self.paused {
+            Some(vec![
+                String::from("PAUSED"),
+                String::new(),
+                String::from("Press P or Esc to resume"),
+                String::from("Press R or Enter to restart")
+            ])
+        } else {
+            None
+        }
+    }
+}
+
+/// Covers the whole game with a dimmed background and some lines of text, while it is paused or
+/// over.
+struct Overlay {
+    g: web_sys::SvggElement,
+    lines: Vec<web_sys::Element>,
+    /// The lines of text shown, or `None` if the overlay is hidden.
+    shown: Option<Vec<String>>
+}
+
+impl Overlay {
+
+    /// Creates the overlay on top of everything drawn on `svg` so far, hidden.
+    fn new(svg: &web_sys::SvgsvgElement, document: &web_sys::Document, gui_width: usize, gui_height: usize) -> Result<Self, JsValue> {
+        let g = document.create_element_ns(Some("http://www.w3.org/2000/svg"), "g")?
+            .dyn_into::<web_sys::SvggElement>()?;
+        (g.as_ref() as &web_sys::Element).set_attribute("display", "none")?;
+
+        let background_rect = document.create_element_ns(Some("http://www.w3.org/2000/svg"), "rect")?;
+        background_rect.set_attribute("x", "0")?;
+        background_rect.set_attribute("y", "0")?;
+        background_rect.set_attribute("width", &gui_width.to_string())?;
+        background_rect.set_attribute("height", &gui_height.to_string())?;
+        background_rect.set_attribute("fill", "#000000")?;
+        background_rect.set_attribute("fill-opacity", "0.6")?;
+        (g.as_ref() as &web_sys::Node).append_child(background_rect.as_ref())?;
+
+        let mut lines = Vec::new();
+        for i in 0..OVERLAY_LINE_COUNT {
+            let text = create_text(gui_width / 2, OVERLAY_TOP_PX + i * HUD_LINE_HEIGHT_PX, "middle", if i == 0 { 36 } else { 22 }, &g, document)?;
+            text.set_attribute("fill", "#ffffff")?;
+            lines.push(text);
+        }
+
+        (svg.as_ref() as &web_sys::Node).append_child(g.as_ref())?;
+
+        Ok(Overlay { g, lines, shown: None })
+    }
+
+    /// Shows the given lines of text, or hides the overlay if there are none.
+    fn draw(&mut self, lines: Option<Vec<String>>) {
+        if lines == self.shown {
             return;
         }
 
-        web_sys::console::log_1(&format!("keydown with code={}", event.code()).into());
-        let mut timed_game_state = timed_game_state.lock().unwrap();
-        match event.code().as_str() {
-            "ArrowLeft" => { timed_game_state.move_left(); },
-            "ArrowRight" => { timed_game_state.move_right(); },
-            "ArrowUp" => { timed_game_state.rotate_right(); },
-            "ArrowDown" => { timed_game_state.hard_drop(); }, // TODO: implement soft drop
-            "Space" => { timed_game_state.hard_drop(); },
-            "KeyZ" => { timed_game_state.rotate_left(); },
-            "KeyX" => { timed_game_state.rotate_right(); },
-            "KeyA" => { timed_game_state.hold(); }
-            "ShiftLeft" => { timed_game_state.hold(); }
-            "ShiftRight" => { timed_game_state.hold(); }
-            _ => {},
+        (self.g.as_ref() as &web_sys::Element).set_attribute("display", if lines.is_some() { "inline" } else { "none" }).unwrap();
+        if let Some(ref lines) = lines {
+            for (i, text) in self.lines.iter().enumerate() {
+                set_text(text, lines.get(i).map_or("", |line| line.as_str()));
+            }
         }
-    }) as Box<FnMut(_)>);
-    (document.as_ref() as &web_sys::EventTarget).add_event_listener_with_callback("keydown", a.as_ref().unchecked_ref())?;
-    a.forget();
+        self.shown = lines;
+    }
+}
 
-    Ok(())
+/// Creates the game to play: a versus match if the page URL has a `versus` parameter, such as
+/// `?versus`, or otherwise a single player game.
+fn initial_game(window: &web_sys::Window, preview_length: usize) -> PageGame {
+    let search = window.location().search().unwrap_or_default();
+    let versus = search.trim_start_matches('?').split('&').any(|param| param == "versus");
+    if versus {
+        let config = tetris::config::GameConfig { preview_length, ..tetris::config::GameConfig::default() };
+        PageGame::Versus(tetris::versus::VersusMatch::with_game_states(
+            tetris::game_state::GameState::with_config(config.clone()),
+            tetris::game_state::GameState::with_config(config)
+        ))
+    } else {
+        PageGame::Single(initial_single_game(&search, preview_length))
+    }
+}
+
+/// Asks the browser to call the given closure before the next repaint.
+fn request_animation_frame(callback: &Closure<FnMut()>) {
+    web_sys::window().expect("no global window")
+        .request_animation_frame(callback.as_ref().unchecked_ref())
+        .expect("should be able to request an animation frame");
+}
+
+/// Measures how much time passed between animation frames.
+struct FrameClock {
+    performance: web_sys::Performance,
+    /// When the last frame started, in milliseconds since the page loaded.
+    last_frame_ms: f64,
+    /// The fraction of a millisecond that passed but has not been given to the game yet.
+    leftover_ms: f64
+}
+
+impl FrameClock {
+
+    fn new(window: &web_sys::Window) -> Self {
+        let performance = window.performance().expect("should have performance on window");
+        let last_frame_ms = performance.now();
+        FrameClock { performance, last_frame_ms, leftover_ms: 0.0 }
+    }
+
+    /// Returns the whole number of milliseconds that passed since the last frame, at most
+    /// `MAX_FRAME_MS`, so that a hitch does not make the game jump ahead. Returns `None` for a gap
+    /// longer than `AWAY_FRAME_MS`, as when the tab was in the background, which the game should
+    /// treat as a pause.
+    fn tick(&mut self) -> Option<u32> {
+        let now = self.performance.now();
+        let elapsed_ms = now - self.last_frame_ms;
+        self.last_frame_ms = now;
+
+        if elapsed_ms > f64::from(AWAY_FRAME_MS) {
+            self.leftover_ms = 0.0;
+            return None;
+        }
+
+        let total_ms = self.leftover_ms + elapsed_ms.max(0.0).min(f64::from(MAX_FRAME_MS));
+        let t = total_ms.floor();
+        self.leftover_ms = total_ms - t;
+        Some(t as u32)
+    }
+}
+
+/// Lets the bot play the game, one input at a time so that it can be watched.
+struct Autoplay {
+    enabled: bool,
+    bot: tetris::bot::Bot,
+    /// The inputs left to make for the placement the bot chose, each with where the falling
+    /// tetromino should be right before it is made.
+    inputs: VecDeque<(tetris::placement::Input, tetris::game_state::Tetromino)>,
+    /// The number of milliseconds until the bot makes its next input.
+    time_to_input: u32
+}
+
+impl Autoplay {
+
+    fn new() -> Self {
+        Autoplay {
+            enabled: false,
+            bot: tetris::bot::Bot::default(),
+            inputs: VecDeque::new(),
+            time_to_input: AUTOPLAY_INPUT_INTERVAL
+        }
+    }
+
+    /// Turns the bot on or off. The player can take over from where the bot left off.
+    fn toggle(&mut self) {
+        self.enabled = !self.enabled;
+        self.inputs.clear();
+    }
+
+    /// Lets the given number of milliseconds pass, making an input every
+    /// `AUTOPLAY_INPUT_INTERVAL` milliseconds.
+    fn advance_time(&mut self, game: &mut Game, t: u32) {
+        self.time_to_input = self.time_to_input.saturating_sub(t);
+        if self.time_to_input == 0 {
+            self.time_to_input = AUTOPLAY_INPUT_INTERVAL;
+            self.step(game);
+        }
+    }
+
+    /// Forgets the inputs left to make, as when the game they were for is replaced.
+    fn reset(&mut self) {
+        self.inputs.clear();
+    }
+
+    /// Makes the next input of the bot, if it is playing and the game is still going on.
+    fn step(&mut self, game: &mut Game) {
+        if !self.enabled || game.is_over() {
+            return;
+        }
+
+        // The path was found as if nothing else moved the tetromino, so it is worked out again from
+        // where the tetromino is if gravity moved it in between inputs.
+        let stale = self.inputs.front()
+            .is_some_and(|&(_, ref expected)| expected != game.timed_game_state().falling_tetromino());
+        if stale || self.inputs.is_empty() {
+            self.inputs = self.plan(game.timed_game_state().game_state());
+        }
+
+        if let Some((input, _)) = self.inputs.pop_front() {
+            game.apply_input(input);
+        }
+    }
+
+    /// Returns the inputs for the placement the bot chooses, each with where the falling
+    /// tetromino is right before it.
+    fn plan(&self, game_state: &tetris::game_state::GameState) -> VecDeque<(tetris::placement::Input, tetris::game_state::Tetromino)> {
+        let inputs = match self.bot.choose(game_state) {
+            Some(decision) => decision.to_inputs(),
+            None => vec![tetris::placement::Input::HardDrop]
+        };
+
+        let mut game_state = game_state.clone();
+        inputs.into_iter().map(|input| {
+            let before = game_state.falling_tetromino().clone();
+            game_state.apply_input(input);
+            (input, before)
+        }).collect()
+    }
+}
+
+/// Creates the single player game to play, starting from the board of the fumen in the page URL
+/// if there is one, such as `?fumen=v115@vhAAgH`, and played with the mode in the page URL if
+/// there is one, such as `?mode=ultra`.
+fn initial_single_game(search: &str, preview_length: usize) -> Game {
+
+    let mut options = GameOptions::new();
+    options.set_preview_length(preview_length);
+    if let Some(mode) = url_param(search, "mode") {
+        if options.set_mode(&mode).is_err() {
+            web_sys::console::log_1(&format!("unknown mode {}", mode).into());
+        }
+    }
+
+    let fumen = match url_param(search, "fumen") {
+        Some(fumen) => fumen,
+        None => return Game::with_options(&options),
+    };
+
+    Game::from_fumen_with_options(&fumen, &options).unwrap_or_else(|e| {
+        web_sys::console::log_2(&"could not load fumen:".into(), &e);
+        Game::with_options(&options)
+    })
+}
+
+/// Returns the decoded value of the parameter with the given name in the query string of a URL,
+/// such as `?mode=ultra&fumen=v115@vhAAgH`, or `None` if it has no such parameter.
+fn url_param(search: &str, name: &str) -> Option<String> {
+    search.trim_start_matches('?').split('&')
+        .find_map(|param| param.strip_prefix(name).and_then(|rest| rest.strip_prefix('=')))
+        .map(percent_decode)
+}
+
+/// Decodes the `%XX` escapes of a URL component.
+fn percent_decode(s: &str) -> String {
+    let bytes = s.as_bytes();
+    let mut decoded = Vec::new();
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' && i + 2 < bytes.len() {
+            let hex = std::str::from_utf8(&bytes[i + 1..i + 3]).unwrap_or("");
+            if let Ok(byte) = u8::from_str_radix(hex, 16) {
+                decoded.push(byte);
+                i += 3;
+                continue;
+            }
+        }
+        decoded.push(bytes[i]);
+        i += 1;
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
 }
 
 const STROKE_WIDTH: usize = 3;
 
-fn place_square_on_matrix(placed_square_type: &tetris::TetrominoType, col: usize, row: usize, placed_squares_g: &web_sys::SvggElement, document: &web_sys::Document) {
+/// Size of a square on the matrix, including its border.
+const SQUARE_PX: usize = 30;
+/// Size of the hold box and of each slot of the next preview.
+const PIECE_BOX_WIDTH_PX: usize = 150;
+const PIECE_BOX_HEIGHT_PX: usize = 100;
+/// The smallest space left between a tetromino and the edges of its box.
+const PIECE_BOX_MARGIN_PX: usize = 15;
+/// Where the HUD starts, below the hold box, and the space between two of its lines.
+const HUD_TOP_PX: usize = 260;
+const HUD_LINE_HEIGHT_PX: usize = 40;
+/// The most lines of numbers the HUD shows: five, and up to two goals of the game mode.
+const HUD_LINE_COUNT: usize = 7;
+/// Where the names of the latest line clear start, below the HUD, and how many lines they take
+/// at most: the clear, back-to-back, the combo and a perfect clear.
+const POPUP_TOP_PX: usize = 580;
+const POPUP_LINE_COUNT: usize = 4;
+/// The number of milliseconds the names of a line clear are shown for.
+const POPUP_MS: u32 = 1500;
+/// Where the lines of text over a paused or finished game start, and how many there are at most.
+const OVERLAY_TOP_PX: usize = 250;
+const OVERLAY_LINE_COUNT: usize = 10;
+/// The colors of the held tetromino while it cannot be swapped back.
+const UNAVAILABLE_HOLD_COLORS: (&str, &str) = ("#808080", "#b0b0b0");
+
+/// The longest time between two frames that is given to the game at once. Longer gaps are cut
+/// short to this.
+const MAX_FRAME_MS: u32 = 250;
+/// The longest time between two frames before the player is taken to have been away, and the
+/// game is paused.
+const AWAY_FRAME_MS: u32 = 1000;
+/// The number of milliseconds between two inputs of the bot, so that it can be watched.
+const AUTOPLAY_INPUT_INTERVAL: u32 = 40;
 
-    place_square(placed_square_type, 250 + col * 30 + STROKE_WIDTH, 700 - row * 30 + STROKE_WIDTH, placed_squares_g, document);
+/// Width of one player's side of the page.
+const PLAYER_WIDTH_PX: usize = 800;
+/// Distance from the left of a player's side of the page to the left of their matrix.
+const MATRIX_LEFT_PX: usize = 250;
+
+/// The numbers shown below the hold box, and the names of the latest line clear below them.
+struct Hud {
+    /// The label and the value of each line of numbers.
+    lines: Vec<(web_sys::Element, web_sys::Element)>,
+    /// One line for each name of the latest line clear.
+    popup: Vec<web_sys::Element>,
+    /// The number of milliseconds until the names of the latest line clear are hidden.
+    time_to_hide_popup: u32
 }
 
-fn place_square(placed_square_type: &tetris::TetrominoType, x: usize, y: usize, placed_squares_g: &web_sys::SvggElement, document: &web_sys::Document) {
+impl Hud {
+
+    /// Creates the texts of the HUD, all empty. `left_px` is where the player's side starts.
+    fn new(left_px: usize, placed_squares_g: &web_sys::SvggElement, document: &web_sys::Document) -> Result<Self, JsValue> {
+        let mut lines = Vec::new();
+        for i in 0..HUD_LINE_COUNT {
+            let y = HUD_TOP_PX + i * HUD_LINE_HEIGHT_PX;
+            lines.push((
+                create_text(left_px + 50, y, "start", 20, placed_squares_g, document)?,
+                create_text(left_px + 200, y, "end", 20, placed_squares_g, document)?
+            ));
+        }
+
+        let mut popup = Vec::new();
+        for i in 0..POPUP_LINE_COUNT {
+            popup.push(create_text(left_px + 125, POPUP_TOP_PX + i * HUD_LINE_HEIGHT_PX, "middle", 22, placed_squares_g, document)?);
+        }
+
+        Ok(Hud { lines, popup, time_to_hide_popup: 0 })
+    }
 
-    let placed_square_rect = document.create_element_ns(Some("http://www.w3.org/2000/svg"), "rect").unwrap()
-        .dyn_into::<web_sys::SvgRectElement>().unwrap();
-    let (stroke_color, fill_color) = tetromino_type_to_colors(placed_square_type);
+    /// Shows the given numbers.
+    fn draw(&self, timers: &tetris::view::Timers) {
+        let mut texts = vec![
+            (String::from("SCORE"), timers.score.to_string()),
+            (String::from("LEVEL"), timers.level.to_string()),
+            (String::from("LINES"), timers.lines_cleared.to_string()),
+            (String::from("TIME"), format_time(timers.elapsed)),
+            (String::from("PPS"), format!("{:.2}", timers.pieces_per_second())),
+        ];
+        if let Some(lines_remaining) = timers.lines_remaining {
+            texts.push((String::from("LINES LEFT"), lines_remaining.to_string()));
+        }
+        if let Some(time_remaining) = timers.time_remaining {
+            texts.push((String::from("TIME LEFT"), format_time(time_remaining)));
+        }
+
+        for (i, &(ref label, ref value)) in self.lines.iter().enumerate() {
+            let (label_text, value_text) = texts.get(i).map_or(("", ""), |&(ref l, ref v)| (l.as_str(), v.as_str()));
+            set_text(label, label_text);
+            set_text(value, value_text);
+        }
+    }
+
+    /// Shows the names of the last line clear among the given events for `POPUP_MS` milliseconds,
+    /// and hides the names shown before once their time is up, `t` milliseconds after the last
+    /// frame.
+    fn announce(&mut self, events: &[tetris::game_state::GameEvent], t: u32) {
+        let mut labels = Vec::new();
+        for event in events {
+            match *event {
+                tetris::game_state::GameEvent::LineClear { ref clear, .. } => labels = clear.labels(),
+                tetris::game_state::GameEvent::PerfectClear { .. } => labels.push(String::from("PERFECT CLEAR")),
+            }
+        }
+
+        if !labels.is_empty() {
+            for (i, text) in self.popup.iter().enumerate() {
+                set_text(text, labels.get(i).map_or("", |label| label.as_str()));
+            }
+            self.time_to_hide_popup = POPUP_MS;
+        } else if self.time_to_hide_popup > 0 {
+            self.time_to_hide_popup = self.time_to_hide_popup.saturating_sub(t);
+            if self.time_to_hide_popup == 0 {
+                for text in &self.popup {
+                    set_text(text, "");
+                }
+            }
+        }
+    }
+}
+
+/// Writes a number of milliseconds as minutes, seconds and hundredths, such as `1:05.32`.
+fn format_time(ms: u32) -> String {
+    format!("{}:{:02}.{:02}", ms / 60_000, ms / 1000 % 60, ms / 10 % 100)
+}
+
+/// Creates an empty text anchored at the given position, with `anchor` being `start`, `middle` or
+/// `end`.
+fn create_text(x: usize, y: usize, anchor: &str, font_size: usize, placed_squares_g: &web_sys::SvggElement, document: &web_sys::Document) -> Result<web_sys::Element, JsValue> {
+
+    let text = document.create_element_ns(Some("http://www.w3.org/2000/svg"), "text")?;
+
+    text.set_attribute("x", &x.to_string())?;
+    text.set_attribute("y", &y.to_string())?;
+    text.set_attribute("text-anchor", anchor)?;
+    text.set_attribute("font-family", "sans-serif")?;
+    text.set_attribute("font-size", &font_size.to_string())?;
+    text.set_attribute("fill", "#404040")?;
+
+    (placed_squares_g.as_ref() as &web_sys::Node).append_child(text.as_ref())?;
+
+    Ok(text)
+}
+
+/// Changes what a text says, if it says something else.
+fn set_text(text: &web_sys::Element, content: &str) {
+    let node = text.as_ref() as &web_sys::Node;
+    if node.text_content().as_ref().map(String::as_str) != Some(content) {
+        node.set_text_content(Some(content));
+    }
+}
+
+/// The squares of a tetromino shown in a box outside the matrix, such as the hold box or one
+/// slot of the next preview.
+struct PieceBox {
+    left_px: usize,
+    top_px: usize,
+    squares: Vec<web_sys::SvgRectElement>
+}
+
+impl PieceBox {
+
+    /// Creates an empty box with its top left at the given position.
+    fn new(left_px: usize, top_px: usize, placed_squares_g: &web_sys::SvggElement, document: &web_sys::Document) -> Result<Self, JsValue> {
+        let mut squares = Vec::new();
+        for _ in 0..4 {
+            squares.push(create_square(left_px, top_px, placed_squares_g, document)?);
+        }
+        Ok(PieceBox { left_px, top_px, squares })
+    }
+
+    /// Draws the given tetromino in the given colors, centered in the box and scaled down if it
+    /// does not fit, or empties the box if there is none.
+    fn draw(&self, piece: Option<&tetris::view::PieceView>, colors: (&str, &str)) {
+        let piece = match piece {
+            Some(piece) => piece,
+            None => {
+                for rect in &self.squares {
+                    set_square_colors(rect, ("none", "none"));
+                }
+                return;
+            }
+        };
+
+        let square_px = SQUARE_PX
+            .min((PIECE_BOX_WIDTH_PX - 2 * PIECE_BOX_MARGIN_PX) / piece.width.max(1))
+            .min((PIECE_BOX_HEIGHT_PX - 2 * PIECE_BOX_MARGIN_PX) / piece.height.max(1));
+        let piece_left_px = self.left_px + (PIECE_BOX_WIDTH_PX - piece.width * square_px) / 2;
+        let piece_top_px = self.top_px + (PIECE_BOX_HEIGHT_PX - piece.height * square_px) / 2;
+
+        for (rect, &(col, row)) in self.squares.iter().zip(piece.minoes.iter()) {
+            let x = piece_left_px + col * square_px + STROKE_WIDTH;
+            let y = piece_top_px + (piece.height - 1 - row) * square_px + STROKE_WIDTH;
+            (rect.as_ref() as &web_sys::Element).set_attribute("x", &x.to_string()).unwrap();
+            (rect.as_ref() as &web_sys::Element).set_attribute("y", &y.to_string()).unwrap();
+            (rect.as_ref() as &web_sys::Element).set_attribute("width", &(square_px - 2 * STROKE_WIDTH).to_string()).unwrap();
+            (rect.as_ref() as &web_sys::Element).set_attribute("height", &(square_px - 2 * STROKE_WIDTH).to_string()).unwrap();
+            set_square_colors(rect, colors);
+        }
+    }
+}
+
+/// Creates a square with its top left at the given position, drawn in no color until it is given
+/// some.
+fn create_square(x: usize, y: usize, placed_squares_g: &web_sys::SvggElement, document: &web_sys::Document) -> Result<web_sys::SvgRectElement, JsValue> {
+
+    let placed_square_rect = document.create_element_ns(Some("http://www.w3.org/2000/svg"), "rect")?
+        .dyn_into::<web_sys::SvgRectElement>()?;
+
+    (placed_square_rect.as_ref() as &web_sys::Element).set_attribute("x", &x.to_string())?;
+    (placed_square_rect.as_ref() as &web_sys::Element).set_attribute("y", &y.to_string())?;
+    (placed_square_rect.as_ref() as &web_sys::Element).set_attribute("width", &(30 - 2 * STROKE_WIDTH).to_string())?;
+    (placed_square_rect.as_ref() as &web_sys::Element).set_attribute("height", &(30 - 2 * STROKE_WIDTH).to_string())?;
+    (placed_square_rect.as_ref() as &web_sys::Element).set_attribute("rx", &(STROKE_WIDTH).to_string())?;
+    (placed_square_rect.as_ref() as &web_sys::Element).set_attribute("ry", &(STROKE_WIDTH).to_string())?;
+    (placed_square_rect.as_ref() as &web_sys::Element).set_attribute("stroke-width", &(2 * STROKE_WIDTH).to_string())?;
+    set_square_colors(&placed_square_rect, ("none", "none"));
+
+    (placed_squares_g.as_ref() as &web_sys::Node).append_child(placed_square_rect.as_ref())?;
+
+    Ok(placed_square_rect)
+}
+
+fn set_square_colors(placed_square_rect: &web_sys::SvgRectElement, colors: (&str, &str)) {
+
+    let (stroke_color, fill_color) = colors;
 
-    (placed_square_rect.as_ref() as &web_sys::Element).set_attribute("x", &x.to_string()).unwrap();
-    (placed_square_rect.as_ref() as &web_sys::Element).set_attribute("y", &y.to_string()).unwrap();
-    (placed_square_rect.as_ref() as &web_sys::Element).set_attribute("width", &(30 - 2 * STROKE_WIDTH).to_string()).unwrap();
-    (placed_square_rect.as_ref() as &web_sys::Element).set_attribute("height", &(30 - 2 * STROKE_WIDTH).to_string()).unwrap();
-    (placed_square_rect.as_ref() as &web_sys::Element).set_attribute("rx", &(STROKE_WIDTH).to_string()).unwrap();
-    (placed_square_rect.as_ref() as &web_sys::Element).set_attribute("ry", &(STROKE_WIDTH).to_string()).unwrap();
-    (placed_square_rect.as_ref() as &web_sys::Element).set_attribute("stroke-width", &(2 * STROKE_WIDTH).to_string()).unwrap();
     (placed_square_rect.as_ref() as &web_sys::Element).set_attribute("stroke", stroke_color).unwrap();
     (placed_square_rect.as_ref() as &web_sys::Element).set_attribute("fill", fill_color).unwrap();
+}
 
-    (placed_squares_g.as_ref() as &web_sys::Node).append_child(placed_square_rect.as_ref()).unwrap();
+fn cell_to_colors(cell: &tetris::Cell) -> (&str, &str) {
+    match cell {
+        tetris::Cell::Piece(tt) => tetromino_type_to_colors(tt),
+        tetris::Cell::Garbage => ("#606060", "#909090"),
+        tetris::Cell::Item => ("#c0a000", "#ffffff"),
+        tetris::Cell::Empty | tetris::Cell::Invisible(_) => ("none", "none"),
+    }
 }
 
 fn tetromino_type_to_colors(tt: &tetris::TetrominoType) -> (&str, &str) {
@@ -0,0 +1,204 @@
+//! The player's choice of keys and handling, kept in the browser's local storage between visits.
+
+use serde_json;
+use tetris;
+use wasm_bindgen::prelude::*;
+use web_sys;
+
+/// The local storage key the settings are saved under.
+const STORAGE_KEY: &str = "tetris-settings";
+
+/// The most tetrominoes the next preview can show. The page has a box for each of them to the
+/// right of the matrix, and no room for more.
+pub const MAX_PREVIEW_LENGTH: usize = tetris::game_state::NEXT_PREVIEW_LENGTH;
+/// The longest DAS and ARR, in milliseconds.
+pub const MAX_DELAY: u32 = 10_000;
+/// The smallest soft drop factor.
+pub const MIN_SOFT_DROP_FACTOR: u32 = 1;
+/// The largest soft drop factor.
+pub const MAX_SOFT_DROP_FACTOR: u32 = 1000;
+
+/// The keys that pause and resume the game. They cannot make actions.
+pub const PAUSE_KEYS: [&str; 2] = ["Escape", "KeyP"];
+/// The keys that start a new game once the game is paused or over. They cannot make actions.
+pub const RESTART_KEYS: [&str; 2] = ["Enter", "KeyR"];
+
+/// Something a player can do by pressing a key.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Action {
+    MoveLeft,
+    MoveRight,
+    SoftDrop,
+    HardDrop,
+    RotateLeft,
+    RotateRight,
+    Hold
+}
+
+impl Action {
+
+    /// Every action, in the order the settings panel lists them.
+    pub const ALL: [Action; 7] = [
+        Action::MoveLeft, Action::MoveRight, Action::SoftDrop, Action::HardDrop,
+        Action::RotateLeft, Action::RotateRight, Action::Hold
+    ];
+
+    /// Returns the name of the action shown in the settings panel.
+    pub fn label(self) -> &'static str {
+        match self {
+            Action::MoveLeft => "Move left",
+            Action::MoveRight => "Move right",
+            Action::SoftDrop => "Soft drop",
+            Action::HardDrop => "Hard drop",
+            Action::RotateLeft => "Rotate left",
+            Action::RotateRight => "Rotate right",
+            Action::Hold => "Hold",
+        }
+    }
+}
+
+/// The keys that make an action, named by `KeyboardEvent.code`, such as `ArrowLeft` or `KeyZ`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Binding {
+    pub action: Action,
+    pub keys: Vec<String>
+}
+
+/// How the player likes to play.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    /// The keys of each action. Several keys can make the same action.
+    pub bindings: Vec<Binding>,
+    /// Delayed auto shift: the number of milliseconds a move key is held before the tetromino
+    /// starts moving on its own.
+    pub das: u32,
+    /// Auto repeat rate: the number of milliseconds between two moves once they repeat, or 0 to
+    /// move all the way at once.
+    pub arr: u32,
+    /// How many times faster than gravity the tetromino falls while soft drop is held.
+    pub soft_drop_factor: u32,
+    /// The number of tetrominoes the next preview shows, up to `MAX_PREVIEW_LENGTH`.
+    pub preview_length: usize
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        let binding = |action, keys: &[&str]| Binding {
+            action,
+            keys: keys.iter().map(|&key| String::from(key)).collect()
+        };
+
+        Settings {
+            bindings: vec![
+                binding(Action::MoveLeft, &["ArrowLeft"]),
+                binding(Action::MoveRight, &["ArrowRight"]),
+                binding(Action::SoftDrop, &["ArrowDown"]),
+                binding(Action::HardDrop, &["Space"]),
+                binding(Action::RotateLeft, &["KeyZ"]),
+                binding(Action::RotateRight, &["ArrowUp", "KeyX"]),
+                binding(Action::Hold, &["KeyA", "ShiftLeft", "ShiftRight"]),
+            ],
+            das: 170,
+            arr: 50,
+            soft_drop_factor: 20,
+            preview_length: MAX_PREVIEW_LENGTH
+        }
+    }
+}
+
+impl Settings {
+
+    /// Returns the settings of the two players of a versus match played on one keyboard, who
+    /// share the DAS, ARR and soft drop factor of these settings. The left player uses the letter
+    /// keys and Space, and the right player uses the arrow keys and the keys around them.
+    pub fn versus_players(&self) -> [Settings; 2] {
+        let with_keys = |keys: [&str; 7]| Settings {
+            bindings: Action::ALL.iter().zip(keys.iter())
+                .map(|(&action, &key)| Binding { action, keys: vec![String::from(key)] })
+                .collect(),
+            ..self.clone()
+        };
+
+        // In the order of `Action::ALL`.
+        [
+            with_keys(["KeyA", "KeyD", "KeyS", "Space", "KeyQ", "KeyW", "KeyE"]),
+            with_keys(["ArrowLeft", "ArrowRight", "ArrowDown", "Slash", "ControlRight", "ArrowUp", "ShiftRight"]),
+        ]
+    }
+
+    /// Returns the action the key with the given code makes, if any.
+    pub fn action_for_key(&self, code: &str) -> Option<Action> {
+        self.bindings.iter()
+            .find(|binding| binding.keys.iter().any(|key| key == code))
+            .map(|binding| binding.action)
+    }
+
+    /// Returns the keys that make the given action.
+    pub fn keys_for(&self, action: Action) -> &[String] {
+        self.bindings.iter()
+            .find(|binding| binding.action == action)
+            .map_or(&[], |binding| binding.keys.as_slice())
+    }
+
+    /// Makes the given keys, and no others, make the given action. Use `check_keys` afterwards to
+    /// find out whether the keys can be used.
+    pub fn set_keys(&mut self, action: Action, keys: Vec<String>) {
+        match self.bindings.iter_mut().find(|binding| binding.action == action) {
+            Some(binding) => binding.keys = keys,
+            None => self.bindings.push(Binding { action, keys }),
+        }
+    }
+
+    /// Checks that no key makes more than one action, and that no key is one of the
+    /// `PAUSE_KEYS` or `RESTART_KEYS`. Returns a message for the player describing the first
+    /// problem found otherwise.
+    pub fn check_keys(&self) -> Result<(), String> {
+        let mut bound: Vec<(&str, Action)> = Vec::new();
+        for binding in &self.bindings {
+            for key in &binding.keys {
+                if PAUSE_KEYS.contains(&key.as_str()) {
+                    return Err(format!("{} pauses the game, so it cannot be used for {}", key, binding.action.label()));
+                }
+                if RESTART_KEYS.contains(&key.as_str()) {
+                    return Err(format!("{} restarts the game, so it cannot be used for {}", key, binding.action.label()));
+                }
+                if let Some(&(_, other)) = bound.iter().find(|&&(other_key, _)| other_key == key) {
+                    return Err(format!("{} is used for both {} and {}", key, other.label(), binding.action.label()));
+                }
+                bound.push((key, binding.action));
+            }
+        }
+        Ok(())
+    }
+
+    /// Loads the settings saved in local storage, or returns the default ones if there are none
+    /// or they cannot be read. Saved keys that cannot be used are replaced by the default keys,
+    /// and saved numbers out of range by the closest ones in range.
+    pub fn load(window: &web_sys::Window) -> Self {
+        let saved = window.local_storage().ok()
+            .and_then(|storage| storage)
+            .and_then(|storage| storage.get_item(STORAGE_KEY).ok())
+            .and_then(|saved| saved);
+
+        let mut settings: Settings = match saved {
+            Some(saved) => serde_json::from_str(&saved).unwrap_or_default(),
+            None => Settings::default(),
+        };
+        if settings.check_keys().is_err() {
+            settings.bindings = Settings::default().bindings;
+        }
+        settings.das = settings.das.min(MAX_DELAY);
+        settings.arr = settings.arr.min(MAX_DELAY);
+        settings.soft_drop_factor = settings.soft_drop_factor.clamp(MIN_SOFT_DROP_FACTOR, MAX_SOFT_DROP_FACTOR);
+        settings.preview_length = settings.preview_length.min(MAX_PREVIEW_LENGTH);
+        settings
+    }
+
+    /// Saves the settings in local storage, to be loaded the next time the page is opened.
+    pub fn save(&self, window: &web_sys::Window) -> Result<(), JsValue> {
+        let storage = window.local_storage()?.ok_or_else(|| JsValue::from_str("local storage is not available"))?;
+        let json = serde_json::to_string(self).map_err(|e| JsValue::from_str(&e.to_string()))?;
+        storage.set_item(STORAGE_KEY, &json)
+    }
+}
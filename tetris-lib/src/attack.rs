@@ -0,0 +1,86 @@
+//! The number of lines of garbage a line clear sends to the opponent in versus play, following
+//! the guideline attack table.
+
+use ::game_state::{LineClear, TSpin};
+
+/// Number of milliseconds garbage waits after being received before it can rise into the matrix,
+/// giving the player a chance to cancel it.
+pub const GARBAGE_DELAY: u32 = 1000;
+
+/// Extra lines sent by a line clear that keeps a back-to-back chain going.
+pub const BACK_TO_BACK_BONUS: u32 = 1;
+
+/// Extra lines sent by a perfect clear.
+pub const PERFECT_CLEAR_BONUS: u32 = 10;
+
+/// Extra lines sent by a line clear, indexed by the number of tetrominoes in a row before it that
+/// cleared lines. Longer combos send as much as the last entry.
+const COMBO_BONUS: [u32; 11] = [0, 1, 1, 2, 2, 3, 3, 4, 4, 4, 5];
+
+/// Returns the number of lines of garbage the given line clear sends.
+pub fn lines_sent(clear: &LineClear) -> u32 {
+    let base = match (clear.t_spin, clear.lines) {
+        (_, 0) => 0,
+        (TSpin::None, 1) => 0,
+        (TSpin::None, 2) => 1,
+        (TSpin::None, 3) => 2,
+        (TSpin::None, _) => 4,
+        (TSpin::Mini, 1) => 0,
+        (TSpin::Mini, _) => 1,
+        (TSpin::Full, 1) => 2,
+        (TSpin::Full, 2) => 4,
+        (TSpin::Full, _) => 6,
+    };
+
+    let combo = COMBO_BONUS[(clear.combo as usize).min(COMBO_BONUS.len() - 1)];
+    let back_to_back = if clear.back_to_back { BACK_TO_BACK_BONUS } else { 0 };
+    let perfect_clear = if clear.perfect_clear { PERFECT_CLEAR_BONUS } else { 0 };
+
+    base + combo + back_to_back + perfect_clear
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn clear(lines: usize, t_spin: TSpin) -> LineClear {
+        LineClear { lines, perfect_clear: false, t_spin, combo: 0, back_to_back: false }
+    }
+
+    #[test]
+    fn sends_the_guideline_table() {
+        for &(lines, t_spin, sent) in &[
+            (1, TSpin::None, 0),
+            (2, TSpin::None, 1),
+            (3, TSpin::None, 2),
+            (4, TSpin::None, 4),
+            (1, TSpin::Mini, 0),
+            (2, TSpin::Mini, 1),
+            (1, TSpin::Full, 2),
+            (2, TSpin::Full, 4),
+            (3, TSpin::Full, 6),
+        ] {
+            assert_eq!(lines_sent(&clear(lines, t_spin)), sent, "{} lines, {:?}", lines, t_spin);
+        }
+    }
+
+    #[test]
+    fn adds_one_line_for_back_to_back() {
+        for &(lines, t_spin, sent) in &[(4, TSpin::None, 5), (2, TSpin::Full, 5), (1, TSpin::Mini, 1)] {
+            let clear = LineClear { back_to_back: true, ..clear(lines, t_spin) };
+            assert_eq!(lines_sent(&clear), sent, "{} lines, {:?}", lines, t_spin);
+        }
+    }
+
+    #[test]
+    fn ramps_up_with_combos() {
+        let sent: Vec<u32> = (0..13).map(|combo| lines_sent(&LineClear { combo, ..clear(1, TSpin::None) })).collect();
+        assert_eq!(sent, vec![0, 1, 1, 2, 2, 3, 3, 4, 4, 4, 5, 5, 5]);
+    }
+
+    #[test]
+    fn adds_the_perfect_clear_bonus() {
+        let clear = LineClear { perfect_clear: true, combo: 1, back_to_back: true, ..clear(4, TSpin::None) };
+        assert_eq!(lines_sent(&clear), 4 + 1 + BACK_TO_BACK_BONUS + PERFECT_CLEAR_BONUS);
+    }
+}
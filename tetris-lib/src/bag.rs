@@ -1,5 +1,10 @@
 extern crate rand;
-use self::rand::Rng;
+use self::rand::{FromEntropy, Rng, SeedableRng};
+use self::rand::rngs::StdRng;
+
+use std::collections::VecDeque;
+
+use ::config::{GameConfig, Randomizer};
 
 /// Array of all 7 tetromino types.
 const ALL_TETROMINO_TYPES: [::TetrominoType; 7] = [
@@ -12,29 +17,62 @@ const ALL_TETROMINO_TYPES: [::TetrominoType; 7] = [
     ::TetrominoType::Z
 ];
 
-/// An object that randomly gives `TetrominoType`s in such an order that every
-/// 7 values returned contains one of each variant.
-#[derive(Debug)]
+/// An object that randomly gives `TetrominoType`s. With the 7-bag randomizer, they come in such
+/// an order that every 7 values returned contains one of each variant.
+#[derive(Debug, Clone)]
 pub struct Bag {
-    remaining: Vec<::TetrominoType>
+    remaining: Vec<::TetrominoType>,
+
+    /// Decides whether the types come in sets of 7.
+    randomizer: Randomizer,
+
+    /// The source of randomness for the order of the tetromino types.
+    rng: StdRng,
+
+    /// Tetromino types that will be returned, in order, before any random ones.
+    upcoming: VecDeque<::TetrominoType>
 }
 
 impl Bag {
 
-    /// Creates a new `Bag` in its initial state.
-    pub fn new() -> Self {
+    /// Creates a new `Bag` in its initial state, with the randomizer and seed of the given
+    /// settings.
+    pub fn with_config(config: &GameConfig) -> Self {
+        let rng = match config.seed {
+            Some(seed) => StdRng::seed_from_u64(seed),
+            None => StdRng::from_entropy(),
+        };
         Bag {
             /// A `Vec` of all the pieces that have not yet been returned in
             /// the current set of 7.
-            remaining: Vec::from(&ALL_TETROMINO_TYPES as &[::TetrominoType])
+            remaining: Vec::from(&ALL_TETROMINO_TYPES as &[::TetrominoType]),
+            randomizer: config.randomizer,
+            rng,
+            upcoming: VecDeque::new()
         }
     }
 
+    /// Creates a new `Bag` with the given settings that returns the given tetromino types in
+    /// order, before any random ones.
+    pub fn with_upcoming(config: &GameConfig, upcoming: Vec<::TetrominoType>) -> Self {
+        let mut bag = Bag::with_config(config);
+        bag.upcoming = upcoming.into_iter().collect();
+        bag
+    }
+
     /// Returns the next `TetrominoType`.
     pub fn draw(&mut self) -> ::TetrominoType {
 
+        if let Some(result) = self.upcoming.pop_front() {
+            return result;
+        }
+
+        if let Randomizer::Random = self.randomizer {
+            return ALL_TETROMINO_TYPES[self.rng.gen_range(0, ALL_TETROMINO_TYPES.len())].clone();
+        }
+
         // Remove random element from remaining tetromino types.
-        let index = rand::thread_rng().gen_range(0, self.remaining.len());
+        let index = self.rng.gen_range(0, self.remaining.len());
         let result = self.remaining.remove(index);
 
         // Refill bag if it is empty
@@ -0,0 +1,252 @@
+//! A bot that plays tetris by trying every reachable placement of the falling tetromino, with and
+//! without holding, and picking the one that leaves the best matrix.
+//!
+//! Matrices are compared by a weighted sum of features such as their height and the number of
+//! holes in them. The weights decide the bot's style of play.
+
+use ::game_state::{GameState, Tetromino};
+use ::placement::{self, Input};
+
+/// How much each feature of a matrix counts towards its score. Negative weights make the bot
+/// avoid a feature, and positive ones make it seek the feature out.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Weights {
+    /// Weight of the sum of the heights of all columns.
+    pub aggregate_height: f64,
+    /// Weight of the number of empty squares with a filled square somewhere above them.
+    pub holes: f64,
+    /// Weight of the sum of the height differences between neighbouring columns.
+    pub bumpiness: f64,
+    /// Weight of the sum of the depths of wells, which are columns lower than both of their
+    /// neighbours.
+    pub wells: f64,
+    /// Weight of the number of lines cleared by the placement.
+    pub lines_cleared: f64,
+    /// Weight of the number of places where a T tetromino could be spun in to clear lines.
+    pub t_spin_slots: f64
+}
+
+impl Default for Weights {
+    fn default() -> Self {
+        Weights {
+            aggregate_height: -0.51,
+            holes: -0.36,
+            bumpiness: -0.18,
+            wells: -0.1,
+            lines_cleared: 0.76,
+            t_spin_slots: 0.3
+        }
+    }
+}
+
+/// A placement chosen by the bot.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Decision {
+    /// Whether to hold before placing, so that the held (or next) tetromino is placed instead.
+    pub hold: bool,
+    /// The tetromino in the position it is locked in.
+    pub tetromino: Tetromino,
+    /// The inputs that bring the tetromino from where it spawns to its resting position.
+    pub inputs: Vec<Input>
+}
+
+impl Decision {
+
+    /// Returns every input needed to carry out this decision, starting with holding if needed,
+    /// and ending with the hard drop that locks the tetromino.
+    pub fn to_inputs(&self) -> Vec<Input> {
+        let mut inputs = Vec::with_capacity(self.inputs.len() + 2);
+        if self.hold {
+            inputs.push(Input::Hold);
+        }
+        inputs.extend(self.inputs.iter().cloned());
+        inputs.push(Input::HardDrop);
+        inputs
+    }
+}
+
+/// A bot that picks placements for the falling tetromino.
+#[derive(Debug, Clone, Default)]
+pub struct Bot {
+    weights: Weights
+}
+
+impl Bot {
+
+    /// Creates a bot that compares matrices with the given weights.
+    pub fn new(weights: Weights) -> Self {
+        Bot { weights }
+    }
+
+    /// Returns the weights the bot compares matrices with.
+    pub fn weights(&self) -> &Weights {
+        &self.weights
+    }
+
+    /// Picks where to place the falling tetromino of `gs`, possibly after holding. Returns `None`
+    /// if every placement tops out.
+    pub fn choose(&self, gs: &GameState) -> Option<Decision> {
+        let mut best: Option<(f64, Decision)> = None;
+
+        for &hold in &[false, true] {
+            let mut start = gs.clone();
+            if hold {
+                if !gs.can_hold() || gs.held().as_ref() == Some(gs.falling_tetromino().ttype()) {
+                    continue;
+                }
+                start.hold();
+            }
+
+            for placement in placement::placements(&start) {
+                let mut after = start.clone();
+                for &input in &placement.inputs {
+                    after.apply_input(input);
+                }
+                if !after.lock_piece() {
+                    continue;
+                }
+
+                let score = self.evaluate(&after);
+                if best.as_ref().map_or(true, |&(best_score, _)| score > best_score) {
+                    best = Some((score, Decision {
+                        hold,
+                        tetromino: placement.tetromino,
+                        inputs: placement.inputs
+                    }));
+                }
+            }
+        }
+
+        best.map(|(_, decision)| decision)
+    }
+
+    /// Returns the score of the matrix of `gs`, right after a tetromino was locked on it. Higher
+    /// is better.
+    pub fn evaluate(&self, gs: &GameState) -> f64 {
+        let squares = gs.placed_squares();
+        let heights: Vec<usize> = squares.iter().map(|column| {
+            column.iter().rposition(|square| !square.is_empty()).map_or(0, |row| row + 1)
+        }).collect();
+
+        let aggregate_height: usize = heights.iter().sum();
+
+        let holes = squares.iter().zip(&heights).map(|(column, &height)| {
+            column[..height].iter().filter(|square| square.is_empty()).count()
+        }).sum::<usize>();
+
+        let bumpiness = heights.windows(2).map(|pair| pair[0].abs_diff(pair[1])).sum::<usize>();
+
+        let (width, matrix_height) = (gs.matrix_width(), gs.matrix_height());
+        let wells = (0..width).map(|col| {
+            // The walls count as higher than any column.
+            let left = if col == 0 { matrix_height } else { heights[col - 1] };
+            let right = if col == width - 1 { matrix_height } else { heights[col + 1] };
+            left.min(right).saturating_sub(heights[col])
+        }).sum::<usize>();
+
+        let w = &self.weights;
+        w.aggregate_height * aggregate_height as f64
+            + w.holes * holes as f64
+            + w.bumpiness * bumpiness as f64
+            + w.wells * wells as f64
+            + w.lines_cleared * gs.last_lines_cleared() as f64
+            + w.t_spin_slots * t_spin_slots(gs) as f64
+    }
+}
+
+/// Counts the places where a T tetromino pointing down would rest with at least 3 of the 4
+/// squares diagonal to its center filled, which are the places it could be spun into.
+fn t_spin_slots(gs: &GameState) -> usize {
+    let squares = gs.placed_squares();
+    let (width, height) = (gs.matrix_width() as isize, gs.matrix_height() as isize);
+    let filled = |col: isize, row: isize| {
+        col < 0 || col >= width || row < 0
+            || (row < height && !squares[col as usize][row as usize].is_empty())
+    };
+
+    let mut slots = 0;
+    for col in 1..width - 1 {
+        for row in 1..height - 1 {
+            let t = Tetromino::with_position(::TetrominoType::T, (col, row), 2);
+            if !gs.tetromino_fits(&t) || !filled(col, row - 2) {
+                continue;
+            }
+
+            let corners = [(col - 1, row - 1), (col + 1, row - 1), (col - 1, row + 1), (col + 1, row + 1)];
+            let filled_corners = corners.iter().filter(|&&(c, r)| filled(c, r)).count();
+            // One of the corners above the center must be filled, or the T could just be dropped
+            // in.
+            let covered = filled(col - 1, row + 1) || filled(col + 1, row + 1);
+            if filled_corners >= 3 && covered {
+                slots += 1;
+            }
+        }
+    }
+    slots
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use ::builder::GameStateBuilder;
+
+    /// Makes the inputs of the decision, and returns the number of lines they cleared.
+    fn carry_out(gs: &GameState, decision: &Decision) -> usize {
+        let mut gs = gs.clone();
+        for input in decision.to_inputs() {
+            gs.apply_input(input);
+        }
+        gs.lines_cleared()
+    }
+
+    #[test]
+    fn drops_an_i_into_the_well_for_a_tetris() {
+        let gs = GameStateBuilder::new()
+            .board("\
+                GGGGGGGGG.
+                GGGGGGGGG.
+                GGGGGGGGG.
+                GGGGGGGGG.")
+            .queue("IOOOOO")
+            .build()
+            .unwrap();
+
+        let decision = Bot::default().choose(&gs).unwrap();
+        assert!(!decision.hold);
+        assert_eq!(decision.tetromino.sorted_minoes(), vec![(9, 0), (9, 1), (9, 2), (9, 3)]);
+        assert_eq!(carry_out(&gs, &decision), 4);
+    }
+
+    #[test]
+    fn fills_the_notch_without_leaving_holes() {
+        let gs = GameStateBuilder::new()
+            .board("GGGG.GGGGG")
+            .queue("TOOOOO")
+            .build()
+            .unwrap();
+
+        let decision = Bot::default().choose(&gs).unwrap();
+        assert_eq!(decision.tetromino.sorted_minoes(), vec![(3, 1), (4, 0), (4, 1), (5, 1)]);
+        assert_eq!(carry_out(&gs, &decision), 1);
+    }
+
+    #[test]
+    fn holds_when_the_held_tetromino_fits_better() {
+        let gs = GameStateBuilder::new()
+            .board("\
+                GGGGGGGGG.
+                GGGGGGGGG.
+                GGGGGGGGG.
+                GGGGGGGGG.")
+            .falling("O")
+            .hold("I")
+            .queue("OOOOO")
+            .build()
+            .unwrap();
+
+        let decision = Bot::default().choose(&gs).unwrap();
+        assert!(decision.hold);
+        assert_eq!(carry_out(&gs, &decision), 4);
+    }
+}
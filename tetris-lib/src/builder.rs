@@ -0,0 +1,467 @@
+//! Building `GameState`s from ASCII diagrams of the matrix, and writing them back.
+//!
+//! A diagram has one line per row of the matrix, from top to bottom, with one character per
+//! column:
+//!
+//! - `.` is an empty square.
+//! - `I`, `O`, `T`, `J`, `L`, `S` and `Z` are squares of locked tetrominoes of that type.
+//! - `G` is a square of garbage.
+//! - `*` is an item block.
+//! - `i`, `o`, `t`, `j`, `l`, `s` and `z` are the minoes of the falling tetromino.
+//!
+//! Blank lines and surrounding whitespace are ignored. A diagram may have fewer rows than the
+//! matrix, in which case it describes the bottom rows and the rows above it are empty.
+
+use std::error;
+use std::fmt;
+
+use ::bag;
+use ::config::GameConfig;
+use ::fumen;
+use ::game_state::{GameState, Tetromino};
+
+/// Describes why a `GameStateBuilder` could not build a `GameState`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum BuildError {
+    /// A character of the diagram, the queue or the hold does not name anything.
+    InvalidCharacter(char),
+    /// The hold or the falling tetromino names more than one tetromino type, such as `"TI"`.
+    MoreThanOneTetromino(String),
+    /// A row of the diagram, counted from the top starting at 0, does not have one character per
+    /// column of the matrix, which has `expected` columns.
+    WrongRowWidth { row: usize, width: usize, expected: usize },
+    /// The diagram has more rows than the matrix, which has `expected` rows.
+    TooManyRows { rows: usize, expected: usize },
+    /// The lowercase letters of the diagram do not form a single tetromino.
+    InvalidFallingTetromino,
+    /// The falling tetromino overlaps the squares of the matrix.
+    FallingTetrominoDoesNotFit,
+}
+
+impl fmt::Display for BuildError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            BuildError::InvalidCharacter(c) => write!(f, "invalid character '{}'", c),
+            BuildError::MoreThanOneTetromino(ref s) =>
+                write!(f, "\"{}\" names more than one tetromino type", s),
+            BuildError::WrongRowWidth { row, width, expected } =>
+                write!(f, "row {} has {} squares instead of {}", row, width, expected),
+            BuildError::TooManyRows { rows, expected } =>
+                write!(f, "diagram has {} rows, but the matrix only has {}", rows, expected),
+            BuildError::InvalidFallingTetromino =>
+                write!(f, "lowercase letters do not form a single tetromino"),
+            BuildError::FallingTetrominoDoesNotFit =>
+                write!(f, "falling tetromino overlaps the matrix"),
+        }
+    }
+}
+
+impl error::Error for BuildError {}
+
+/// Builds a `GameState` in an arbitrary position, such as one described by an ASCII diagram.
+#[derive(Debug, Clone, Default)]
+pub struct GameStateBuilder {
+    board: String,
+    /// The squares of the matrix set directly, by column then row, used instead of `board` if
+    /// set.
+    squares: Option<Vec<Vec<::Cell>>>,
+    /// The falling tetromino set directly, used if `board` does not contain one.
+    falling_tetromino: Option<Tetromino>,
+    queue: String,
+    hold: String,
+    falling: String,
+    config: GameConfig
+}
+
+impl GameStateBuilder {
+
+    /// Creates a builder for a `GameState` with an empty matrix, and random tetrominoes.
+    pub fn new() -> Self {
+        GameStateBuilder::default()
+    }
+
+    /// Sets the squares of the matrix, and optionally the falling tetromino, from an ASCII
+    /// diagram.
+    pub fn board(mut self, diagram: &str) -> Self {
+        self.board = diagram.to_string();
+        self.squares = None;
+        self.falling_tetromino = None;
+        self
+    }
+
+    /// Sets the tetromino types that come next, in order, such as `"TIOSZ"`. Any further ones are
+    /// random.
+    pub fn queue(mut self, queue: &str) -> Self {
+        self.queue = queue.to_string();
+        self
+    }
+
+    /// Sets the held tetromino type, such as `"T"`, or none if empty.
+    pub fn hold(mut self, hold: &str) -> Self {
+        self.hold = hold.to_string();
+        self
+    }
+
+    /// Sets the type of the falling tetromino, such as `"T"`, which is placed in spawn position.
+    /// This is ignored if the diagram contains the falling tetromino. If it is not set either
+    /// way, the falling tetromino is the first one of the queue.
+    pub fn falling(mut self, falling: &str) -> Self {
+        self.falling = falling.to_string();
+        self
+    }
+
+    /// Sets the settings the game is played with, including the size of the matrix the diagram
+    /// describes. These are the default settings if not set.
+    pub fn config(mut self, config: GameConfig) -> Self {
+        self.config = config;
+        self
+    }
+
+    /// Sets the squares of the matrix, and the falling tetromino if there is one, from a page of a
+    /// fumen. The tetromino keeps the orientation it has on the page, even when another
+    /// orientation covers the same squares. Squares of the field outside the matrix, such as
+    /// those in the top row of the fumen field, are left out.
+    pub fn fumen_page(mut self, page: &fumen::Page) -> Self {
+        self.board = String::new();
+        self.squares = Some(page.field.clone());
+        self.falling_tetromino = page.tetromino.clone();
+        self
+    }
+
+    /// Builds the `GameState`.
+    pub fn build(self) -> Result<GameState, BuildError> {
+
+        let config = self.config;
+        let (width, height) = (config.matrix_width, config.matrix_height);
+        let (mut placed_squares, falling_minoes) = parse_diagram(&self.board, width, height)?;
+        if let Some(squares) = self.squares {
+            for (col, column) in squares.into_iter().enumerate().take(width) {
+                for (row, square) in column.into_iter().enumerate().take(height) {
+                    placed_squares[col][row] = square;
+                }
+            }
+        }
+        let mut queue = parse_tetromino_types(&self.queue)?;
+        let held = parse_tetromino_type(&self.hold)?;
+        let falling_type = parse_tetromino_type(&self.falling)?;
+
+        let falling_tetromino = if let Some((ttype, minoes)) = falling_minoes {
+            find_tetromino(ttype, &minoes).ok_or(BuildError::InvalidFallingTetromino)?
+        } else if let Some(falling_tetromino) = self.falling_tetromino {
+            falling_tetromino
+        } else if let Some(falling_type) = falling_type {
+            Tetromino::spawned(falling_type, &config)
+        } else if !queue.is_empty() {
+            Tetromino::spawned(queue.remove(0), &config)
+        } else {
+            Tetromino::spawned(bag::Bag::with_config(&config).draw(), &config)
+        };
+
+        let fits = falling_tetromino.minoes().iter().all(|&(col, row)| {
+            col >= 0 && (col as usize) < width && row >= 0 && (row as usize) < height
+                && placed_squares[col as usize][row as usize].is_empty()
+        });
+        if !fits {
+            return Err(BuildError::FallingTetrominoDoesNotFit);
+        }
+
+        let upcoming = if queue.len() > config.preview_length {
+            queue.split_off(config.preview_length)
+        } else {
+            Vec::new()
+        };
+        let mut bag = bag::Bag::with_upcoming(&config, upcoming);
+        while queue.len() < config.preview_length {
+            queue.push(bag.draw());
+        }
+
+        Ok(GameState::from_parts(config, placed_squares, falling_tetromino, bag, queue, held))
+    }
+}
+
+impl GameState {
+
+    /// Returns an ASCII diagram of the matrix and the falling tetromino, in the format read by
+    /// `GameStateBuilder::board`. Empty rows at the top are left out, and invisible squares are
+    /// written like visible ones.
+    pub fn to_ascii(&self) -> String {
+        let mut rows = self.matrix_rows();
+
+        let falling_tetromino = self.falling_tetromino();
+        let falling_char = falling_tetromino.ttype().to_char().to_ascii_lowercase();
+        for (col, row) in falling_tetromino.minoes() {
+            rows[row as usize][col as usize] = falling_char;
+        }
+
+        write_diagram(rows)
+    }
+
+    /// Returns an ASCII diagram of the matrix like `to_ascii`, but without the falling tetromino.
+    pub fn matrix_to_ascii(&self) -> String {
+        write_diagram(self.matrix_rows())
+    }
+
+    /// Returns the diagram characters of the squares of the matrix, by row from the bottom.
+    fn matrix_rows(&self) -> Vec<Vec<char>> {
+        (0..self.matrix_height()).map(|row| {
+            (0..self.matrix_width()).map(|col| cell_to_char(&self.placed_squares()[col][row])).collect()
+        }).collect()
+    }
+}
+
+/// Writes the given rows of diagram characters, indexed from the bottom, as a diagram. Empty rows
+/// at the top are left out.
+pub(crate) fn write_diagram(mut rows: Vec<Vec<char>>) -> String {
+    while rows.len() > 1 && rows.last().is_some_and(|row| row.iter().all(|&c| c == '.')) {
+        rows.pop();
+    }
+
+    let mut diagram = String::new();
+    for row in rows.iter().rev() {
+        diagram.extend(row.iter());
+        diagram.push('\n');
+    }
+    diagram
+}
+
+fn cell_to_char(cell: &::Cell) -> char {
+    match *cell {
+        ::Cell::Empty => '.',
+        ::Cell::Piece(ref tt) | ::Cell::Invisible(ref tt) => tt.to_char(),
+        ::Cell::Garbage => 'G',
+        ::Cell::Item => '*',
+    }
+}
+
+/// The type of the falling tetromino in a diagram, and the positions of its minoes.
+type FallingMinoes = Option<(::TetrominoType, Vec<(isize, isize)>)>;
+
+/// Parses a diagram into the squares of a matrix of the given size and the falling tetromino, if it
+/// has one.
+fn parse_diagram(diagram: &str, width: usize, height: usize)
+        -> Result<(Vec<Vec<::Cell>>, FallingMinoes), BuildError> {
+
+    let lines: Vec<&str> = diagram.lines()
+        .map(|line| line.trim())
+        .filter(|line| !line.is_empty())
+        .collect();
+
+    if lines.len() > height {
+        return Err(BuildError::TooManyRows { rows: lines.len(), expected: height });
+    }
+
+    let mut placed_squares = vec![vec![::Cell::Empty; height]; width];
+    let mut falling_minoes: FallingMinoes = None;
+
+    for (i, line) in lines.iter().enumerate() {
+        let row_width = line.chars().count();
+        if row_width != width {
+            return Err(BuildError::WrongRowWidth { row: i, width: row_width, expected: width });
+        }
+
+        let row = lines.len() - 1 - i;
+        for (col, c) in line.chars().enumerate() {
+            placed_squares[col][row] = match c {
+                '.' => ::Cell::Empty,
+                'G' => ::Cell::Garbage,
+                '*' => ::Cell::Item,
+                _ => match ::TetrominoType::from_char(c) {
+                    Some(tt) if c.is_lowercase() => {
+                        let position = (col as isize, row as isize);
+                        match falling_minoes {
+                            Some((ref falling_type, ref mut minoes)) if *falling_type == tt =>
+                                minoes.push(position),
+                            Some(_) => return Err(BuildError::InvalidFallingTetromino),
+                            None => falling_minoes = Some((tt, vec![position])),
+                        }
+                        ::Cell::Empty
+                    },
+                    Some(tt) => ::Cell::Piece(tt),
+                    None => return Err(BuildError::InvalidCharacter(c)),
+                },
+            };
+        }
+    }
+
+    Ok((placed_squares, falling_minoes))
+}
+
+/// Parses a string of tetromino type letters, ignoring whitespace.
+fn parse_tetromino_types(s: &str) -> Result<Vec<::TetrominoType>, BuildError> {
+    s.chars()
+        .filter(|c| !c.is_whitespace())
+        .map(|c| ::TetrominoType::from_char(c).ok_or(BuildError::InvalidCharacter(c)))
+        .collect()
+}
+
+/// Parses a string of at most one tetromino type letter, ignoring whitespace.
+fn parse_tetromino_type(s: &str) -> Result<Option<::TetrominoType>, BuildError> {
+    let mut types = parse_tetromino_types(s)?;
+    if types.len() > 1 {
+        return Err(BuildError::MoreThanOneTetromino(s.to_string()));
+    }
+    Ok(types.pop())
+}
+
+/// Finds a tetromino of the given type whose minoes are exactly at the given positions.
+fn find_tetromino(ttype: ::TetrominoType, minoes: &[(isize, isize)]) -> Option<Tetromino> {
+    let mut minoes = minoes.to_vec();
+    minoes.sort();
+
+    for orientation in 0..4 {
+        // Try each mino of the shape as the one at the first position, which fixes the center.
+        let at_origin = Tetromino::with_position(ttype.clone(), (0, 0), orientation);
+        for offset in at_origin.minoes() {
+            let center = (minoes[0].0 - offset.0, minoes[0].1 - offset.1);
+            let candidate = Tetromino::with_position(ttype.clone(), center, orientation);
+            let mut candidate_minoes = candidate.minoes();
+            candidate_minoes.sort();
+            if candidate_minoes == minoes {
+                return Some(candidate);
+            }
+        }
+    }
+
+    None
+}
+
+/// A game whose falling I clears four lines, but not the whole matrix, when it is hard dropped.
+#[cfg(test)]
+pub(crate) fn tetris_ready() -> GameState {
+    GameStateBuilder::new()
+        .board("\
+            G.........
+            GGGGGGGGGi
+            GGGGGGGGGi
+            GGGGGGGGGi
+            GGGGGGGGGi")
+        .build()
+        .unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trips_a_diagram() {
+        let diagram = "\
+            ....t.....\n\
+            ...ttt....\n\
+            *.........\n\
+            GGGG.GGGGG\n";
+        let gs = GameStateBuilder::new().board(diagram).build().unwrap();
+        assert_eq!(gs.to_ascii(), diagram);
+        assert_eq!(gs.matrix_to_ascii(), "*.........\nGGGG.GGGGG\n");
+    }
+
+    #[test]
+    fn kicks_a_t_into_a_t_spin_double() {
+        // The T cannot turn in place, nor one square to the left, so it takes the third kick of
+        // the SRS table, down and to the left, into the slot.
+        let mut gs = GameStateBuilder::new()
+            .board("\
+                ..t.......
+                GttGGGGGGG
+                ..tGGGGGGG
+                G.GGGGGGGG")
+            .queue("O")
+            .build()
+            .unwrap();
+
+        assert!(gs.rotate_left());
+        assert_eq!(gs.to_ascii(), "\
+            G..GGGGGGG\n\
+            tttGGGGGGG\n\
+            GtGGGGGGGG\n");
+
+        assert!(gs.hard_drop());
+        assert_eq!(gs.matrix_to_ascii(), "G..GGGGGGG\n");
+        let clear = gs.last_clear().unwrap();
+        assert_eq!((clear.lines, clear.t_spin), (2, ::game_state::TSpin::Full));
+    }
+
+    #[test]
+    fn kicks_an_i_off_the_wall() {
+        // Turning flat in place would put the I outside the matrix, so it is kicked to the right.
+        let mut gs = GameStateBuilder::new()
+            .board("\
+                i.........
+                i.........
+                i.........
+                i.........")
+            .build()
+            .unwrap();
+
+        assert!(gs.rotate_right());
+        assert_eq!(gs.to_ascii(), "\
+            iiii......\n\
+            ..........\n");
+    }
+
+    #[test]
+    fn clears_lines_and_moves_the_rows_above_down() {
+        let mut gs = GameStateBuilder::new()
+            .board("\
+                .........i
+                G........i
+                GGGGGGGGGi
+                GGGGGGGGGi")
+            .build()
+            .unwrap();
+
+        assert!(gs.hard_drop());
+        assert_eq!(gs.matrix_to_ascii(), "\
+            .........I\n\
+            G........I\n");
+        assert_eq!(gs.lines_cleared(), 2);
+    }
+
+    #[test]
+    fn rejects_more_than_one_held_or_falling_tetromino() {
+        assert_eq!(
+            GameStateBuilder::new().hold("TI").build().unwrap_err(),
+            BuildError::MoreThanOneTetromino(String::from("TI"))
+        );
+        assert_eq!(
+            GameStateBuilder::new().falling("SZ").build().unwrap_err(),
+            BuildError::MoreThanOneTetromino(String::from("SZ"))
+        );
+        assert_eq!(GameStateBuilder::new().hold("T").build().unwrap().held(), &Some(::TetrominoType::T));
+    }
+
+    #[test]
+    fn keeps_the_orientation_of_a_fumen_tetromino() {
+        // A vertical I facing left in the rightmost column, which covers the same squares as an I
+        // facing right one column to its left, but kicks differently when it turns.
+        let pages = fumen::decode("v115@vhA5IJ").unwrap();
+        let gs = GameStateBuilder::new().fumen_page(&pages[0]).build().unwrap();
+        assert_eq!(gs.falling_tetromino().ttype(), &::TetrominoType::I);
+        assert_eq!(gs.falling_tetromino().orientation(), 3);
+        assert_eq!(gs.falling_tetromino(), pages[0].tetromino.as_ref().unwrap());
+
+        // An S facing down, which covers the same squares as an S facing up one row lower.
+        let page = fumen::Page {
+            tetromino: Some(Tetromino::with_position(::TetrominoType::S, (4, 1), 2)),
+            ..fumen::Page::new()
+        };
+        let decoded = fumen::decode(&fumen::encode(&[page])).unwrap();
+        let gs = GameStateBuilder::new().fumen_page(&decoded[0]).build().unwrap();
+        assert_eq!(gs.falling_tetromino().ttype(), &::TetrominoType::S);
+        assert_eq!(gs.falling_tetromino().orientation(), 2);
+        assert_eq!(gs.falling_tetromino().center(), (4, 1));
+    }
+
+    #[test]
+    fn loads_a_fumen_field_taller_than_the_matrix() {
+        let mut page = fumen::Page::new();
+        page.field[0][0] = ::Cell::Garbage;
+        page.field[3][fumen::FIELD_HEIGHT - 1] = ::Cell::Piece(::TetrominoType::T);
+        page.tetromino = Some(Tetromino::with_position(::TetrominoType::O, (4, 5), 0));
+
+        let gs = GameStateBuilder::new().fumen_page(&page).build().unwrap();
+        assert_eq!(gs.placed_squares()[0][0], ::Cell::Garbage);
+        assert_eq!(gs.matrix_to_ascii(), "G.........\n");
+        assert_eq!(gs.falling_tetromino(), page.tetromino.as_ref().unwrap());
+    }
+}
@@ -0,0 +1,286 @@
+//! Parsing the command-line arguments of `tetris_cli`.
+
+use std::error;
+use std::fmt;
+use std::str::FromStr;
+
+use tetris::config::{GameConfig, Randomizer, RotationSystem, MIN_MATRIX_HEIGHT, MIN_MATRIX_WIDTH};
+use tetris::game_mode::{GameMode, MARATHON_MAX_LEVEL};
+
+use super::color::ColorMode;
+
+pub const USAGE: &str = "\
+usage: tetris_cli [options] [fumen]
+
+Starts from the board of the given fumen, or from an empty matrix.
+
+options:
+  --mode MODE          endless, ultra, marathon, dig or survival (default: endless)
+  --level N            starting level of marathon, from 1 to 15 (default: 1)
+  --seed N             seed for the order of the tetrominoes (default: random)
+  --width N            number of columns of the matrix (default: 10)
+  --height N           number of rows of the matrix, including hidden ones (default: 22)
+  --preview N          number of tetrominoes shown in advance (default: 5)
+  --step MS            milliseconds an empty line advances time by (default: 150)
+  --randomizer NAME    bag or random (default: bag)
+  --rotation NAME      srs or no-kicks (default: srs)
+  --script FILE        run the commands and assertions in FILE without interaction, then
+                       print the board and exit with 0 if they all passed (default seed: 0);
+                       cannot be used with --tui or --autoplay
+  --color MODE         auto, never, 16, 256 or truecolor (default: auto)
+  --tui                play in real time
+  --autoplay           let the bot play
+  -h, --help           print this help";
+
+/// Describes why the command-line arguments could not be parsed.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ArgsError {
+    /// The option is not known.
+    UnknownOption(String),
+    /// The option needs a value, but none was given.
+    MissingValue(String),
+    /// The value given to the option is not one it accepts. `expected` describes the ones it
+    /// accepts.
+    InvalidValue { option: String, value: String, expected: &'static str },
+    /// More than one fumen was given.
+    TooManyFumens,
+    /// `--level` was given for a game mode without levels.
+    LevelWithoutMarathon,
+    /// Two options were given that cannot be used together, such as `--script` and `--tui`.
+    ConflictingOptions(&'static str, &'static str),
+}
+
+impl fmt::Display for ArgsError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ArgsError::UnknownOption(ref option) => write!(f, "unknown option {}", option),
+            ArgsError::MissingValue(ref option) => write!(f, "{} needs a value", option),
+            ArgsError::InvalidValue { ref option, ref value, expected } =>
+                write!(f, "invalid value '{}' for {}, expected {}", value, option, expected),
+            ArgsError::TooManyFumens => write!(f, "only one fumen can be given"),
+            ArgsError::LevelWithoutMarathon => write!(f, "--level only applies to --mode marathon"),
+            ArgsError::ConflictingOptions(first, second) => write!(f, "{} cannot be used with {}", first, second),
+        }
+    }
+}
+
+impl error::Error for ArgsError {}
+
+/// What `tetris_cli` was asked to do.
+#[derive(Debug, Clone)]
+pub struct Options {
+    /// The settings of the game.
+    pub config: GameConfig,
+    /// The rules that decide how the game ends.
+    pub mode: GameMode,
+    /// The number of milliseconds an empty line advances time by.
+    pub step: u32,
+    /// The file of commands and assertions to run without interaction.
+    pub script: Option<String>,
+    /// The fumen whose board the game starts from.
+    pub fumen: Option<String>,
+    /// Which colors to draw the game in.
+    pub colors: ColorMode,
+    /// Whether to play in real time.
+    pub real_time: bool,
+    /// Whether to let the bot play.
+    pub autoplay: bool,
+    /// Whether to print the usage and exit.
+    pub help: bool
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Options {
+            config: GameConfig::default(),
+            mode: GameMode::Endless,
+            step: 150,
+            script: None,
+            fumen: None,
+            colors: ColorMode::detect(),
+            real_time: false,
+            autoplay: false,
+            help: false
+        }
+    }
+}
+
+/// Parses the given arguments, not including the name of the program. Options that take a value
+/// accept it either as the next argument or after an `=`, as in `--seed=7`.
+pub fn parse<I: IntoIterator<Item = String>>(args: I) -> Result<Options, ArgsError> {
+    let mut options = Options::default();
+    let mut mode_name = String::from("endless");
+    let mut level = None;
+
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        if !arg.starts_with('-') {
+            if options.fumen.is_some() {
+                return Err(ArgsError::TooManyFumens);
+            }
+            options.fumen = Some(arg);
+            continue;
+        }
+
+        let (option, inline_value) = match arg.find('=') {
+            Some(i) => (arg[..i].to_string(), Some(arg[i + 1..].to_string())),
+            None => (arg.clone(), None),
+        };
+
+        match option.as_str() {
+            "-h" | "--help" => { options.help = true; continue; },
+            "--tui" => { options.real_time = true; continue; },
+            "--autoplay" => { options.autoplay = true; continue; },
+            _ => {}
+        }
+
+        if !takes_value(&option) {
+            return Err(ArgsError::UnknownOption(option));
+        }
+        let value = match inline_value.or_else(|| args.next()) {
+            Some(value) => value,
+            None => return Err(ArgsError::MissingValue(option)),
+        };
+        let invalid = |expected| ArgsError::InvalidValue {
+            option: option.clone(),
+            value: value.clone(),
+            expected
+        };
+
+        match option.as_str() {
+            "--mode" => {
+                match value.as_str() {
+                    "endless" | "ultra" | "marathon" | "dig" | "survival" => mode_name = value.clone(),
+                    _ => return Err(invalid("endless, ultra, marathon, dig or survival")),
+                }
+            },
+            "--level" => level = Some(value.parse().ok().filter(|level| (1..=MARATHON_MAX_LEVEL).contains(level))
+                .ok_or_else(|| invalid("a level from 1 to 15"))?),
+            "--seed" => options.config.seed = Some(value.parse().map_err(|_| invalid("a whole number"))?),
+            "--width" => options.config.matrix_width = parse_at_least(&value, MIN_MATRIX_WIDTH)
+                .ok_or_else(|| invalid("a number of columns of at least 4"))?,
+            "--height" => options.config.matrix_height = parse_at_least(&value, MIN_MATRIX_HEIGHT)
+                .ok_or_else(|| invalid("a number of rows of at least 4"))?,
+            "--preview" => options.config.preview_length = value.parse().map_err(|_| invalid("a whole number"))?,
+            "--step" => options.step = parse_at_least(&value, 1).ok_or_else(|| invalid("a number of milliseconds of at least 1"))?,
+            "--randomizer" => options.config.randomizer = match value.as_str() {
+                "bag" => Randomizer::SevenBag,
+                "random" => Randomizer::Random,
+                _ => return Err(invalid("bag or random")),
+            },
+            "--rotation" => options.config.rotation_system = match value.as_str() {
+                "srs" => RotationSystem::Srs,
+                "no-kicks" => RotationSystem::NoKicks,
+                _ => return Err(invalid("srs or no-kicks")),
+            },
+            "--script" => options.script = Some(value.clone()),
+            "--color" => options.colors = ColorMode::from_name(&value)
+                .ok_or_else(|| invalid("auto, never, 16, 256 or truecolor"))?,
+            _ => return Err(ArgsError::UnknownOption(option)),
+        }
+    }
+
+    options.mode = match mode_name.as_str() {
+        "ultra" => GameMode::ultra(),
+        "marathon" => GameMode::marathon(level.unwrap_or(1)),
+        "dig" => GameMode::dig(),
+        "survival" => GameMode::survival(),
+        _ => GameMode::Endless,
+    };
+    if level.is_some() && mode_name != "marathon" {
+        return Err(ArgsError::LevelWithoutMarathon);
+    }
+    // A script plays the game by itself, so it cannot also be played by the player or the bot.
+    if options.script.is_some() {
+        if options.real_time {
+            return Err(ArgsError::ConflictingOptions("--script", "--tui"));
+        }
+        if options.autoplay {
+            return Err(ArgsError::ConflictingOptions("--script", "--autoplay"));
+        }
+    }
+
+    Ok(options)
+}
+
+/// Returns `true` if the given option is one that takes a value.
+fn takes_value(option: &str) -> bool {
+    matches!(option,
+        "--mode" | "--level" | "--seed" | "--width" | "--height" | "--preview" | "--step"
+        | "--randomizer" | "--rotation" | "--script" | "--color")
+}
+
+/// Parses a number that must be at least `min`.
+fn parse_at_least<T: FromStr + PartialOrd>(value: &str, min: T) -> Option<T> {
+    value.parse().ok().filter(|n| *n >= min)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse_args(args: &[&str]) -> Result<Options, ArgsError> {
+        parse(args.iter().map(|arg| arg.to_string()))
+    }
+
+    #[test]
+    fn parses_script_options() {
+        let options = parse_args(&["--script", "kicks.txt", "--seed=7"]).unwrap();
+        assert_eq!(options.script, Some(String::from("kicks.txt")));
+        assert_eq!(options.config.seed, Some(7));
+    }
+
+    #[test]
+    fn rejects_script_with_interactive_options() {
+        assert_eq!(
+            parse_args(&["--script", "kicks.txt", "--tui"]).unwrap_err(),
+            ArgsError::ConflictingOptions("--script", "--tui")
+        );
+        assert_eq!(
+            parse_args(&["--autoplay", "--script=kicks.txt"]).unwrap_err(),
+            ArgsError::ConflictingOptions("--script", "--autoplay")
+        );
+    }
+
+    #[test]
+    fn parses_game_settings() {
+        let options = parse_args(&[
+            "--mode", "marathon", "--level=4", "--width", "6", "--height", "30", "--preview", "2",
+            "--randomizer", "random", "--rotation=no-kicks", "v115@vhAAgH"
+        ]).unwrap();
+        assert!(matches!(options.mode, GameMode::Marathon { start_level: 4, .. }));
+        assert_eq!(options.config, GameConfig {
+            matrix_width: 6,
+            matrix_height: 30,
+            preview_length: 2,
+            randomizer: Randomizer::Random,
+            rotation_system: RotationSystem::NoKicks,
+            seed: None
+        });
+        assert_eq!(options.fumen, Some(String::from("v115@vhAAgH")));
+    }
+
+    #[test]
+    fn rejects_invalid_settings() {
+        assert_eq!(parse_args(&["--level", "3"]).unwrap_err(), ArgsError::LevelWithoutMarathon);
+        assert_eq!(parse_args(&["--mode", "ultra", "--level", "3"]).unwrap_err(), ArgsError::LevelWithoutMarathon);
+        assert_eq!(parse_args(&["--mode", "marathon", "--level", "16"]).unwrap_err(), ArgsError::InvalidValue {
+            option: String::from("--level"),
+            value: String::from("16"),
+            expected: "a level from 1 to 15"
+        });
+        assert_eq!(parse_args(&["--width", "3"]).unwrap_err(), ArgsError::InvalidValue {
+            option: String::from("--width"),
+            value: String::from("3"),
+            expected: "a number of columns of at least 4"
+        });
+        assert_eq!(parse_args(&["--mode=tetris"]).unwrap_err(), ArgsError::InvalidValue {
+            option: String::from("--mode"),
+            value: String::from("tetris"),
+            expected: "endless, ultra, marathon, dig or survival"
+        });
+        assert_eq!(parse_args(&["--seed"]).unwrap_err(), ArgsError::MissingValue(String::from("--seed")));
+        assert_eq!(parse_args(&["--speed", "3"]).unwrap_err(), ArgsError::UnknownOption(String::from("--speed")));
+        assert_eq!(parse_args(&["v115@vhAAgH", "v115@vhAAgH"]).unwrap_err(), ArgsError::TooManyFumens);
+    }
+}
@@ -0,0 +1,168 @@
+//! Coloring squares with ANSI escape codes, in the colors the web version uses.
+
+use std::env;
+use std::io::{self, IsTerminal};
+
+use tetris;
+
+/// A color, both as a red, green and blue value for terminals that support it, and as the closest
+/// of the 16 basic ANSI foreground colors for those that do not.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Color {
+    rgb: (u8, u8, u8),
+    ansi: u8
+}
+
+/// The color of garbage squares.
+pub const GARBAGE_COLOR: Color = Color { rgb: (0x90, 0x90, 0x90), ansi: 90 };
+
+/// Returns the color of squares of the given tetromino type, which is the lighter of the two
+/// colors of the web version.
+pub fn tetromino_type_to_color(tt: &tetris::TetrominoType) -> Color {
+    match *tt {
+        tetris::TetrominoType::I => Color { rgb: (0x40, 0xff, 0xff), ansi: 96 },
+        tetris::TetrominoType::J => Color { rgb: (0x40, 0x40, 0xff), ansi: 94 },
+        tetris::TetrominoType::L => Color { rgb: (0xff, 0xa0, 0x40), ansi: 33 },
+        tetris::TetrominoType::O => Color { rgb: (0xff, 0xff, 0x40), ansi: 93 },
+        tetris::TetrominoType::S => Color { rgb: (0x40, 0xff, 0x40), ansi: 92 },
+        tetris::TetrominoType::T => Color { rgb: (0xff, 0x40, 0xff), ansi: 95 },
+        tetris::TetrominoType::Z => Color { rgb: (0xff, 0x40, 0x40), ansi: 91 },
+    }
+}
+
+/// Which colors the terminal can show.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColorMode {
+    /// No colors at all.
+    Monochrome,
+    /// The 16 basic ANSI colors.
+    Ansi16,
+    /// The 256 colors of the xterm palette.
+    Ansi256,
+    /// Any red, green and blue value.
+    TrueColor
+}
+
+impl ColorMode {
+
+    /// Guesses what the terminal on stdout supports from the environment. Colors are left out
+    /// when stdout is not a terminal, or when `NO_COLOR` is set.
+    pub fn detect() -> Self {
+        ColorMode::from_environment(
+            env::var_os("NO_COLOR").is_some_and(|value| !value.is_empty()),
+            io::stdout().is_terminal(),
+            &env::var("COLORTERM").unwrap_or_default(),
+            &env::var("TERM").unwrap_or_default()
+        )
+    }
+
+    /// Guesses what a terminal supports from whether `NO_COLOR` is set to something, whether
+    /// the output is a terminal, and the values of `COLORTERM` and `TERM`.
+    fn from_environment(no_color: bool, is_terminal: bool, colorterm: &str, term: &str) -> Self {
+        if no_color || !is_terminal {
+            ColorMode::Monochrome
+        } else if colorterm == "truecolor" || colorterm == "24bit" {
+            ColorMode::TrueColor
+        } else if term.contains("256color") {
+            ColorMode::Ansi256
+        } else if term == "dumb" {
+            ColorMode::Monochrome
+        } else {
+            ColorMode::Ansi16
+        }
+    }
+
+    /// Parses the name of a color mode as given on the command line: `auto`, `never`, `16`, `256`
+    /// or `truecolor`.
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "auto" => Some(ColorMode::detect()),
+            "never" => Some(ColorMode::Monochrome),
+            "16" => Some(ColorMode::Ansi16),
+            "256" => Some(ColorMode::Ansi256),
+            "truecolor" => Some(ColorMode::TrueColor),
+            _ => None
+        }
+    }
+
+    /// Returns the given text in the given color.
+    pub fn paint(self, text: &str, color: Color) -> String {
+        let (r, g, b) = color.rgb;
+        match self {
+            ColorMode::Monochrome => String::from(text),
+            ColorMode::Ansi16 => format!("\x1b[{}m{}\x1b[0m", color.ansi, text),
+            ColorMode::Ansi256 => format!("\x1b[38;5;{}m{}\x1b[0m", rgb_to_ansi256(r, g, b), text),
+            ColorMode::TrueColor => format!("\x1b[38;2;{};{};{}m{}\x1b[0m", r, g, b, text),
+        }
+    }
+}
+
+/// Returns the closest color of the 6×6×6 cube of the xterm palette.
+fn rgb_to_ansi256(r: u8, g: u8, b: u8) -> u8 {
+    let level = |value: u8| ((u16::from(value) * 5 + 127) / 255) as u8;
+    16 + 36 * level(r) + 6 * level(g) + level(b)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_color_mode_names() {
+        assert_eq!(ColorMode::from_name("never"), Some(ColorMode::Monochrome));
+        assert_eq!(ColorMode::from_name("16"), Some(ColorMode::Ansi16));
+        assert_eq!(ColorMode::from_name("256"), Some(ColorMode::Ansi256));
+        assert_eq!(ColorMode::from_name("truecolor"), Some(ColorMode::TrueColor));
+        assert!(ColorMode::from_name("auto").is_some());
+        assert_eq!(ColorMode::from_name("always"), None);
+    }
+
+    #[test]
+    fn guesses_the_color_mode_from_the_environment() {
+        assert_eq!(ColorMode::from_environment(false, true, "truecolor", "xterm"), ColorMode::TrueColor);
+        assert_eq!(ColorMode::from_environment(false, true, "24bit", "xterm"), ColorMode::TrueColor);
+        assert_eq!(ColorMode::from_environment(false, true, "", "xterm-256color"), ColorMode::Ansi256);
+        assert_eq!(ColorMode::from_environment(false, true, "", "xterm"), ColorMode::Ansi16);
+        assert_eq!(ColorMode::from_environment(false, true, "", "dumb"), ColorMode::Monochrome);
+    }
+
+    #[test]
+    fn leaves_colors_out_with_no_color_or_without_a_terminal() {
+        assert_eq!(ColorMode::from_environment(true, true, "truecolor", "xterm-256color"), ColorMode::Monochrome);
+        assert_eq!(ColorMode::from_environment(false, false, "truecolor", "xterm-256color"), ColorMode::Monochrome);
+    }
+
+    #[test]
+    fn finds_the_closest_color_of_the_xterm_cube() {
+        assert_eq!(rgb_to_ansi256(0, 0, 0), 16);
+        assert_eq!(rgb_to_ansi256(0xff, 0xff, 0xff), 231);
+        assert_eq!(rgb_to_ansi256(0xff, 0, 0), 196);
+        assert_eq!(rgb_to_ansi256(0x40, 0xff, 0xff), 16 + 36 + 6 * 5 + 5);
+    }
+
+    #[test]
+    fn paints_in_each_color_mode() {
+        let color = tetromino_type_to_color(&tetris::TetrominoType::Z);
+        assert_eq!(ColorMode::Monochrome.paint("x", color), "x");
+        assert_eq!(ColorMode::Ansi16.paint("x", color), "\x1b[91mx\x1b[0m");
+        assert_eq!(ColorMode::Ansi256.paint("x", color), "\x1b[38;5;203mx\x1b[0m");
+        assert_eq!(ColorMode::TrueColor.paint("x", color), "\x1b[38;2;255;64;64mx\x1b[0m");
+    }
+
+    #[test]
+    fn uses_the_lighter_colors_of_the_web_version() {
+        // The second color of each type in `tetromino_type_to_colors` of the web version.
+        for &(ref tt, rgb) in &[
+            (tetris::TetrominoType::I, (0x40, 0xff, 0xff)),
+            (tetris::TetrominoType::J, (0x40, 0x40, 0xff)),
+            (tetris::TetrominoType::L, (0xff, 0xa0, 0x40)),
+            (tetris::TetrominoType::O, (0xff, 0xff, 0x40)),
+            (tetris::TetrominoType::S, (0x40, 0xff, 0x40)),
+            (tetris::TetrominoType::T, (0xff, 0x40, 0xff)),
+            (tetris::TetrominoType::Z, (0xff, 0x40, 0x40)),
+        ] {
+            assert_eq!(tetromino_type_to_color(tt).rgb, rgb, "{:?}", tt);
+        }
+        assert_eq!(GARBAGE_COLOR.rgb, (0x90, 0x90, 0x90));
+    }
+}
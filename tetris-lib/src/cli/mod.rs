@@ -0,0 +1,7 @@
+//! The parts of `tetris_cli` that display the game and read the player's input.
+
+pub mod args;
+pub mod color;
+pub mod render;
+pub mod script;
+pub mod tui;
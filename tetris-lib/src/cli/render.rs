@@ -0,0 +1,166 @@
+//! Laying out games as text tables, shared by the line-based and the real-time interfaces.
+
+use prettytable;
+
+use tetris;
+use tetris::game_state::{GameEvent, TimedGameState};
+use tetris::view::{PieceView, Square, Timers, ViewModel};
+
+use super::color::{self, ColorMode};
+
+/// Lays out the hold, the matrix, the next preview and the timers of the game side by side, with
+/// squares in the given colors.
+pub fn render_timed_game_state(tgs: &TimedGameState, colors: ColorMode) -> prettytable::Table {
+
+    let view = ViewModel::new(tgs);
+    let hold_display = render_hold_display(&view, colors);
+    let matrix_display = render_matrix_display(&view, colors);
+    let piece_queue_display = render_next_preview(&view, colors);
+    let time_display = render_time_display(&view.timers);
+
+    let mut table = table![
+        [hold_display, matrix_display, piece_queue_display, time_display]
+    ];
+
+    table.set_format(*prettytable::format::consts::FORMAT_CLEAN);
+
+    table
+}
+
+fn render_time_display(timers: &Timers) -> prettytable::Table {
+
+    let mut table = table![
+        ["score", timers.score],
+        ["level", timers.level],
+        ["time to lock", format!("{} ms", timers.time_to_lock)],
+        ["time to fall", match timers.time_to_fall {
+            Some(time_to_fall) => format!("{} ms", time_to_fall),
+            None => format!("---")
+        }]
+    ];
+
+    if let Some(time_remaining) = timers.time_remaining {
+        table.add_row(row!["time left", format!("{} ms", time_remaining)]);
+    }
+
+    if let Some(time_to_garbage) = timers.time_to_garbage {
+        table.add_row(row!["time to garbage", format!("{} ms", time_to_garbage)]);
+    }
+
+    if let Some(lines_remaining) = timers.lines_remaining {
+        table.add_row(row!["lines left", lines_remaining]);
+    }
+
+    table.set_format(*prettytable::format::consts::FORMAT_CLEAN);
+
+    table
+}
+
+fn render_hold_display(view: &ViewModel, colors: ColorMode) -> prettytable::Table {
+    let mut hold_table = table![
+        ["HOLD:"],
+        [table![[
+            if let Some(ref held) = view.hold {
+                render_tetromino(held, colors, view.hold_available)
+            } else {
+                String::from("        \n        \n")
+            }
+        ]]]
+    ];
+
+    hold_table.set_format(*prettytable::format::consts::FORMAT_CLEAN);
+
+    hold_table
+}
+
+fn render_matrix_display(view: &ViewModel, colors: ColorMode) -> prettytable::Table {
+
+    let mut matrix_display: String = String::new();
+
+    for ii in 0..view.height {
+        let i = view.height - 1 - ii;
+        matrix_display.push_str(" ");
+        for j in 0..view.width {
+            let c = match *view.square(j, i) {
+                Square::Locked(tetris::Cell::Piece(ref tt)) | Square::Falling(ref tt) =>
+                    colors.paint("▣ ", color::tetromino_type_to_color(tt)),
+                Square::Locked(tetris::Cell::Garbage) => colors.paint("▩ ", color::GARBAGE_COLOR),
+                Square::Locked(tetris::Cell::Item) => String::from("◈ "),
+                Square::Ghost(ref tt) => colors.paint("□ ", color::tetromino_type_to_color(tt)),
+                Square::Locked(_) | Square::Empty => String::from("· "),
+            };
+            matrix_display.push_str(&c);
+        }
+        matrix_display.push_str("\n");
+    }
+
+    table![[matrix_display]]
+}
+
+fn render_next_preview(view: &ViewModel, colors: ColorMode) -> prettytable::Table {
+
+    let next = match view.preview.first() {
+        Some(next) => render_tetromino(next, colors, true),
+        None => String::from("        \n        \n")
+    };
+
+    let mut subsequent_table = prettytable::Table::new();
+    for piece in view.preview.iter().skip(1) {
+        subsequent_table.add_row(row![render_tetromino(piece, colors, true)]);
+    }
+
+    let mut next_preview_table = table![
+        [table![[next]]],
+        [subsequent_table]
+    ];
+
+    next_preview_table.set_format(*prettytable::format::consts::FORMAT_NO_BORDER);
+
+    let mut next_preview_table_with_label = table![
+        ["NEXT:"],
+        [next_preview_table]
+    ];
+
+    next_preview_table_with_label.set_format(*prettytable::format::consts::FORMAT_CLEAN);
+
+    next_preview_table_with_label
+}
+
+/// Draws the tetromino centered in a box 4 squares wide and 2 high, in its color if `available`,
+/// or grayed out otherwise.
+fn render_tetromino(piece: &PieceView, colors: ColorMode, available: bool) -> String {
+
+    let piece_color = if available { color::tetromino_type_to_color(&piece.ttype) } else { color::GARBAGE_COLOR };
+
+    let left = (4 - piece.width) / 2;
+    let mut squares_to_print: Vec<Vec<bool>> = vec![vec![false; 2]; 4];
+    for &(col, row) in piece.minoes.iter() {
+        squares_to_print[left + col][row] = true;
+    }
+
+    let mut tetromino_display = String::new();
+
+    for ii in 0..2 {
+        let i = 1 - ii;
+        for j in 0..4 {
+            let c = if squares_to_print[j][i] {
+                colors.paint("▣ ", piece_color)
+            } else { String::from("  ") };
+            tetromino_display.push_str(&c);
+        }
+
+        tetromino_display.push_str("\n");
+    }
+
+    tetromino_display
+}
+
+/// Describes the given event in a line of text.
+pub fn render_event(event: &GameEvent) -> String {
+    match *event {
+        GameEvent::LineClear { ref clear, score } =>
+            format!("{}: +{}", clear.labels().join(", "), score),
+        GameEvent::PerfectClear { score, .. } =>
+            format!("PERFECT CLEAR: +{}", score),
+    }
+}
@@ -0,0 +1,268 @@
+//! Running scripts of commands and assertions against a game without any interaction, for
+//! regression scenarios such as kicks and line clears.
+//!
+//! A script has one command per line. Blank lines and lines starting with `#` are ignored.
+//!
+//! - `l`, `r`, `rl`, `rr`, `hd` and `h` move left, move right, rotate left, rotate right, hard
+//!   drop and hold, as in the interactive mode.
+//! - `wait MS` advances time by the given number of milliseconds.
+//! - `assert-board` checks the matrix against the diagram on the following lines, up to the next
+//!   blank line, written as for `GameStateBuilder::board`. The diagram can also be given on the
+//!   same line, with rows separated by `/`. It describes the bottom rows of the matrix, and the
+//!   rows above it must be empty. The falling tetromino is only compared if the diagram contains
+//!   lowercase letters.
+//! - `assert-hold T` checks the held tetromino type, or that nothing is held with
+//!   `assert-hold none`.
+//! - `assert-lines N` checks the total number of lines cleared.
+
+use std::error;
+use std::fmt;
+
+use tetris;
+use tetris::game_state::TimedGameState;
+use tetris::placement::Input;
+
+/// What a line of a script does.
+#[derive(Debug, Clone, PartialEq)]
+enum Command {
+    Input(Input),
+    Wait(u32),
+    AssertBoard(String),
+    AssertHold(Option<tetris::TetrominoType>),
+    AssertLines(usize)
+}
+
+/// A command, with the number of the line it is on, starting at 1.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Step {
+    line: usize,
+    command: Command
+}
+
+/// Describes why a script could not be run, or failed.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ScriptError {
+    /// The line with the given number is not a valid command.
+    Syntax { line: usize, message: String },
+    /// The assertion on the line with the given number does not hold.
+    AssertionFailed { line: usize, message: String },
+    /// The line with the given number comes after the game ended.
+    GameEnded { line: usize },
+}
+
+impl fmt::Display for ScriptError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ScriptError::Syntax { line, ref message } => write!(f, "line {}: {}", line, message),
+            ScriptError::AssertionFailed { line, ref message } =>
+                write!(f, "line {}: assertion failed: {}", line, message),
+            ScriptError::GameEnded { line } => write!(f, "line {}: the game has already ended", line),
+        }
+    }
+}
+
+impl error::Error for ScriptError {}
+
+/// Parses a whole script into its steps.
+pub fn parse(script: &str) -> Result<Vec<Step>, ScriptError> {
+    let mut steps = Vec::new();
+    let mut lines = script.lines().enumerate().map(|(i, line)| (i + 1, line.trim()));
+
+    while let Some((line, text)) = lines.next() {
+        if text.is_empty() || text.starts_with('#') {
+            continue;
+        }
+
+        let syntax = |message: &str| ScriptError::Syntax { line, message: message.to_string() };
+        let mut words = text.splitn(2, char::is_whitespace);
+        let name = words.next().unwrap_or("");
+        let argument = words.next().map_or("", str::trim);
+
+        let command = match name {
+            "l" => Command::Input(Input::MoveLeft),
+            "r" => Command::Input(Input::MoveRight),
+            "rl" => Command::Input(Input::RotateLeft),
+            "rr" => Command::Input(Input::RotateRight),
+            "hd" => Command::Input(Input::HardDrop),
+            "h" => Command::Input(Input::Hold),
+            "wait" => Command::Wait(argument.parse().map_err(|_| syntax("wait needs a number of milliseconds"))?),
+            "assert-board" if !argument.is_empty() => Command::AssertBoard(argument.replace('/', "\n")),
+            "assert-board" => {
+                let rows: Vec<&str> = lines.by_ref().map(|(_, text)| text).take_while(|text| !text.is_empty()).collect();
+                if rows.is_empty() {
+                    return Err(syntax("assert-board needs a diagram"));
+                }
+                Command::AssertBoard(rows.join("\n"))
+            },
+            "assert-hold" if argument == "none" => Command::AssertHold(None),
+            "assert-hold" => {
+                let mut chars = argument.chars();
+                match (chars.next().and_then(tetris::TetrominoType::from_char), chars.next()) {
+                    (Some(tt), None) => Command::AssertHold(Some(tt)),
+                    _ => return Err(syntax("assert-hold needs a tetromino type or none")),
+                }
+            },
+            "assert-lines" => Command::AssertLines(argument.parse().map_err(|_| syntax("assert-lines needs a number of lines"))?),
+            _ => return Err(syntax(&format!("unknown command {}", name))),
+        };
+
+        steps.push(Step { line, command });
+    }
+
+    Ok(steps)
+}
+
+/// Runs the given steps on the game, stopping at the first assertion that fails.
+pub fn run(tgs: &mut TimedGameState, steps: &[Step]) -> Result<(), ScriptError> {
+    let mut ended = false;
+
+    for step in steps {
+        let line = step.line;
+        let failed = |message: String| ScriptError::AssertionFailed { line, message };
+
+        match step.command {
+            Command::Input(_) | Command::Wait(_) if ended => return Err(ScriptError::GameEnded { line }),
+            Command::Input(input) => ended = !tgs.apply_input(input),
+            Command::Wait(t) => ended = !tgs.advance_time(t),
+            Command::AssertBoard(ref diagram) => {
+                let expected = normalize_diagram(diagram);
+                // Only compare the falling tetromino if the diagram shows it.
+                let actual = if diagram.chars().any(char::is_lowercase) {
+                    tgs.game_state().to_ascii()
+                } else {
+                    tgs.game_state().matrix_to_ascii()
+                };
+                if actual != expected {
+                    return Err(failed(format!("expected board\n{}but found\n{}", expected, actual)));
+                }
+            },
+            Command::AssertHold(ref expected) => {
+                if tgs.held() != expected {
+                    let name = |held: &Option<tetris::TetrominoType>| {
+                        held.as_ref().map_or(String::from("none"), |tt| tt.to_char().to_string())
+                    };
+                    return Err(failed(format!("expected hold {}, but found {}", name(expected), name(tgs.held()))));
+                }
+            },
+            Command::AssertLines(expected) => {
+                let actual = tgs.game_state().lines_cleared();
+                if actual != expected {
+                    return Err(failed(format!("expected {} lines cleared, but found {}", expected, actual)));
+                }
+            },
+        }
+    }
+
+    Ok(())
+}
+
+/// Writes a diagram the way `GameState::to_ascii` does: one row per line, without surrounding
+/// whitespace or empty rows at the top.
+fn normalize_diagram(diagram: &str) -> String {
+    let mut rows: Vec<&str> = diagram.lines().map(str::trim).filter(|row| !row.is_empty()).collect();
+    while rows.len() > 1 && rows[0].chars().all(|c| c == '.') {
+        rows.remove(0);
+    }
+
+    rows.iter().map(|row| format!("{}\n", row)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use tetris::builder::GameStateBuilder;
+    use tetris::game_mode::GameMode;
+
+    fn commands(script: &str) -> Vec<(usize, Command)> {
+        parse(script).unwrap().into_iter().map(|step| (step.line, step.command)).collect()
+    }
+
+    fn game(diagram: &str, queue: &str) -> TimedGameState {
+        let gs = GameStateBuilder::new().board(diagram).queue(queue).build().unwrap();
+        TimedGameState::with_game_state(gs, GameMode::Endless)
+    }
+
+    #[test]
+    fn parses_each_command() {
+        assert_eq!(commands("l\nr\nrl\nrr\nhd\nh\nwait 250\nassert-hold T\nassert-hold none\nassert-lines 4"), vec![
+            (1, Command::Input(Input::MoveLeft)),
+            (2, Command::Input(Input::MoveRight)),
+            (3, Command::Input(Input::RotateLeft)),
+            (4, Command::Input(Input::RotateRight)),
+            (5, Command::Input(Input::HardDrop)),
+            (6, Command::Input(Input::Hold)),
+            (7, Command::Wait(250)),
+            (8, Command::AssertHold(Some(tetris::TetrominoType::T))),
+            (9, Command::AssertHold(None)),
+            (10, Command::AssertLines(4)),
+        ]);
+    }
+
+    #[test]
+    fn skips_comments_and_blank_lines() {
+        assert_eq!(commands("# move twice\n\n  l  \n# then drop\nhd\n"), vec![
+            (3, Command::Input(Input::MoveLeft)),
+            (5, Command::Input(Input::HardDrop)),
+        ]);
+    }
+
+    #[test]
+    fn parses_inline_and_multi_line_boards() {
+        assert_eq!(commands("assert-board ....t...../...ttt....\nhd"), vec![
+            (1, Command::AssertBoard(String::from("....t.....\n...ttt...."))),
+            (2, Command::Input(Input::HardDrop)),
+        ]);
+        assert_eq!(commands("assert-board\n  ....t.....\n  ...ttt....\n\nhd"), vec![
+            (1, Command::AssertBoard(String::from("....t.....\n...ttt...."))),
+            (5, Command::Input(Input::HardDrop)),
+        ]);
+    }
+
+    #[test]
+    fn reports_syntax_errors_with_their_line() {
+        let line_of = |script: &str| match parse(script) {
+            Err(ScriptError::Syntax { line, .. }) => line,
+            result => panic!("expected a syntax error, found {:?}", result),
+        };
+        assert_eq!(line_of("l\n\nwait soon"), 3);
+        assert_eq!(line_of("hd\njump"), 2);
+        assert_eq!(line_of("assert-board\n\nl"), 1);
+        assert_eq!(line_of("# hold\nassert-hold TI"), 2);
+        assert_eq!(line_of("assert-lines many"), 1);
+    }
+
+    #[test]
+    fn passes_when_the_assertions_hold() {
+        let mut tgs = game("GGGG.GGGGG\nGGGG.GGGGG\nGGGG.GGGGG\nGGGG.GGGGG", "IO");
+        let steps = parse("\
+            rr
+            l
+            hd
+            assert-lines 4
+            assert-board ..........
+            h
+            assert-hold O
+        ").unwrap();
+        assert_eq!(run(&mut tgs, &steps), Ok(()));
+    }
+
+    #[test]
+    fn stops_at_the_first_failed_assertion() {
+        let mut tgs = game("GGGG.GGGGG", "IO");
+        let steps = parse("hd\nassert-lines 1\nassert-lines 2").unwrap();
+        match run(&mut tgs, &steps) {
+            Err(ScriptError::AssertionFailed { line: 2, .. }) => {},
+            result => panic!("expected line 2 to fail, found {:?}", result),
+        }
+    }
+
+    #[test]
+    fn reports_inputs_after_the_game_ended() {
+        // The next tetromino cannot spawn once the I locks on top of the stack.
+        let stack: Vec<&str> = (0..20).map(|_| "G.GGGGGGGG").collect();
+        let mut tgs = game(&stack.join("\n"), "II");
+        let steps = parse("hd\nassert-lines 0\nl").unwrap();
+        assert_eq!(run(&mut tgs, &steps), Err(ScriptError::GameEnded { line: 3 }));
+    }
+}
@@ -0,0 +1,174 @@
+//! A real-time interface that puts the terminal in raw mode, reacts to keys as soon as they are
+//! pressed, and advances the game at a fixed rate, redrawing it in place.
+
+use std::io::{self, Write};
+use std::panic;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crossterm::cursor;
+use crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+use crossterm::style::Print;
+use crossterm::terminal::{self, Clear, ClearType, EnterAlternateScreen, LeaveAlternateScreen};
+
+use tetris::game_state::TimedGameState;
+use tetris::placement::Input;
+
+use super::color::ColorMode;
+use super::render;
+
+/// The time between two redraws of the game.
+const FRAME_INTERVAL: Duration = Duration::from_millis(16);
+
+const HELP: &str = "←/→ move   ↑/x rotate right   z rotate left   ↓ soft drop   space hard drop   \
+                    c hold   p pause   q quit";
+
+/// What a key does.
+enum Command {
+    Input(Input),
+    SoftDrop,
+    Pause,
+    Quit
+}
+
+fn key_to_command(key: &KeyEvent) -> Option<Command> {
+    if key.modifiers.contains(KeyModifiers::CONTROL) {
+        return match key.code {
+            KeyCode::Char('c') => Some(Command::Quit),
+            _ => None
+        };
+    }
+
+    match key.code {
+        KeyCode::Left => Some(Command::Input(Input::MoveLeft)),
+        KeyCode::Right => Some(Command::Input(Input::MoveRight)),
+        KeyCode::Up | KeyCode::Char('x') => Some(Command::Input(Input::RotateRight)),
+        KeyCode::Char('z') => Some(Command::Input(Input::RotateLeft)),
+        KeyCode::Down => Some(Command::SoftDrop),
+        KeyCode::Char(' ') => Some(Command::Input(Input::HardDrop)),
+        KeyCode::Char('c') => Some(Command::Input(Input::Hold)),
+        KeyCode::Char('p') | KeyCode::Esc => Some(Command::Pause),
+        KeyCode::Char('q') => Some(Command::Quit),
+        _ => None
+    }
+}
+
+/// Plays the game in the terminal until it ends or the player quits, restoring the terminal
+/// afterwards.
+pub fn run(tgs: &mut TimedGameState, colors: ColorMode) -> io::Result<()> {
+    let mut stdout = io::stdout();
+    let _guard = TerminalGuard::new(&mut stdout)?;
+    game_loop(tgs, colors, &mut stdout)
+}
+
+/// Keeps the terminal in raw mode and on the alternate screen while it is alive, and restores it
+/// when it is dropped, even when the game panics.
+struct TerminalGuard;
+
+impl TerminalGuard {
+    fn new(stdout: &mut io::Stdout) -> io::Result<Self> {
+        // The panic message would be lost on the alternate screen, so the terminal is restored
+        // before it is printed, and not only when the guard is dropped while unwinding.
+        let default_hook = panic::take_hook();
+        panic::set_hook(Box::new(move |info| {
+            restore_terminal();
+            default_hook(info);
+        }));
+
+        // Restores the terminal if entering the alternate screen fails after raw mode is on.
+        let guard = TerminalGuard;
+        terminal::enable_raw_mode()?;
+        execute!(stdout, EnterAlternateScreen, cursor::Hide)?;
+        Ok(guard)
+    }
+}
+
+impl Drop for TerminalGuard {
+    fn drop(&mut self) {
+        restore_terminal();
+        // Puts back the default panic hook. The hook cannot be changed while unwinding, and then
+        // the process is about to end anyway.
+        if !thread::panicking() {
+            drop(panic::take_hook());
+        }
+    }
+}
+
+/// Leaves the alternate screen and raw mode. Every step is tried, even when an earlier one fails.
+fn restore_terminal() {
+    let _ = execute!(io::stdout(), cursor::Show, LeaveAlternateScreen);
+    let _ = terminal::disable_raw_mode();
+}
+
+fn game_loop(tgs: &mut TimedGameState, colors: ColorMode, stdout: &mut io::Stdout) -> io::Result<()> {
+    let mut paused = false;
+    let mut message = String::new();
+    let mut last_advance = Instant::now();
+
+    loop {
+        for event in tgs.take_events() {
+            message = render::render_event(&event);
+        }
+        draw(stdout, tgs, colors, paused, &message)?;
+
+        // React to keys until the next frame is due.
+        let next_frame = Instant::now() + FRAME_INTERVAL;
+        loop {
+            let now = Instant::now();
+            if now >= next_frame || !event::poll(next_frame - now)? {
+                break;
+            }
+
+            let key = match event::read()? {
+                Event::Key(key) if key.kind != KeyEventKind::Release => key,
+                _ => continue
+            };
+
+            let continue_game = match key_to_command(&key) {
+                Some(Command::Quit) => return Ok(()),
+                Some(Command::Pause) => {
+                    paused = !paused;
+                    true
+                },
+                Some(_) if paused => true,
+                Some(Command::SoftDrop) => {
+                    tgs.soft_drop();
+                    true
+                },
+                Some(Command::Input(input)) => tgs.apply_input(input),
+                None => true
+            };
+
+            if !continue_game {
+                return Ok(());
+            }
+        }
+
+        // Only advance by whole milliseconds, carrying the rest over to the next frame.
+        if paused {
+            last_advance = Instant::now();
+        } else {
+            let elapsed = last_advance.elapsed();
+            let millis = elapsed.as_secs() as u32 * 1000 + elapsed.subsec_millis();
+            last_advance += Duration::from_millis(u64::from(millis));
+            if !tgs.advance_time(millis) {
+                return Ok(());
+            }
+        }
+    }
+}
+
+fn draw(stdout: &mut io::Stdout, tgs: &TimedGameState, colors: ColorMode, paused: bool, message: &str)
+        -> io::Result<()> {
+    let table = render::render_timed_game_state(tgs, colors).to_string();
+    let status = if paused { "PAUSED" } else { message };
+
+    queue!(stdout, cursor::MoveTo(0, 0))?;
+    // Raw mode does not return to the start of the line on a line feed, and lines left over from
+    // the previous frame have to be cleared.
+    for line in table.lines().chain(vec![status, HELP]) {
+        queue!(stdout, Print(line), Clear(ClearType::UntilNewLine), Print("\r\n"))?;
+    }
+    queue!(stdout, Clear(ClearType::FromCursorDown))?;
+    stdout.flush()
+}
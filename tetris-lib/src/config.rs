@@ -0,0 +1,174 @@
+//! Settings of a game that are chosen before it starts and never change, such as the size of the
+//! matrix and how tetrominoes are randomized.
+
+use ::game_state::{MATRIX_HEIGHT, MATRIX_WIDTH, NEXT_PREVIEW_LENGTH};
+
+/// The smallest matrix width a game can be played with, which fits every tetromino in spawn
+/// position.
+pub const MIN_MATRIX_WIDTH: usize = 4;
+/// The smallest matrix height a game can be played with, which leaves room above the spawn
+/// position for every tetromino to rotate.
+pub const MIN_MATRIX_HEIGHT: usize = 4;
+
+/// Decides the order in which tetromino types come.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Randomizer {
+    /// Every 7 tetrominoes, starting with the first, contain one of each type in random order.
+    SevenBag,
+    /// Every tetromino is of a random type, regardless of the ones before it.
+    Random
+}
+
+/// Decides how tetrominoes move when they are rotated.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RotationSystem {
+    /// The Super Rotation System, which tries moving a tetromino to each of the positions in its
+    /// kick table when it cannot rotate in place.
+    Srs,
+    /// The rotations of the Super Rotation System, without any kicks: a tetromino that cannot
+    /// rotate in place does not rotate.
+    NoKicks
+}
+
+impl RotationSystem {
+
+    /// Returns the number of entries of the SRS kick tables to try, the first being a rotation in
+    /// place.
+    pub fn kick_count(self) -> usize {
+        match self {
+            RotationSystem::Srs => 5,
+            RotationSystem::NoKicks => 1,
+        }
+    }
+}
+
+/// The settings of a game.
+#[derive(Debug, Clone, PartialEq)]
+pub struct GameConfig {
+    /// The number of columns of the matrix.
+    pub matrix_width: usize,
+    /// The number of rows of the matrix, both visible and hidden.
+    pub matrix_height: usize,
+    /// The number of tetrominoes that the player can see in advance.
+    pub preview_length: usize,
+    /// Decides the order in which tetromino types come.
+    pub randomizer: Randomizer,
+    /// Decides how tetrominoes move when they are rotated.
+    pub rotation_system: RotationSystem,
+    /// Makes the tetrominoes always come in the same order, or come in a different order every
+    /// game if `None`.
+    pub seed: Option<u64>
+}
+
+impl Default for GameConfig {
+    fn default() -> Self {
+        GameConfig {
+            matrix_width: MATRIX_WIDTH,
+            matrix_height: MATRIX_HEIGHT,
+            preview_length: NEXT_PREVIEW_LENGTH,
+            randomizer: Randomizer::SevenBag,
+            rotation_system: RotationSystem::Srs,
+            seed: None
+        }
+    }
+}
+
+impl GameConfig {
+
+    /// Returns the position of the center of a tetromino in spawn position: near the middle of
+    /// the matrix, two rows from the top.
+    pub fn spawn_center(&self) -> (isize, isize) {
+        ((self.matrix_width as isize - 1) / 2, self.matrix_height as isize - 2)
+    }
+
+    /// Returns the number of rows of the matrix that are shown to the player. The two rows at the
+    /// top, where tetrominoes spawn, are hidden.
+    pub fn visible_height(&self) -> usize {
+        self.matrix_height - 2
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use ::bag::Bag;
+    use ::builder::GameStateBuilder;
+    use ::game_state::{GameState, Tetromino};
+
+    fn config_with(randomizer: Randomizer, seed: u64) -> GameConfig {
+        GameConfig { randomizer, seed: Some(seed), ..GameConfig::default() }
+    }
+
+    fn draw(config: &GameConfig, count: usize) -> Vec<::TetrominoType> {
+        let mut bag = Bag::with_config(config);
+        (0..count).map(|_| bag.draw()).collect()
+    }
+
+    /// Returns `true` if every 7 tetromino types, starting with the first, are one of each type.
+    fn comes_in_sets_of_seven(types: &[::TetrominoType]) -> bool {
+        types.chunks(7).all(|set| {
+            let mut set = set.to_vec();
+            set.sort_by_key(|tt| tt.to_char());
+            set.dedup();
+            set.len() == 7
+        })
+    }
+
+    #[test]
+    fn the_same_seed_gives_the_same_queue() {
+        let config = config_with(Randomizer::SevenBag, 42);
+        assert_eq!(draw(&config, 70), draw(&config, 70));
+        assert_ne!(draw(&config, 70), draw(&config_with(Randomizer::SevenBag, 43), 70));
+
+        let first = GameState::with_config(config.clone());
+        let second = GameState::with_config(config);
+        assert_eq!(first.falling_tetromino(), second.falling_tetromino());
+        assert_eq!(first.next_preview(), second.next_preview());
+    }
+
+    #[test]
+    fn only_the_seven_bag_comes_in_sets_of_seven() {
+        for seed in 0..10 {
+            assert!(comes_in_sets_of_seven(&draw(&config_with(Randomizer::SevenBag, seed), 70)));
+        }
+        assert!(!comes_in_sets_of_seven(&draw(&config_with(Randomizer::Random, 0), 70)));
+    }
+
+    #[test]
+    fn no_kicks_refuses_a_rotation_that_srs_kicks() {
+        let board = "\
+            i.........
+            i.........
+            i.........
+            i.........";
+        let mut srs = GameStateBuilder::new().board(board).build().unwrap();
+        assert!(srs.rotate_right());
+
+        let config = GameConfig { rotation_system: RotationSystem::NoKicks, ..GameConfig::default() };
+        let mut no_kicks = GameStateBuilder::new().board(board).config(config).build().unwrap();
+        let before = no_kicks.falling_tetromino().clone();
+        assert!(!no_kicks.rotate_right());
+        assert_eq!(no_kicks.falling_tetromino(), &before);
+    }
+
+    #[test]
+    fn spawns_in_bounds_of_any_matrix_size() {
+        let all_types = [
+            ::TetrominoType::I, ::TetrominoType::O, ::TetrominoType::T, ::TetrominoType::J,
+            ::TetrominoType::L, ::TetrominoType::S, ::TetrominoType::Z
+        ];
+        for &(matrix_width, matrix_height) in &[(MIN_MATRIX_WIDTH, MIN_MATRIX_HEIGHT), (5, 6), (7, 30), (16, 40)] {
+            let config = GameConfig { matrix_width, matrix_height, ..GameConfig::default() };
+            for tt in all_types.iter() {
+                let tetromino = Tetromino::spawned(tt.clone(), &config);
+                assert!(tetromino.minoes().iter().all(|&(col, row)| {
+                    col >= 0 && (col as usize) < matrix_width && row >= 0 && (row as usize) < matrix_height
+                }), "{:?} spawns out of a {}x{} matrix", tt, matrix_width, matrix_height);
+            }
+
+            let gs = GameState::with_config(config);
+            assert_eq!((gs.matrix_width(), gs.matrix_height()), (matrix_width, matrix_height));
+        }
+    }
+}
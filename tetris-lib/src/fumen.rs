@@ -0,0 +1,739 @@
+//! Encoding and decoding of fumen (version 115) strings, the de facto standard for sharing tetris
+//! board setups.
+//!
+//! A fumen is a sequence of pages. Each page has a field 10 squares wide and 23 squares tall,
+//! a row of garbage below the field that can rise into it, an optional tetromino, and a comment.
+//! The field of each page follows from the one before: the tetromino of the page is locked and
+//! lines are cleared, unless the page says otherwise.
+
+use std::char;
+use std::error;
+use std::fmt;
+
+use ::builder;
+use ::game_state::{GameState, Tetromino};
+
+/// Width of the field of a fumen page.
+pub const FIELD_WIDTH: usize = 10;
+/// Height of the field of a fumen page, not counting the garbage row below it.
+pub const FIELD_HEIGHT: usize = 23;
+
+/// Number of squares in a fumen field, including the garbage row.
+const FIELD_BLOCKS: usize = FIELD_WIDTH * (FIELD_HEIGHT + 1);
+
+/// The characters fumen data is written with, each standing for a 6-bit value.
+const ENCODE_TABLE: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+/// The characters comments are written with, once escaped.
+const COMMENT_TABLE: &[u8] =
+    b" !\"#$%&'()*+,-./0123456789:;<=>?@ABCDEFGHIJKLMNOPQRSTUVWXYZ[\\]^_`abcdefghijklmnopqrstuvwxyz{|}~";
+
+/// Number of different values a character of a comment is encoded as.
+const COMMENT_CHAR_VALUES: u32 = COMMENT_TABLE.len() as u32 + 1;
+
+/// The longest comment that can be encoded, once escaped.
+const MAX_COMMENT_LENGTH: usize = 4095;
+
+/// Describes why a fumen string could not be decoded.
+#[derive(Debug, Clone, PartialEq)]
+pub enum FumenError {
+    /// The string does not start with a version 115 header, such as `v115@`.
+    UnsupportedVersion,
+    /// The data contains a character that fumen data is never written with.
+    InvalidCharacter(char),
+    /// The data ended in the middle of a page.
+    UnexpectedEnd,
+    /// A page describes something that cannot be on a fumen field, such as an unknown kind of
+    /// square.
+    InvalidData,
+}
+
+impl fmt::Display for FumenError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            FumenError::UnsupportedVersion => write!(f, "not a version 115 fumen"),
+            FumenError::InvalidCharacter(c) => write!(f, "invalid character '{}' in fumen", c),
+            FumenError::UnexpectedEnd => write!(f, "fumen ended unexpectedly"),
+            FumenError::InvalidData => write!(f, "fumen contains invalid data"),
+        }
+    }
+}
+
+impl error::Error for FumenError {}
+
+/// A single page of a fumen.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Page {
+    /// The squares of the field, `FIELD_WIDTH` columns of `FIELD_HEIGHT` rows each, indexed like
+    /// `GameState::placed_squares`.
+    pub field: Vec<Vec<::Cell>>,
+    /// The row of garbage below the field, from left to right.
+    pub garbage_row: Vec<::Cell>,
+    /// The tetromino shown on the page, if any.
+    pub tetromino: Option<Tetromino>,
+    /// The comment of the page.
+    pub comment: String,
+    /// Whether the tetromino is locked and lines are cleared before the next page.
+    pub lock: bool,
+    /// Whether the garbage row rises into the field before the next page.
+    pub rise: bool,
+    /// Whether the field is mirrored before the next page.
+    pub mirror: bool
+}
+
+impl Page {
+
+    /// Creates a page with an empty field and no tetromino.
+    pub fn new() -> Self {
+        Page {
+            field: vec![vec![::Cell::Empty; FIELD_HEIGHT]; FIELD_WIDTH],
+            garbage_row: vec![::Cell::Empty; FIELD_WIDTH],
+            tetromino: None,
+            comment: String::new(),
+            lock: true,
+            rise: false,
+            mirror: false
+        }
+    }
+
+    /// Creates a page showing the matrix and the falling tetromino of the given `GameState`.
+    pub fn from_game_state(gs: &GameState) -> Self {
+        let mut page = Page::new();
+        for (col, column) in gs.placed_squares().iter().enumerate().take(FIELD_WIDTH) {
+            for (row, square) in column.iter().enumerate().take(FIELD_HEIGHT) {
+                page.field[col][row] = square.clone();
+            }
+        }
+        page.tetromino = Some(gs.falling_tetromino().clone());
+        page
+    }
+
+    /// Returns an ASCII diagram of the field and the tetromino of this page, in the format read
+    /// by `GameStateBuilder::board`.
+    pub fn to_ascii(&self) -> String {
+        let mut rows: Vec<Vec<char>> = (0..FIELD_HEIGHT).map(|row| {
+            (0..FIELD_WIDTH).map(|col| match self.field[col][row] {
+                ::Cell::Empty => '.',
+                ::Cell::Piece(ref tt) | ::Cell::Invisible(ref tt) => tt.to_char(),
+                ::Cell::Garbage | ::Cell::Item => 'G',
+            }).collect()
+        }).collect();
+
+        if let Some(ref tetromino) = self.tetromino {
+            let c = tetromino.ttype().to_char().to_ascii_lowercase();
+            for (col, row) in tetromino.minoes() {
+                if col >= 0 && (col as usize) < FIELD_WIDTH && row >= 0 && (row as usize) < FIELD_HEIGHT {
+                    rows[row as usize][col as usize] = c;
+                }
+            }
+        }
+
+        builder::write_diagram(rows)
+    }
+}
+
+impl Default for Page {
+    fn default() -> Self {
+        Page::new()
+    }
+}
+
+/// Decodes a fumen string into its pages. The string may be a whole fumen URL.
+pub fn decode(fumen: &str) -> Result<Vec<Page>, FumenError> {
+
+    let data = match fumen.find("115@") {
+        Some(index) => &fumen[index + 4..],
+        None => return Err(FumenError::UnsupportedVersion),
+    };
+
+    let mut values = Values::decode(data)?;
+
+    let mut pages = Vec::new();
+    let mut prev_field = vec![0u8; FIELD_BLOCKS];
+    let mut prev_comment = String::new();
+    let mut repeat_count = 0;
+
+    while !values.is_empty() {
+
+        let mut field = prev_field.clone();
+
+        if repeat_count > 0 {
+            repeat_count -= 1;
+        } else {
+            let mut index = 0;
+            let mut changed = true;
+            while index < FIELD_BLOCKS {
+                let value = values.poll(2)? as usize;
+                let diff = value / FIELD_BLOCKS;
+                let count = value % FIELD_BLOCKS + 1;
+                if diff == 8 && count == FIELD_BLOCKS {
+                    changed = false;
+                }
+                if index + count > FIELD_BLOCKS {
+                    return Err(FumenError::InvalidData);
+                }
+                for block in &mut field[index..index + count] {
+                    let new_block = *block as isize + diff as isize - 8;
+                    if !(0..=8).contains(&new_block) {
+                        return Err(FumenError::InvalidData);
+                    }
+                    *block = new_block as u8;
+                }
+                index += count;
+            }
+            if !changed {
+                repeat_count = values.poll(1)?;
+            }
+        }
+
+        let mut action = values.poll(3)?;
+        let piece = action % 8;
+        action /= 8;
+        let rotation = action % 4;
+        action /= 4;
+        let position = (action as usize) % FIELD_BLOCKS;
+        action /= FIELD_BLOCKS as u32;
+        let rise = action % 2 == 1;
+        action /= 2;
+        let mirror = action % 2 == 1;
+        action /= 2;
+        // The next flag marks the first page as colored, which every page of this engine is.
+        action /= 2;
+        let has_comment = action % 2 == 1;
+        action /= 2;
+        let lock = action % 2 == 0;
+
+        let comment = if has_comment {
+            let length = values.poll(2)? as usize;
+            let mut escaped = String::new();
+            for _ in 0..length.div_ceil(4) {
+                let mut value = values.poll(5)?;
+                for _ in 0..4 {
+                    let index = (value % COMMENT_CHAR_VALUES) as usize;
+                    value /= COMMENT_CHAR_VALUES;
+                    escaped.push(*COMMENT_TABLE.get(index).unwrap_or(&b' ') as char);
+                }
+            }
+            escaped.truncate(length);
+            unescape(&escaped)
+        } else {
+            prev_comment.clone()
+        };
+
+        let tetromino = match code_to_tetromino_type(piece) {
+            Some(ttype) => {
+                let (center, orientation) = decode_position(&ttype, rotation, position);
+                Some(Tetromino::with_position(ttype, center, orientation))
+            },
+            None => None,
+        };
+
+        let page = Page {
+            field: (0..FIELD_WIDTH).map(|col| {
+                (0..FIELD_HEIGHT).map(|row| code_to_cell(field[block_index(col, row as isize)])).collect()
+            }).collect(),
+            garbage_row: (0..FIELD_WIDTH).map(|col| code_to_cell(field[block_index(col, -1)])).collect(),
+            tetromino,
+            comment: comment.clone(),
+            lock,
+            rise,
+            mirror
+        };
+
+        prev_field = next_field(&field, &page);
+        prev_comment = comment;
+        pages.push(page);
+    }
+
+    Ok(pages)
+}
+
+/// Encodes the given pages into a fumen string.
+pub fn encode(pages: &[Page]) -> String {
+
+    let mut values = Values::new();
+    let mut prev_field = vec![0u8; FIELD_BLOCKS];
+    let mut prev_comment = String::new();
+    // The index of the value counting how many following pages repeat the last field, if the
+    // last page repeated the one before it.
+    let mut repeat_index: Option<usize> = None;
+
+    for (i, page) in pages.iter().enumerate() {
+
+        let mut field = vec![0u8; FIELD_BLOCKS];
+        for col in 0..FIELD_WIDTH {
+            for row in 0..FIELD_HEIGHT {
+                field[block_index(col, row as isize)] = cell_to_code(&page.field[col][row]);
+            }
+            field[block_index(col, -1)] = cell_to_code(&page.garbage_row[col]);
+        }
+
+        // Write the field as runs of squares with the same difference from the previous field.
+        let mut field_values = Values::new();
+        let mut run_diff = field[0] as usize + 8 - prev_field[0] as usize;
+        let mut run_length = 0;
+        for index in 1..FIELD_BLOCKS {
+            let diff = field[index] as usize + 8 - prev_field[index] as usize;
+            if diff == run_diff {
+                run_length += 1;
+            } else {
+                field_values.push((run_diff * FIELD_BLOCKS + run_length) as u32, 2);
+                run_diff = diff;
+                run_length = 0;
+            }
+        }
+        field_values.push((run_diff * FIELD_BLOCKS + run_length) as u32, 2);
+        let changed = !(run_diff == 8 && run_length == FIELD_BLOCKS - 1);
+
+        match repeat_index {
+            Some(index) if !changed && values.values[index] < 63 => {
+                values.values[index] += 1;
+            },
+            _ => {
+                values.append(field_values);
+                if changed {
+                    repeat_index = None;
+                } else {
+                    values.push(0, 1);
+                    repeat_index = Some(values.values.len() - 1);
+                }
+            },
+        }
+
+        let has_comment = page.comment != prev_comment;
+
+        let (piece, rotation, position) = match page.tetromino {
+            Some(ref tetromino) => encode_position(tetromino),
+            None => (0, 0, block_index(0, 22)),
+        };
+
+        let mut action = if page.lock { 0 } else { 1 };
+        action = action * 2 + if has_comment { 1 } else { 0 };
+        action = action * 2 + if i == 0 { 1 } else { 0 };
+        action = action * 2 + if page.mirror { 1 } else { 0 };
+        action = action * 2 + if page.rise { 1 } else { 0 };
+        action = action * FIELD_BLOCKS as u32 + position as u32;
+        action = action * 4 + rotation;
+        action = action * 8 + piece;
+        values.push(action, 3);
+
+        if has_comment {
+            let escaped: Vec<u8> = escape(&page.comment).bytes().take(MAX_COMMENT_LENGTH).collect();
+            values.push(escaped.len() as u32, 2);
+            for chunk in escaped.chunks(4) {
+                let mut value = 0;
+                for (count, c) in chunk.iter().enumerate() {
+                    let index = COMMENT_TABLE.iter().position(|t| t == c).unwrap_or(0) as u32;
+                    value += index * COMMENT_CHAR_VALUES.pow(count as u32);
+                }
+                values.push(value, 5);
+            }
+        }
+
+        prev_field = next_field(&field, page);
+        prev_comment = page.comment.clone();
+    }
+
+    let data = values.encode();
+
+    // Long fumens are broken up with question marks, like the fumen editor does.
+    let mut result = String::from("v115@");
+    if data.len() <= 42 {
+        result.push_str(&data);
+    } else {
+        result.push_str(&data[..42]);
+        for chunk in data.as_bytes()[42..].chunks(47) {
+            result.push('?');
+            result.push_str(&String::from_utf8_lossy(chunk));
+        }
+    }
+    result
+}
+
+/// A sequence of 6-bit values, which is what fumen data is made of.
+struct Values {
+    values: Vec<u32>,
+    /// The index of the next value to be read.
+    next: usize
+}
+
+impl Values {
+
+    fn new() -> Self {
+        Values { values: Vec::new(), next: 0 }
+    }
+
+    /// Reads the values from their characters, skipping any question marks.
+    fn decode(data: &str) -> Result<Self, FumenError> {
+        let values = data.chars()
+            .filter(|&c| c != '?')
+            .map(|c| {
+                ENCODE_TABLE.iter().position(|&t| t as char == c)
+                    .map(|value| value as u32)
+                    .ok_or(FumenError::InvalidCharacter(c))
+            })
+            .collect::<Result<Vec<u32>, FumenError>>()?;
+        Ok(Values { values, next: 0 })
+    }
+
+    fn encode(&self) -> String {
+        self.values.iter().map(|&value| ENCODE_TABLE[value as usize] as char).collect()
+    }
+
+    fn is_empty(&self) -> bool {
+        self.next >= self.values.len()
+    }
+
+    /// Reads a number written with `count` values, least significant first.
+    fn poll(&mut self, count: usize) -> Result<u32, FumenError> {
+        let mut number = 0;
+        for i in 0..count {
+            let value = *self.values.get(self.next).ok_or(FumenError::UnexpectedEnd)?;
+            number += value << (6 * i);
+            self.next += 1;
+        }
+        Ok(number)
+    }
+
+    /// Writes a number with `count` values, least significant first.
+    fn push(&mut self, number: u32, count: usize) {
+        let mut number = number;
+        for _ in 0..count {
+            self.values.push(number % 64);
+            number /= 64;
+        }
+    }
+
+    fn append(&mut self, other: Values) {
+        self.values.extend(other.values);
+    }
+}
+
+/// Returns the index in a fumen field of the square at the given column and row, where row -1 is
+/// the garbage row.
+fn block_index(col: usize, row: isize) -> usize {
+    (FIELD_HEIGHT as isize - 1 - row) as usize * FIELD_WIDTH + col
+}
+
+/// Returns the field of the page after the given one, given the field of the page in fumen
+/// order.
+fn next_field(field: &[u8], page: &Page) -> Vec<u8> {
+    let mut field = field.to_vec();
+
+    if !page.lock {
+        return field;
+    }
+
+    if let Some(ref tetromino) = page.tetromino {
+        let code = tetromino_type_to_code(tetromino.ttype());
+        for (col, row) in tetromino.minoes() {
+            if col >= 0 && (col as usize) < FIELD_WIDTH && row >= 0 && (row as usize) < FIELD_HEIGHT {
+                field[block_index(col as usize, row)] = code;
+            }
+        }
+    }
+
+    // Clear full lines, moving the rows above them down.
+    let mut rows: Vec<Vec<u8>> = (0..FIELD_HEIGHT).map(|row| {
+        (0..FIELD_WIDTH).map(|col| field[block_index(col, row as isize)]).collect()
+    }).collect();
+    rows.retain(|row| row.contains(&0));
+    rows.resize(FIELD_HEIGHT, vec![0; FIELD_WIDTH]);
+
+    let mut garbage_row: Vec<u8> = (0..FIELD_WIDTH).map(|col| field[block_index(col, -1)]).collect();
+
+    if page.rise {
+        rows.insert(0, garbage_row);
+        rows.truncate(FIELD_HEIGHT);
+        garbage_row = vec![0; FIELD_WIDTH];
+    }
+
+    if page.mirror {
+        for row in rows.iter_mut() {
+            row.reverse();
+        }
+    }
+
+    for (row, squares) in rows.iter().enumerate() {
+        for (col, &code) in squares.iter().enumerate() {
+            field[block_index(col, row as isize)] = code;
+        }
+    }
+    for (col, &code) in garbage_row.iter().enumerate() {
+        field[block_index(col, -1)] = code;
+    }
+
+    field
+}
+
+/// Returns the fumen codes of the type, rotation and position of the given tetromino.
+///
+/// Fumen writes the O, I, S and Z tetrominoes in the same place whichever way round their squares
+/// are, so in some orientations the position is moved from the SRS center to where fumen expects
+/// it.
+fn encode_position(tetromino: &Tetromino) -> (u32, u32, usize) {
+    let ttype = tetromino.ttype();
+    let orientation = tetromino.orientation();
+    let (mut col, mut row) = tetromino.center();
+
+    match (ttype, orientation) {
+        (&::TetrominoType::O, 3) => { col -= 1; row += 1; },
+        (&::TetrominoType::O, 2) => { col -= 1; },
+        (&::TetrominoType::O, 0) => { row += 1; },
+        (&::TetrominoType::I, 2) => { col -= 1; },
+        (&::TetrominoType::I, 3) => { row += 1; },
+        (&::TetrominoType::S, 0) => { row += 1; },
+        (&::TetrominoType::S, 1) => { col += 1; },
+        (&::TetrominoType::Z, 0) => { row += 1; },
+        (&::TetrominoType::Z, 3) => { col -= 1; },
+        _ => {},
+    }
+
+    let col = col.max(0).min(FIELD_WIDTH as isize - 1) as usize;
+    let row = row.max(-1).min(FIELD_HEIGHT as isize - 1);
+
+    (tetromino_type_to_code(ttype) as u32, orientation_to_rotation(orientation), block_index(col, row))
+}
+
+/// Returns the center and orientation of a tetromino from its fumen codes. This undoes
+/// `encode_position`.
+fn decode_position(ttype: &::TetrominoType, rotation: u32, position: usize) -> ((isize, isize), u32) {
+    let orientation = rotation_to_orientation(rotation);
+    let mut col = (position % FIELD_WIDTH) as isize;
+    let mut row = FIELD_HEIGHT as isize - 1 - (position / FIELD_WIDTH) as isize;
+
+    match (ttype, orientation) {
+        (&::TetrominoType::O, 3) => { col += 1; row -= 1; },
+        (&::TetrominoType::O, 2) => { col += 1; },
+        (&::TetrominoType::O, 0) => { row -= 1; },
+        (&::TetrominoType::I, 2) => { col += 1; },
+        (&::TetrominoType::I, 3) => { row -= 1; },
+        (&::TetrominoType::S, 0) => { row -= 1; },
+        (&::TetrominoType::S, 1) => { col -= 1; },
+        (&::TetrominoType::Z, 0) => { row -= 1; },
+        (&::TetrominoType::Z, 3) => { col += 1; },
+        _ => {},
+    }
+
+    ((col, row), orientation)
+}
+
+/// Fumen numbers rotations 0 for upside down, 1 for right, 2 for spawn and 3 for left, while
+/// orientations go 0 for spawn, 1 for right, 2 for upside down and 3 for left.
+fn orientation_to_rotation(orientation: u32) -> u32 {
+    (6 - orientation) % 4
+}
+
+/// Undoes `orientation_to_rotation`, which is its own inverse.
+fn rotation_to_orientation(rotation: u32) -> u32 {
+    (6 - rotation) % 4
+}
+
+fn tetromino_type_to_code(tt: &::TetrominoType) -> u8 {
+    match *tt {
+        ::TetrominoType::I => 1,
+        ::TetrominoType::L => 2,
+        ::TetrominoType::O => 3,
+        ::TetrominoType::Z => 4,
+        ::TetrominoType::T => 5,
+        ::TetrominoType::J => 6,
+        ::TetrominoType::S => 7,
+    }
+}
+
+fn code_to_tetromino_type(code: u32) -> Option<::TetrominoType> {
+    match code {
+        1 => Some(::TetrominoType::I),
+        2 => Some(::TetrominoType::L),
+        3 => Some(::TetrominoType::O),
+        4 => Some(::TetrominoType::Z),
+        5 => Some(::TetrominoType::T),
+        6 => Some(::TetrominoType::J),
+        7 => Some(::TetrominoType::S),
+        _ => None,
+    }
+}
+
+fn cell_to_code(cell: &::Cell) -> u8 {
+    match *cell {
+        ::Cell::Empty => 0,
+        ::Cell::Piece(ref tt) | ::Cell::Invisible(ref tt) => tetromino_type_to_code(tt),
+        ::Cell::Garbage | ::Cell::Item => 8,
+    }
+}
+
+fn code_to_cell(code: u8) -> ::Cell {
+    match code_to_tetromino_type(code as u32) {
+        Some(tt) => ::Cell::Piece(tt),
+        None if code == 0 => ::Cell::Empty,
+        None => ::Cell::Garbage,
+    }
+}
+
+/// Escapes a comment the way JavaScript's `escape` function does, which is how fumen stores
+/// comments.
+fn escape(s: &str) -> String {
+    let mut escaped = String::new();
+    for c in s.chars() {
+        if c.is_ascii_alphanumeric() || "@*_+-./".contains(c) {
+            escaped.push(c);
+        } else {
+            let mut units = [0u16; 2];
+            for unit in c.encode_utf16(&mut units) {
+                if *unit < 0x100 {
+                    escaped.push_str(&format!("%{:02X}", unit));
+                } else {
+                    escaped.push_str(&format!("%u{:04X}", unit));
+                }
+            }
+        }
+    }
+    escaped
+}
+
+/// Undoes `escape`.
+fn unescape(s: &str) -> String {
+    let chars: Vec<char> = s.chars().collect();
+    let mut units: Vec<u16> = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        let hex = |from: usize, len: usize| -> Option<u16> {
+            if from + len > chars.len() {
+                return None;
+            }
+            let digits: String = chars[from..from + len].iter().collect();
+            u16::from_str_radix(&digits, 16).ok()
+        };
+        if chars[i] == '%' {
+            if chars.get(i + 1) == Some(&'u') {
+                if let Some(unit) = hex(i + 2, 4) {
+                    units.push(unit);
+                    i += 6;
+                    continue;
+                }
+            } else if let Some(unit) = hex(i + 1, 2) {
+                units.push(unit);
+                i += 3;
+                continue;
+            }
+        }
+        let mut buffer = [0u16; 2];
+        units.extend_from_slice(chars[i].encode_utf16(&mut buffer));
+        i += 1;
+    }
+    char::decode_utf16(units).map(|c| c.unwrap_or(char::REPLACEMENT_CHARACTER)).collect()
+}
+
+impl GameState {
+
+    /// Returns the fumen string of a single page showing the matrix and the falling tetromino.
+    pub fn to_fumen(&self) -> String {
+        encode(&[Page::from_game_state(self)])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn blank_pages(count: usize, comment: &str) -> Vec<Page> {
+        (0..count).map(|_| Page { comment: String::from(comment), ..Page::new() }).collect()
+    }
+
+    #[test]
+    fn decodes_empty_field() {
+        let pages = decode("v115@vhAAgH").unwrap();
+        assert_eq!(pages, vec![Page::new()]);
+    }
+
+    #[test]
+    fn decodes_vertical_t() {
+        // A T pointing right against the left wall, written with fumen rotation 1 (right) at the
+        // square of its center.
+        let pages = decode("v115@vhANJJ").unwrap();
+        let tetromino = pages[0].tetromino.as_ref().unwrap();
+        assert_eq!(tetromino.ttype(), &::TetrominoType::T);
+        assert_eq!(tetromino.orientation(), 1);
+        assert_eq!(tetromino.sorted_minoes(), vec![(0, 0), (0, 1), (0, 2), (1, 1)]);
+    }
+
+    #[test]
+    fn decodes_vertical_i() {
+        // An I standing against the right wall, written with fumen rotation 3 (left), which fumen
+        // places by its second square from the bottom.
+        let pages = decode("v115@vhA5IJ").unwrap();
+        let tetromino = pages[0].tetromino.as_ref().unwrap();
+        assert_eq!(tetromino.ttype(), &::TetrominoType::I);
+        assert_eq!(tetromino.orientation(), 3);
+        assert_eq!(tetromino.sorted_minoes(), vec![(9, 0), (9, 1), (9, 2), (9, 3)]);
+    }
+
+    #[test]
+    fn encodes_vertical_pieces_as_the_editor_does() {
+        let t = Tetromino::with_position(::TetrominoType::T, (0, 1), 1);
+        let i = Tetromino::with_position(::TetrominoType::I, (9, 1), 3);
+        assert_eq!(encode(&[Page { tetromino: Some(t), ..Page::new() }]), "v115@vhANJJ");
+        assert_eq!(encode(&[Page { tetromino: Some(i), ..Page::new() }]), "v115@vhA5IJ");
+    }
+
+    /// Returns a fumen of an empty field with a tetromino written with the given fumen codes.
+    fn raw_fumen(piece: u32, rotation: u32, col: usize, row: isize) -> String {
+        let mut values = Values::new();
+        values.push(((4 * FIELD_BLOCKS as u32 + block_index(col, row) as u32) * 4 + rotation) * 8 + piece, 3);
+        format!("v115@vhA{}", values.encode())
+    }
+
+    #[test]
+    fn places_symmetric_pieces_like_the_editor() {
+        // The editor draws O, I, S and Z in the same squares for rotations half a turn apart, and
+        // O in the same squares for every rotation.
+        for &(ref ttype, rotations) in [
+            (::TetrominoType::O, &[0, 1, 2, 3][..]),
+            (::TetrominoType::I, &[0, 2][..]),
+            (::TetrominoType::I, &[1, 3][..]),
+            (::TetrominoType::S, &[0, 2][..]),
+            (::TetrominoType::S, &[1, 3][..]),
+            (::TetrominoType::Z, &[0, 2][..]),
+            (::TetrominoType::Z, &[1, 3][..]),
+        ].iter() {
+            let squares: Vec<Vec<(isize, isize)>> = rotations.iter().map(|&rotation| {
+                let pages = decode(&raw_fumen(tetromino_type_to_code(ttype) as u32, rotation, 4, 10)).unwrap();
+                pages[0].tetromino.as_ref().unwrap().sorted_minoes()
+            }).collect();
+            assert!(squares.iter().all(|s| *s == squares[0]), "{:?} {:?}", ttype, squares);
+        }
+    }
+
+    #[test]
+    fn round_trips_every_orientation() {
+        let types = [
+            ::TetrominoType::I, ::TetrominoType::J, ::TetrominoType::L, ::TetrominoType::O,
+            ::TetrominoType::S, ::TetrominoType::T, ::TetrominoType::Z
+        ];
+        for ttype in types.iter() {
+            for orientation in 0..4 {
+                let tetromino = Tetromino::with_position(ttype.clone(), (4, 10), orientation);
+                let page = Page { tetromino: Some(tetromino.clone()), lock: false, ..Page::new() };
+                let decoded = decode(&encode(&[page])).unwrap();
+                let decoded_tetromino = decoded[0].tetromino.as_ref().unwrap();
+                assert_eq!(decoded_tetromino.sorted_minoes(), tetromino.sorted_minoes(), "{:?} {}", ttype, orientation);
+            }
+        }
+    }
+
+    #[test]
+    fn breaks_up_long_data_like_the_editor() {
+        // 7 pages with a comment of 12 characters make 41 characters of data, 13 pages without a
+        // comment make 42, and 11 pages with a comment of 4 characters make 43.
+        for &(ref pages, data_length, breaks) in [
+            (blank_pages(7, "abcdefghijkl"), 41, false),
+            (blank_pages(13, ""), 42, false),
+            (blank_pages(11, "abcd"), 43, true),
+        ].iter() {
+            let fumen = encode(pages);
+            assert_eq!(fumen.len(), "v115@".len() + data_length + if breaks { 1 } else { 0 });
+            assert_eq!(fumen.find('?'), if breaks { Some("v115@".len() + 42) } else { None });
+            assert_eq!(&decode(&fumen).unwrap(), pages);
+        }
+    }
+}
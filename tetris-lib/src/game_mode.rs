@@ -0,0 +1,132 @@
+use std::fmt;
+
+/// Number of milliseconds an Ultra game lasts, unless another limit is chosen.
+pub const ULTRA_DEFAULT_TIME_LIMIT: u32 = 120_000;
+
+/// Number of lines that must be cleared to finish a Marathon game, unless another goal is
+/// chosen.
+pub const MARATHON_DEFAULT_LINE_GOAL: usize = 150;
+/// Number of lines that must be cleared to advance to the next level in Marathon.
+pub const MARATHON_LINES_PER_LEVEL: usize = 10;
+/// The highest level that can be reached in Marathon.
+pub const MARATHON_MAX_LEVEL: u32 = 15;
+
+/// Number of rows of garbage a Dig game starts with, unless another number is chosen.
+pub const DIG_DEFAULT_GARBAGE_ROWS: usize = 10;
+/// Chance that the hole moves between one row of garbage and the next in Dig, unless another
+/// messiness is chosen.
+pub const DIG_DEFAULT_MESSINESS: f64 = 1.0;
+
+/// Number of milliseconds between the first rows of garbage rising in Survival, unless another
+/// interval is chosen.
+pub const SURVIVAL_DEFAULT_GARBAGE_INTERVAL: u32 = 5000;
+/// Number of milliseconds the interval between rows of garbage rising in Survival shrinks to,
+/// unless another interval is chosen.
+pub const SURVIVAL_DEFAULT_MIN_GARBAGE_INTERVAL: u32 = 1000;
+/// Chance that the hole moves between one row of garbage and the next in Survival, unless another
+/// messiness is chosen.
+pub const SURVIVAL_DEFAULT_MESSINESS: f64 = 0.3;
+/// The fraction of the interval between rows of garbage rising in Survival that is kept each time
+/// a row rises.
+pub const SURVIVAL_GARBAGE_INTERVAL_DECAY: f64 = 0.95;
+
+/// Describes the rules that decide how a timed tetris game ends.
+#[derive(Debug, Clone)]
+pub enum GameMode {
+    /// The game goes on until the player tops out.
+    Endless,
+    /// The player tries to get the highest score they can before `time_limit` milliseconds have
+    /// passed.
+    Ultra { time_limit: u32 },
+    /// The player starts at `start_level` and levels up every `MARATHON_LINES_PER_LEVEL` lines,
+    /// making the tetrominoes fall faster. The game is finished once `line_goal` lines have been
+    /// cleared, or goes on until the player tops out if `line_goal` is `None`. A `start_level`
+    /// outside 1 to `MARATHON_MAX_LEVEL` is treated as the closest level in that range.
+    Marathon { start_level: u32, line_goal: Option<usize> },
+    /// The matrix starts with `garbage_rows` rows of garbage, each with `holes` holes, and the
+    /// game is finished once all of them have been cleared. `messiness` is the chance, from 0 to
+    /// 1, that the holes move to other columns between one row and the next. There is always at
+    /// least one row, and no more than fit below the top 4 rows of the matrix.
+    Dig { garbage_rows: usize, holes: usize, messiness: f64 },
+    /// A row of garbage rises from the bottom of the matrix every so often, and the player tries
+    /// to last as long as they can. The first row rises after `garbage_interval` milliseconds,
+    /// and the interval shrinks each time a row rises, down to `min_garbage_interval`
+    /// milliseconds. `messiness` is the chance, from 0 to 1, that the hole moves to another column
+    /// between one row and the next.
+    Survival { garbage_interval: u32, min_garbage_interval: u32, messiness: f64 }
+}
+
+impl GameMode {
+
+    /// Creates an Ultra mode with the default time limit.
+    pub fn ultra() -> Self {
+        GameMode::Ultra { time_limit: ULTRA_DEFAULT_TIME_LIMIT }
+    }
+
+    /// Creates a Marathon mode starting at the given level, with the default line goal.
+    pub fn marathon(start_level: u32) -> Self {
+        GameMode::Marathon { start_level, line_goal: Some(MARATHON_DEFAULT_LINE_GOAL) }
+    }
+
+    /// Creates a Dig mode with the default number of garbage rows, each with a single hole that
+    /// moves with the default messiness.
+    pub fn dig() -> Self {
+        GameMode::Dig {
+            garbage_rows: DIG_DEFAULT_GARBAGE_ROWS,
+            holes: 1,
+            messiness: DIG_DEFAULT_MESSINESS
+        }
+    }
+
+    /// Creates a Survival mode with the default garbage intervals and messiness.
+    pub fn survival() -> Self {
+        GameMode::Survival {
+            garbage_interval: SURVIVAL_DEFAULT_GARBAGE_INTERVAL,
+            min_garbage_interval: SURVIVAL_DEFAULT_MIN_GARBAGE_INTERVAL,
+            messiness: SURVIVAL_DEFAULT_MESSINESS
+        }
+    }
+}
+
+/// Describes why a game ended.
+#[derive(Debug, Clone, PartialEq)]
+pub enum GameEnd {
+    /// There was no space to spawn the next tetromino.
+    ToppedOut,
+    /// The time limit of the game mode ran out.
+    TimeUp,
+    /// The goal of the game mode was reached.
+    Completed
+}
+
+/// A summary of a finished game.
+#[derive(Debug, Clone)]
+pub struct GameResult {
+    /// The reason the game ended.
+    pub end: GameEnd,
+    /// The final score.
+    pub score: u32,
+    /// The level the game ended on.
+    pub level: u32,
+    /// The total number of lines cleared.
+    pub lines_cleared: usize,
+    /// The total number of tetrominoes locked on the matrix.
+    pub pieces_placed: usize,
+    /// The number of milliseconds the game lasted.
+    pub time: u32
+}
+
+impl fmt::Display for GameResult {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "{}", match self.end {
+            GameEnd::ToppedOut => "GAME OVER",
+            GameEnd::TimeUp => "TIME UP",
+            GameEnd::Completed => "COMPLETED",
+        })?;
+        writeln!(f, "score:  {}", self.score)?;
+        writeln!(f, "level:  {}", self.level)?;
+        writeln!(f, "lines:  {}", self.lines_cleared)?;
+        writeln!(f, "pieces: {}", self.pieces_placed)?;
+        write!(f, "time:   {}.{:03} s", self.time / 1000, self.time % 1000)
+    }
+}
@@ -1,19 +1,26 @@
+use std::collections::VecDeque;
+
+use ::attack;
 use ::bag;
+use ::config::GameConfig;
+use ::game_mode::{GameEnd, GameMode, GameResult, MARATHON_LINES_PER_LEVEL, MARATHON_MAX_LEVEL,
+                  SURVIVAL_GARBAGE_INTERVAL_DECAY};
+use ::garbage::GarbageGenerator;
+use ::placement::Input;
+use ::scoring;
 use ::tetromino_data;
 
-// TODO: make these constants configurable at runtime
-
-/// The number of pieces that the player can see in advance.
+/// The number of pieces that the player can see in advance, unless another number is chosen.
 pub const NEXT_PREVIEW_LENGTH: usize = 5;
 
-/// Width of the play field.
+/// Width of the play field, unless another width is chosen.
 pub const MATRIX_WIDTH: usize = 10;
-/// Height of the play field (both visible and hidden parts).
+/// Height of the play field (both visible and hidden parts), unless another height is chosen.
 pub const MATRIX_HEIGHT: usize = 22;
 
 /// Describes a tetromino of some type at some position and orientation on the
 /// matrix.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub struct Tetromino {
     ttype: ::TetrominoType,
     center: (isize, isize),
@@ -44,19 +51,45 @@ impl Tetromino {
             )
         }).collect()
     }
+
+    /// Returns the positions of the minoes of this tetromino in order, so that tests can compare
+    /// where tetrominoes are regardless of how their center and orientation describe it.
+    #[cfg(test)]
+    pub(crate) fn sorted_minoes(&self) -> Vec<(isize, isize)> {
+        let mut minoes = self.minoes();
+        minoes.sort();
+        minoes
+    }
 }
 
 impl Tetromino {
 
-    /// Creates a tetromino of the given type in spawn position.
+    /// Creates a tetromino of the given type in spawn position, on a matrix of the default size.
     pub fn new(tt: ::TetrominoType) -> Tetromino {
+        Tetromino::spawned(tt, &GameConfig::default())
+    }
+
+    /// Creates a tetromino of the given type in spawn position, on a matrix of the size given by
+    /// the settings.
+    pub fn spawned(tt: ::TetrominoType, config: &GameConfig) -> Tetromino {
         Tetromino {
             ttype: tt,
-            center: (4, (MATRIX_HEIGHT - 2) as isize),
+            center: config.spawn_center(),
             orientation: 0
         }
     }
 
+    /// Creates a tetromino of the given type with its center at the given coordinates, in the
+    /// given orientation. Orientation 0 is the spawn orientation, and each following orientation
+    /// is rotated another quarter turn clockwise.
+    pub fn with_position(tt: ::TetrominoType, center: (isize, isize), orientation: u32) -> Tetromino {
+        Tetromino {
+            ttype: tt,
+            center,
+            orientation: orientation % 4
+        }
+    }
+
     /// Returns the grid coordinates of the center of this tetromino.
     /// 
     /// The first index identifies the row, where 0 is the bottom row. The second
@@ -70,6 +103,74 @@ impl Tetromino {
         return &self.ttype;
     }
 
+    /// Returns the orientation of this tetromino, from 0 to 3. Orientation 0 is the spawn
+    /// orientation, and each following orientation is rotated another quarter turn clockwise.
+    pub fn orientation(&self) -> u32 {
+        self.orientation
+    }
+
+}
+
+/// Describes whether a T tetromino was spun into place.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum TSpin {
+    /// The tetromino was not a T, or it was not spun into place.
+    None,
+    /// The T was spun into place with only one of the squares diagonally in front of its center
+    /// filled.
+    Mini,
+    /// The T was spun into place with both squares diagonally in front of its center filled, or
+    /// with the last kick of the SRS kick tables.
+    Full
+}
+
+/// Describes the lines cleared by locking a tetromino.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct LineClear {
+    /// The number of lines cleared.
+    pub lines: usize,
+    /// Whether the matrix was left empty.
+    pub perfect_clear: bool,
+    /// Whether the tetromino was spun into place.
+    pub t_spin: TSpin,
+    /// The number of tetrominoes in a row before this one that cleared lines.
+    pub combo: u32,
+    /// Whether this and the previous line clear were both tetrises or T-spins.
+    pub back_to_back: bool
+}
+
+impl LineClear {
+
+    /// Returns `true` if this is a tetris or a T-spin, which keeps a back-to-back chain going.
+    pub fn is_difficult(&self) -> bool {
+        self.lines >= 4 || self.t_spin != TSpin::None
+    }
+
+    /// Returns the names of what this line clear achieved, in the order frontends announce them:
+    /// the clear itself, such as "T-SPIN DOUBLE" or "TETRIS", then "BACK-TO-BACK" and the combo,
+    /// if any. Perfect clears have their own event, so they are left out.
+    pub fn labels(&self) -> Vec<String> {
+        let lines = match self.lines {
+            1 => "SINGLE",
+            2 => "DOUBLE",
+            3 => "TRIPLE",
+            _ => "TETRIS",
+        };
+        let mut labels = vec![match self.t_spin {
+            TSpin::None => lines.to_string(),
+            TSpin::Mini => format!("T-SPIN MINI {}", lines),
+            TSpin::Full => format!("T-SPIN {}", lines),
+        }];
+
+        if self.back_to_back {
+            labels.push(String::from("BACK-TO-BACK"));
+        }
+        if self.combo > 0 {
+            labels.push(format!("{} COMBO", self.combo));
+        }
+
+        labels
+    }
 }
 
 /// Describes the state of a Tetris game.
@@ -77,9 +178,9 @@ impl Tetromino {
 /// Rows are indexed from bottom to top, where the bottom row is indexed as 0.
 /// Columns are indexed from left to right, where the left-most row is indexed
 /// as 0. Coordinates are specified as (col, row).
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct GameState {
-    placed_squares: Vec<Vec<Option<::TetrominoType>>>,
+    placed_squares: Vec<Vec<::Cell>>,
 
     falling_tetromino: Tetromino,
 
@@ -88,7 +189,33 @@ pub struct GameState {
 
     next_preview: Vec<::TetrominoType>,
 
-    held: Option<::TetrominoType>
+    held: Option<::TetrominoType>,
+
+    /// Whether the falling tetromino can be held. Only one hold is allowed per tetromino, so this
+    /// is `false` from a hold until the falling tetromino locks.
+    hold_available: bool,
+
+    /// The settings the game is played with.
+    config: GameConfig,
+
+    /// The lines cleared by the most recently locked tetromino, if it cleared any.
+    last_clear: Option<LineClear>,
+
+    /// The index of the kick used by the most recent action on the falling tetromino, if it was a
+    /// rotation.
+    last_kick: Option<usize>,
+
+    /// The number of tetrominoes in a row that cleared lines, up to the most recently locked one.
+    clears_in_a_row: u32,
+
+    /// Whether the most recent line clear was a tetris or a T-spin.
+    back_to_back: bool,
+
+    /// The total number of lines cleared so far.
+    lines_cleared: usize,
+
+    /// The total number of tetrominoes locked on the matrix so far.
+    pieces_placed: usize
 }
 
 impl GameState {
@@ -96,36 +223,86 @@ impl GameState {
     /// Create a new `GameState` representing the initial state of a tetris
     /// game.
     pub fn new() -> Self {
+        GameState::with_config(GameConfig::default())
+    }
 
-        let mut bag = bag::Bag::new();
+    /// Create a new `GameState` representing the initial state of a tetris game, where the
+    /// tetrominoes always come in the same order for the same seed.
+    pub fn with_seed(seed: u64) -> Self {
+        GameState::with_config(GameConfig { seed: Some(seed), ..GameConfig::default() })
+    }
+
+    /// Create a new `GameState` representing the initial state of a tetris game played with the
+    /// given settings.
+    pub fn with_config(config: GameConfig) -> Self {
+
+        let mut bag = bag::Bag::with_config(&config);
 
         let initial_falling_tetromino_type = bag.draw();
         let initial_next_preview =
-            (0..NEXT_PREVIEW_LENGTH)
+            (0..config.preview_length)
             .map(|_| bag.draw())
             .collect();
 
         GameState {
-            placed_squares: vec![vec![None; MATRIX_HEIGHT]; MATRIX_WIDTH],
-            falling_tetromino: Tetromino::new(initial_falling_tetromino_type),
+            placed_squares: vec![vec![::Cell::Empty; config.matrix_height]; config.matrix_width],
+            falling_tetromino: Tetromino::spawned(initial_falling_tetromino_type, &config),
             bag: bag,
             next_preview: initial_next_preview,
-            held: None
+            held: None,
+            hold_available: true,
+            config,
+            last_clear: None,
+            last_kick: None,
+            clears_in_a_row: 0,
+            back_to_back: false,
+            lines_cleared: 0,
+            pieces_placed: 0
+        }
+    }
+
+    /// Creates a `GameState` from its parts, as if the game had been played up to that point.
+    /// The next tetrominoes after `next_preview` are drawn from `bag`.
+    pub(crate) fn from_parts(config: GameConfig,
+                             placed_squares: Vec<Vec<::Cell>>,
+                             falling_tetromino: Tetromino,
+                             bag: bag::Bag,
+                             next_preview: Vec<::TetrominoType>,
+                             held: Option<::TetrominoType>) -> Self {
+        GameState {
+            placed_squares,
+            falling_tetromino,
+            bag,
+            next_preview,
+            held,
+            hold_available: true,
+            config,
+            last_clear: None,
+            last_kick: None,
+            clears_in_a_row: 0,
+            back_to_back: false,
+            lines_cleared: 0,
+            pieces_placed: 0
         }
     }
 
     // Getters
 
     /// Returns a representation of all the squares that have previously been
-    /// placed the matrix. It is expressed as a `Vec<Vec<Option<::TetrominoType>>>`
-    /// where:
+    /// placed the matrix. It is expressed as a `Vec<Vec<::Cell>>` where:
     ///
-    /// - `placed_squares[i][j] == Some(tt)` indicates that there has been a
+    /// - `placed_squares[i][j] == Cell::Piece(tt)` indicates that there has been a
     ///   square placed at coordinates (`i`, `j`), which came from a tetromino
     ///   of type `tt`.
-    /// - `placed_squares[i][j] == None` indicates that there has not been any
+    /// - `placed_squares[i][j] == Cell::Garbage` indicates that there is a square
+    ///   of garbage at coordinates (`i`, `j`).
+    /// - `placed_squares[i][j] == Cell::Invisible(tt)` indicates the same as
+    ///   `Cell::Piece(tt)`, except that the square should not be shown.
+    /// - `placed_squares[i][j] == Cell::Item` indicates that there is an item
+    ///   block at coordinates (`i`, `j`).
+    /// - `placed_squares[i][j] == Cell::Empty` indicates that there has not been any
     ///   square placed at coordinates (`i`, `j`).
-    pub fn placed_squares(&self) -> &Vec<Vec<Option<::TetrominoType>>> {
+    pub fn placed_squares(&self) -> &Vec<Vec<::Cell>> {
         return &self.placed_squares;
     }
 
@@ -144,6 +321,71 @@ impl GameState {
         return &self.held;
     }
 
+    /// Returns where the falling tetromino would land if it were hard dropped.
+    pub fn ghost_tetromino(&self) -> Tetromino {
+        let mut ghost = self.falling_tetromino.clone();
+        loop {
+            let below = Tetromino { center: (ghost.center.0, ghost.center.1 - 1), ..ghost.clone() };
+            if !self.tetromino_fits(&below) {
+                return ghost;
+            }
+            ghost = below;
+        }
+    }
+
+    /// Whether the falling tetromino can be held. After a hold, it cannot until the falling
+    /// tetromino locks.
+    pub fn can_hold(&self) -> bool {
+        self.hold_available
+    }
+
+    /// The settings the game is played with.
+    pub fn config(&self) -> &GameConfig {
+        &self.config
+    }
+
+    /// The number of columns of the matrix.
+    pub fn matrix_width(&self) -> usize {
+        self.config.matrix_width
+    }
+
+    /// The number of rows of the matrix, both visible and hidden.
+    pub fn matrix_height(&self) -> usize {
+        self.config.matrix_height
+    }
+
+    /// The number of lines cleared by the most recently locked tetromino.
+    pub fn last_lines_cleared(&self) -> usize {
+        self.last_clear.as_ref().map_or(0, |clear| clear.lines)
+    }
+
+    /// The lines cleared by the most recently locked tetromino, if it cleared any.
+    pub fn last_clear(&self) -> Option<&LineClear> {
+        self.last_clear.as_ref()
+    }
+
+    /// Returns `true` if nothing has been placed on the matrix, or everything has been cleared.
+    pub fn is_matrix_empty(&self) -> bool {
+        self.placed_squares.iter().all(|column| column.iter().all(|square| square.is_empty()))
+    }
+
+    /// The total number of lines cleared so far.
+    pub fn lines_cleared(&self) -> usize {
+        self.lines_cleared
+    }
+
+    /// The total number of tetrominoes locked on the matrix so far.
+    pub fn pieces_placed(&self) -> usize {
+        self.pieces_placed
+    }
+
+    /// The number of rows that contain at least one square of garbage.
+    pub fn garbage_row_count(&self) -> usize {
+        (0..self.matrix_height()).filter(|&row| {
+            (0..self.matrix_width()).any(|col| self.placed_squares[col][row] == ::Cell::Garbage)
+        }).count()
+    }
+
     // Actions that can be made by the player
 
     /// Moves the currently falling piece down, due to gravity. Returns `true`
@@ -203,9 +445,14 @@ impl GameState {
     ///
     /// If there is already a piece held, that piece will be spawned. If not, the next
     /// piece in the preview will be spawned. This might fail if there is no space
-    /// to spawn the tetromino, in which case this function will do nothing.
+    /// to spawn the tetromino, in which case this function will do nothing. It also does nothing
+    /// if there was already a hold since the last tetromino locked (see `can_hold`), or if the
+    /// game has ended.
     pub fn hold(&mut self) {
-        // TODO: this shouldn't be allowed twice in a row
+        if !self.hold_available {
+            return;
+        }
+
         let new_held = self.falling_tetromino.ttype.clone();
         let ttype_to_spawn = if let Some(ref old_held) = self.held {
             old_held.clone()
@@ -217,6 +464,7 @@ impl GameState {
 
         if success {
             self.held = Some(new_held);
+            self.hold_available = false;
         }
     }
 
@@ -226,20 +474,67 @@ impl GameState {
     /// this `GameState` should not be mutated further after that.
     pub fn lock_piece(&mut self) -> bool {
 
+        let t_spin = self.t_spin();
+
         // place tetromino squares on matrix
         self.falling_tetromino.minoes().iter().for_each(|mino_position| {
-            self.placed_squares[mino_position.0 as usize][mino_position.1 as usize] = Some(self.falling_tetromino.ttype.clone());
+            self.placed_squares[mino_position.0 as usize][mino_position.1 as usize] = ::Cell::Piece(self.falling_tetromino.ttype.clone());
         });
 
-        self.clear_lines();
+        self.pieces_placed += 1;
+        let lines = self.clear_lines();
+        self.lines_cleared += lines;
+        self.last_clear = if lines > 0 {
+            let mut clear = LineClear {
+                lines,
+                perfect_clear: self.is_matrix_empty(),
+                t_spin,
+                combo: self.clears_in_a_row,
+                back_to_back: false
+            };
+            clear.back_to_back = self.back_to_back && clear.is_difficult();
+            self.back_to_back = clear.is_difficult();
+            self.clears_in_a_row += 1;
+            Some(clear)
+        } else {
+            self.clears_in_a_row = 0;
+            None
+        };
 
         // FIXME: don't pop until we know we can successfully spawn the piece
         let ttype_to_spawn = self.pop_next_tetromino_from_preview();
+        self.hold_available = true;
         self.spawn_tetromino(ttype_to_spawn)
 
         // TODO: activate pending garbage lines
     }
 
+    /// Pushes everything on the matrix up by one row, and fills the bottom row with garbage,
+    /// except for the squares in the columns listed in `holes`. The currently falling tetromino is
+    /// pushed up too if the garbage would overlap it. Returns `false` if a square was pushed out of
+    /// the top of the matrix or the falling tetromino could not be pushed up, which indicates a game
+    /// over, or `true` otherwise.
+    pub fn add_garbage_row(&mut self, holes: &[usize]) -> bool {
+        let mut topped_out = false;
+
+        for (col, column) in self.placed_squares.iter_mut().enumerate() {
+            let square = if holes.contains(&col) { ::Cell::Empty } else { ::Cell::Garbage };
+            column.insert(0, square);
+            if let Some(pushed_out) = column.pop() {
+                topped_out |= !pushed_out.is_empty();
+            }
+        }
+
+        while !self.tetromino_fits(&self.falling_tetromino) {
+            self.falling_tetromino.center.1 += 1;
+            if self.falling_tetromino.center.1 >= self.matrix_height() as isize {
+                return false;
+            }
+        }
+
+        !topped_out
+    }
+
     // Helpers
 
     /// Returns `true` if the current tetromino has space under it to fall into, or
@@ -257,33 +552,36 @@ impl GameState {
     }
 
     /// Clears any full lines that are on the matrix, then moves the above lines
-    /// down.
-    fn clear_lines(&mut self) {
+    /// down. Returns the number of lines that were cleared.
+    fn clear_lines(&mut self) -> usize {
+        let (width, height) = (self.matrix_width(), self.matrix_height());
         let mut num_cleared_rows = 0;
 
         // Write the contents of each row into the below row into which it fell. num_cleared_rows
         // keeps track of how many rows to move down.
-        for row in 0..MATRIX_HEIGHT {
-            let row_filled = (0..MATRIX_WIDTH).all(|col| {
-                self.placed_squares[col][row].is_some()
+        for row in 0..height {
+            let row_filled = (0..width).all(|col| {
+                !self.placed_squares[col][row].is_empty()
             });
             if row_filled {
                 // This row is filled, it will be overwritten by a higher row.
                 num_cleared_rows += 1;
             } else {
                 // Write this row num_cleared_rows below.
-                for col in 0..MATRIX_WIDTH {
+                for col in 0..width {
                     self.placed_squares[col][row - num_cleared_rows] = self.placed_squares[col][row].clone();
                 }
             }
         }
 
         // Empty the top num_cleared_rows rows.
-        for row in MATRIX_HEIGHT-num_cleared_rows..MATRIX_HEIGHT {
-            for col in 0..MATRIX_WIDTH {
-                self.placed_squares[col][row] = None;
+        for row in height-num_cleared_rows..height {
+            for col in 0..width {
+                self.placed_squares[col][row] = ::Cell::Empty;
             }
         }
+
+        num_cleared_rows
     }
 
     /// Tries to spawn a tetromino of the given type.
@@ -292,11 +590,12 @@ impl GameState {
     /// replaces the current tetromino with the new one. Otherwise, returns `false`.
     fn spawn_tetromino(&mut self, ttype: ::TetrominoType) -> bool {
 
-        let candidate = Tetromino::new(ttype);
+        let candidate = Tetromino::spawned(ttype, &self.config);
 
         if self.tetromino_fits(&candidate) {
             // spawn the tetromino
             self.falling_tetromino = candidate;
+            self.last_kick = None;
             return true;
         } else {
             // no space for spawning tetromino
@@ -325,24 +624,42 @@ impl GameState {
 
         if fits {
             self.falling_tetromino = candidate;
+            self.last_kick = None;
         }
 
         fits
     }
 
-    fn tetromino_fits(&self, t: &Tetromino) -> bool {
+    /// Returns `true` if the given tetromino is within the matrix and does not overlap any placed
+    /// squares.
+    pub(crate) fn tetromino_fits(&self, t: &Tetromino) -> bool {
         t.minoes().iter().all(|mino_position| {
             mino_position.0 >= 0 &&
-            mino_position.0 < (MATRIX_WIDTH as isize) &&
+            mino_position.0 < (self.matrix_width() as isize) &&
             mino_position.1 >= 0 &&
-            mino_position.1 < (MATRIX_HEIGHT as isize) &&
-            self.placed_squares[mino_position.0 as usize][mino_position.1 as usize].is_none()
+            mino_position.1 < (self.matrix_height() as isize) &&
+            self.placed_squares[mino_position.0 as usize][mino_position.1 as usize].is_empty()
         })
     }
 
     fn rotate_to_orientation(&mut self, new_orientation: u32) -> bool {
-        let old_orientation = self.falling_tetromino.orientation;
-        for offset_data in tetromino_data::tetromino_offset_data_from(&self.falling_tetromino.ttype) {
+        match self.rotated_tetromino(&self.falling_tetromino, new_orientation) {
+            Some((rotated, kick)) => {
+                self.falling_tetromino = rotated;
+                self.last_kick = Some(kick);
+                true
+            },
+            None => false
+        }
+    }
+
+    /// Returns the given tetromino rotated to the given orientation, using the first of the kicks
+    /// specified in the SRS kick tables that fits, along with the index of that kick. Only the
+    /// kicks allowed by the rotation system are tried. Returns `None` if none of them fit.
+    pub(crate) fn rotated_tetromino(&self, t: &Tetromino, new_orientation: u32) -> Option<(Tetromino, usize)> {
+        let old_orientation = t.orientation;
+        let kick_count = self.config.rotation_system.kick_count();
+        for (kick, offset_data) in tetromino_data::tetromino_offset_data_from(&t.ttype).iter().take(kick_count).enumerate() {
 
             let offset = (
                 offset_data[old_orientation as usize].0 - offset_data[new_orientation as usize].0,
@@ -350,20 +667,51 @@ impl GameState {
             );
 
             let candidate_tetromino = Tetromino {
-                ttype: self.falling_tetromino.ttype.clone(),
+                ttype: t.ttype.clone(),
                 center: (
-                    self.falling_tetromino.center.0 + offset.0,
-                    self.falling_tetromino.center.1 + offset.1,
+                    t.center.0 + offset.0,
+                    t.center.1 + offset.1,
                 ),
                 orientation: new_orientation,
             };
 
             if self.tetromino_fits(&candidate_tetromino) {
-                self.falling_tetromino = candidate_tetromino;
-                return true;
+                return Some((candidate_tetromino, kick));
             }
         }
-        false
+        None
+    }
+
+    /// Returns whether the falling tetromino was spun into place, following the guideline rules:
+    /// it must be a T whose last action was a rotation, with at least 3 of the 4 squares diagonal
+    /// to its center filled. Walls and the floor count as filled.
+    fn t_spin(&self) -> TSpin {
+        let t = &self.falling_tetromino;
+        let kick = match self.last_kick {
+            Some(kick) if t.ttype == ::TetrominoType::T => kick,
+            _ => return TSpin::None,
+        };
+
+        // In clockwise order, starting with the front left corner in spawn orientation.
+        let corners = [(-1, 1), (1, 1), (1, -1), (-1, -1)];
+        let filled: Vec<bool> = corners.iter().map(|&(dc, dr)| {
+            let (col, row) = (t.center.0 + dc, t.center.1 + dr);
+            col < 0 || col >= self.matrix_width() as isize || row < 0
+                || (row < self.matrix_height() as isize && !self.placed_squares[col as usize][row as usize].is_empty())
+        }).collect();
+
+        if filled.iter().filter(|&&f| f).count() < 3 {
+            return TSpin::None;
+        }
+
+        let front = t.orientation as usize;
+        let both_front_filled = filled[front] && filled[(front + 1) % 4];
+        // The last kick is the one that lets a T spin into a T-spin triple.
+        if both_front_filled || kick == 4 {
+            TSpin::Full
+        } else {
+            TSpin::Mini
+        }
     }
 }
 
@@ -372,6 +720,14 @@ pub const FALL_INTERVAL: u32 = 500;
 /// Number of milliseconds a tetromino spends on the ground until it is forced to lock.
 pub const LOCK_INTERVAL: u32 = 1000;
 
+/// Returns the number of milliseconds it takes for a tetromino to go down by 1 space on the given
+/// level, following the gravity curve of the guideline Marathon.
+pub fn level_fall_interval(level: u32) -> u32 {
+    let level = level.max(1) as f64;
+    let seconds_per_row = (0.8 - (level - 1.0) * 0.007).powf(level - 1.0);
+    ((seconds_per_row * 1000.0) as u32).max(1)
+}
+
 /// Describes what the current tetromino is doing.
 pub enum TimeStateAction {
     /// The current tetromino is falling, and there are `time_to_fall` milliseconds until it falls
@@ -387,27 +743,127 @@ pub struct TimeState {
     pub action: TimeStateAction,
     /// The remaining number of milliseconds the current tetromino can spend on the ground
     /// before it is forced to lock.
-    pub time_to_lock: u32
+    pub time_to_lock: u32,
+    /// The remaining number of milliseconds until the next row of garbage rises, or `None` if
+    /// garbage does not rise in this game.
+    pub time_to_garbage: Option<u32>
+}
+
+/// Something that happened in a tetris game that a player might want to be told about.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub enum GameEvent {
+    /// A tetromino was locked and cleared lines, scoring `score` points.
+    LineClear { clear: LineClear, score: u32 },
+    /// A tetromino was locked and left the matrix empty after clearing `lines` lines, scoring
+    /// `score` bonus points.
+    PerfectClear { lines: usize, score: u32 }
+}
+
+/// Lines of garbage received from an opponent, waiting to rise into the matrix.
+struct IncomingGarbage {
+    lines: u32,
+    /// The remaining number of milliseconds until the garbage can rise.
+    time_to_rise: u32
 }
 
 /// Describes the state of a tetris game, with timing information.
 pub struct TimedGameState {
     game_state: GameState,
-    time_state: TimeState
+    time_state: TimeState,
+
+    /// The rules deciding when this game ends.
+    mode: GameMode,
+
+    /// The number of points scored so far.
+    score: u32,
+
+    /// The number of milliseconds that have passed since the game started.
+    elapsed: u32,
+
+    /// The reason the game ended, or `None` if it is still going on.
+    end: Option<GameEnd>,
+
+    /// Decides where the holes are in rising garbage, if garbage rises in this game.
+    garbage_generator: Option<GarbageGenerator>,
+
+    /// The number of milliseconds between the current and the next row of rising garbage.
+    garbage_interval: u32,
+
+    /// Events that happened since they were last taken.
+    events: Vec<GameEvent>,
+
+    /// Garbage received from an opponent that has not risen into the matrix yet, oldest first.
+    incoming_garbage: VecDeque<IncomingGarbage>,
+
+    /// Decides where the holes are in garbage received from an opponent.
+    incoming_garbage_generator: GarbageGenerator,
+
+    /// The number of lines of garbage to send to an opponent since they were last taken.
+    outgoing_attack: u32
 }
 
 impl TimedGameState {
 
     /// Create a new `TimedGameState` corresponding to the initial state of a timed tetris game.
     pub fn new() -> Self {
+        TimedGameState::with_mode(GameMode::Endless)
+    }
+
+    /// Create a new `TimedGameState` corresponding to the initial state of a timed tetris game
+    /// played with the given mode.
+    pub fn with_mode(mode: GameMode) -> Self {
+        TimedGameState::with_game_state(GameState::new(), mode)
+    }
+
+    /// Create a new `TimedGameState` that continues from the given `GameState`, played with the
+    /// given mode.
+    pub fn with_game_state(game_state: GameState, mode: GameMode) -> Self {
+        let mut game_state = game_state;
+        let width = game_state.matrix_width();
+        let seed = game_state.config().seed;
+        let mut garbage_generator = None;
+        let mut garbage_interval = 0;
+
+        if let GameMode::Dig { garbage_rows, holes, messiness } = mode {
+            // Leave enough space above the garbage for tetrominoes to spawn, but always add a row,
+            // or the game would be finished before it starts. Even the smallest matrix has room
+            // for one row below the spawn position.
+            let rows = garbage_rows.min(game_state.matrix_height() - 4).max(1);
+            let mut generator = GarbageGenerator::with_width(width, holes, messiness, seed);
+            for _ in 0..rows {
+                game_state.add_garbage_row(&generator.next_holes());
+            }
+        }
+
+        if let GameMode::Survival { garbage_interval: interval, messiness, .. } = mode {
+            garbage_generator = Some(GarbageGenerator::with_width(width, 1, messiness, seed));
+            garbage_interval = interval;
+        }
+
         let mut tgs = TimedGameState {
-            game_state: GameState::new(),
+            game_state,
             time_state: TimeState {
                 action: TimeStateAction::Falling {
                     time_to_fall: FALL_INTERVAL
                 },
-                time_to_lock: LOCK_INTERVAL
-            }
+                time_to_lock: LOCK_INTERVAL,
+                time_to_garbage: garbage_generator.as_ref().map(|_| garbage_interval)
+            },
+            mode,
+            score: 0,
+            elapsed: 0,
+            end: None,
+            garbage_generator,
+            garbage_interval,
+            events: Vec::new(),
+            incoming_garbage: VecDeque::new(),
+            // Received garbage gets its own stream of holes, apart from any Survival garbage.
+            incoming_garbage_generator:
+                GarbageGenerator::with_width(width, 1, 1.0, seed.map(|seed| seed.wrapping_add(1))),
+            outgoing_attack: 0
+        };
+        tgs.time_state.action = TimeStateAction::Falling {
+            time_to_fall: tgs.fall_interval()
         };
         tgs.update_time_state();
 
@@ -426,18 +882,120 @@ impl TimedGameState {
         &self.time_state
     }
 
+    /// Returns the mode this game is played with.
+    pub fn mode(&self) -> &GameMode {
+        &self.mode
+    }
+
+    /// Returns the number of points scored so far.
+    pub fn score(&self) -> u32 {
+        self.score
+    }
+
+    /// Returns the number of milliseconds that have passed since the game started.
+    pub fn elapsed(&self) -> u32 {
+        self.elapsed
+    }
+
+    /// Returns the current level.
+    pub fn level(&self) -> u32 {
+        self.level_at(self.game_state.lines_cleared())
+    }
+
+    /// Returns the number of milliseconds it currently takes for a tetromino to go down by 1
+    /// space.
+    pub fn fall_interval(&self) -> u32 {
+        match self.mode {
+            GameMode::Marathon { .. } => level_fall_interval(self.level()),
+            _ => FALL_INTERVAL,
+        }
+    }
+
+    /// Returns the number of lines left to clear before the goal of the game mode is reached, or
+    /// `None` if the game mode has no line goal. In Dig, this is the number of rows of garbage
+    /// left.
+    pub fn lines_remaining(&self) -> Option<usize> {
+        match self.mode {
+            GameMode::Marathon { line_goal: Some(line_goal), .. } =>
+                Some(line_goal.saturating_sub(self.game_state.lines_cleared())),
+            GameMode::Dig { .. } => Some(self.game_state.garbage_row_count()),
+            _ => None,
+        }
+    }
+
+    /// Returns the number of milliseconds left before the time limit of the game mode runs out,
+    /// or `None` if the game mode has no time limit.
+    pub fn time_remaining(&self) -> Option<u32> {
+        match self.mode {
+            GameMode::Ultra { time_limit } => Some(time_limit.saturating_sub(self.elapsed)),
+            _ => None,
+        }
+    }
+
+    /// Returns the reason the game ended, or `None` if it is still going on.
+    pub fn end(&self) -> Option<&GameEnd> {
+        self.end.as_ref()
+    }
+
+    /// Removes and returns the events that happened since this was last called. Frontends should
+    /// call this regularly, as events pile up until they are taken.
+    pub fn take_events(&mut self) -> Vec<GameEvent> {
+        std::mem::take(&mut self.events)
+    }
+
+    /// Returns the number of lines of garbage received from an opponent that have not risen into
+    /// the matrix yet.
+    pub fn pending_garbage(&self) -> u32 {
+        self.incoming_garbage.iter().map(|garbage| garbage.lines).sum()
+    }
+
+    /// Returns the number of lines of garbage this player sent since this was last called, after
+    /// cancelling incoming garbage, and resets it to 0.
+    pub fn take_attack(&mut self) -> u32 {
+        std::mem::replace(&mut self.outgoing_attack, 0)
+    }
+
+    /// Receives lines of garbage from an opponent. After `attack::GARBAGE_DELAY` milliseconds,
+    /// they rise into the matrix the next time a tetromino locks without clearing lines, unless
+    /// they are cancelled by line clears first.
+    pub fn receive_garbage(&mut self, lines: u32) {
+        if lines > 0 {
+            self.incoming_garbage.push_back(IncomingGarbage {
+                lines,
+                time_to_rise: attack::GARBAGE_DELAY
+            });
+        }
+    }
+
+    /// Returns a summary of the game if it has ended, or `None` if it is still going on.
+    pub fn result(&self) -> Option<GameResult> {
+        self.end.as_ref().map(|end| GameResult {
+            end: end.clone(),
+            score: self.score,
+            level: self.level(),
+            lines_cleared: self.game_state.lines_cleared(),
+            pieces_placed: self.game_state.pieces_placed(),
+            time: self.elapsed
+        })
+    }
+
     // Getters for fields within the underlying GameState
 
     /// Returns a representation of all the squares that have previously been
-    /// placed the matrix. It is expressed as a `Vec<Vec<Option<::TetrominoType>>>`
-    /// where:
+    /// placed the matrix. It is expressed as a `Vec<Vec<::Cell>>` where:
     ///
-    /// - `placed_squares[i][j] == Some(tt)` indicates that there has been a
+    /// - `placed_squares[i][j] == Cell::Piece(tt)` indicates that there has been a
     ///   square placed at coordinates (`i`, `j`), which came from a tetromino
     ///   of type `tt`.
-    /// - `placed_squares[i][j] == None` indicates that there has not been any
+    /// - `placed_squares[i][j] == Cell::Garbage` indicates that there is a square
+    ///   of garbage at coordinates (`i`, `j`).
+    /// - `placed_squares[i][j] == Cell::Invisible(tt)` indicates the same as
+    ///   `Cell::Piece(tt)`, except that the square should not be shown.
+    /// - `placed_squares[i][j] == Cell::Item` indicates that there is an item
+    ///   block at coordinates (`i`, `j`).
+    /// - `placed_squares[i][j] == Cell::Empty` indicates that there has not been any
     ///   square placed at coordinates (`i`, `j`).
-    pub fn placed_squares(&self) -> &Vec<Vec<Option<::TetrominoType>>> {
+    pub fn placed_squares(&self) -> &Vec<Vec<::Cell>> {
         return self.game_state.placed_squares();
     }
 
@@ -456,12 +1014,21 @@ impl TimedGameState {
         return self.game_state.held();
     }
 
+    /// Whether the falling tetromino can be held. After a hold, it cannot until the falling
+    /// tetromino locks.
+    pub fn can_hold(&self) -> bool {
+        self.game_state.can_hold()
+    }
+
     // Actions passed through to the underlying `GameState`. These also update the `TimeState`
-    // accordingly.
+    // accordingly, and do nothing once the game has ended.
 
     /// Moves the current tetromino to the left, if it can. Returns `true` if
     /// the tetromino was moved successfully, else returns `false`.
     pub fn move_left(&mut self) -> bool {
+        if self.end.is_some() {
+            return false;
+        }
         let r = self.game_state.move_left();
         self.update_time_state();
         r
@@ -470,6 +1037,9 @@ impl TimedGameState {
     /// Moves the current tetromino to the right, if it can. Returns `true` if
     /// the tetromino was moved successfully, else returns `false`.
     pub fn move_right(&mut self) -> bool {
+        if self.end.is_some() {
+            return false;
+        }
         let r = self.game_state.move_right();
         self.update_time_state();
         r
@@ -479,6 +1049,9 @@ impl TimedGameState {
     /// kicks specified in the SRS kick tables will be attempted. Returns `true`
     /// if a rotation successfully occurred, else returns `false`.
     pub fn rotate_left(&mut self) -> bool {
+        if self.end.is_some() {
+            return false;
+        }
         let r = self.game_state.rotate_left();
         self.update_time_state();
         r
@@ -488,50 +1061,174 @@ impl TimedGameState {
     /// specified in the SRS kick tables will be attempted. Returns `true` if a
     /// rotation successfully occurred, else returns `false`.
     pub fn rotate_right(&mut self) -> bool {
+        if self.end.is_some() {
+            return false;
+        }
         let r = self.game_state.rotate_right();
         self.update_time_state();
         r
     }
 
+    /// Moves the current tetromino down by one row, if it can, and restarts the time until it
+    /// falls again. Returns `true` if the tetromino was moved successfully, else returns `false`.
+    pub fn soft_drop(&mut self) -> bool {
+        if self.end.is_some() {
+            return false;
+        }
+        let r = self.game_state.apply_gravity();
+        if r {
+            self.time_state.action = TimeStateAction::Falling {
+                time_to_fall: self.fall_interval()
+            };
+        }
+        self.update_time_state();
+        r
+    }
+
     /// Instantly drops the currently falling tetromino on the ground directly below it,
     /// then spawns the next tetromino. Returns `true` if spawning the next piece was
     /// successful, or `false` otherwise. A return value of `false` indicates a game over, and
-    /// this `TimedGameState` should not be mutated further after that.
+    /// this `TimedGameState` should not be mutated further after that. Returns `false` without
+    /// doing anything if the game has already ended.
     pub fn hard_drop(&mut self) -> bool {
+        if self.end.is_some() {
+            return false;
+        }
         let success = self.game_state.hard_drop();
         self.update_time_state();
 
         if success {
             self.time_state.time_to_lock = LOCK_INTERVAL;
-            true
         } else {
             self.time_state.time_to_lock = 0;
-            false
         }
+
+        self.handle_lock(success)
     }
 
     /// Holds the currently falling piece and spawns another to replace it.
     ///
     /// If there is already a piece held, that piece will be spawned. If not, the next
     /// piece in the preview will be spawned. This might fail if there is no space
-    /// to spawn the tetromino, in which case this function will do nothing.
+    /// to spawn the tetromino, in which case this function will do nothing. It also does nothing
+    /// if there was already a hold since the last tetromino locked (see `can_hold`), or if the
+    /// game has ended.
     pub fn hold(&mut self) {
+        if self.end.is_some() || !self.game_state.can_hold() {
+            return;
+        }
         self.game_state.hold();
         self.time_state.time_to_lock = LOCK_INTERVAL;
         self.update_time_state();
     }
 
+    /// Makes the given input on the falling tetromino. Returns `false` if the input ended the
+    /// game or the game had already ended, or `true` otherwise.
+    pub fn apply_input(&mut self, input: Input) -> bool {
+        if self.end.is_some() {
+            return false;
+        }
+        match input {
+            Input::HardDrop => return self.hard_drop(),
+            Input::Hold => self.hold(),
+            Input::SoftDrop => while self.soft_drop() {},
+            _ => {
+                self.game_state.apply_input(input);
+                self.update_time_state();
+            }
+        }
+        true
+    }
+
     // Time-related functions
 
     /// Advance the tetris game by the given number of milliseconds, assuming no inputs are given
     /// during this period. Returns `true` if nothing ended the game in the elapsed time, or
-    /// `false` if something did. A return value of `false` indicates a game over, and
-    /// this `TimedGameState` should not be mutated further after that.
+    /// `false` if something did. A return value of `false` indicates that the game ended (see
+    /// `end()` for the reason), and this `TimedGameState` should not be mutated further after
+    /// that.
     pub fn advance_time(&mut self, t: u32) -> bool {
 
+        if self.end.is_some() {
+            return false;
+        }
+
+        let mut remaining = t;
+
+        while remaining > 0 {
+            // Never step past the time limit or the rising of garbage, so that they are handled
+            // exactly when they are due.
+            let mut step = remaining;
+            if let Some(time_remaining) = self.time_remaining() {
+                step = step.min(time_remaining);
+            }
+            if let Some(time_to_garbage) = self.time_state.time_to_garbage {
+                step = step.min(time_to_garbage);
+            }
+
+            let success = self.advance_falling_time(step);
+            self.elapsed += step;
+            remaining -= step;
+
+            for garbage in self.incoming_garbage.iter_mut() {
+                garbage.time_to_rise = garbage.time_to_rise.saturating_sub(step);
+            }
+
+            if !success {
+                return false;
+            }
+
+            if self.time_remaining() == Some(0) {
+                self.end = Some(GameEnd::TimeUp);
+                return false;
+            }
+
+            if let Some(time_to_garbage) = self.time_state.time_to_garbage {
+                if time_to_garbage > step {
+                    self.time_state.time_to_garbage = Some(time_to_garbage - step);
+                } else if !self.raise_garbage() {
+                    return false;
+                }
+            }
+        }
+
+        true
+    }
+
+    /// Raises a row of garbage from the bottom of the matrix and restarts the garbage timer with
+    /// a shorter interval. Returns `true` if the game goes on, or `false` if the garbage caused a
+    /// game over.
+    fn raise_garbage(&mut self) -> bool {
+        let holes = match self.garbage_generator {
+            Some(ref mut generator) => generator.next_holes(),
+            None => return true
+        };
+
+        let success = self.game_state.add_garbage_row(&holes);
+        self.update_time_state();
+
+        if let GameMode::Survival { min_garbage_interval, .. } = self.mode {
+            let shrunk = (self.garbage_interval as f64 * SURVIVAL_GARBAGE_INTERVAL_DECAY) as u32;
+            self.garbage_interval = shrunk.max(min_garbage_interval).max(1);
+        }
+        self.time_state.time_to_garbage = Some(self.garbage_interval);
+
+        if !success {
+            self.time_state.time_to_lock = 0;
+            self.end = Some(GameEnd::ToppedOut);
+        }
+
+        success
+    }
+
+    /// Advance the falling and locking of tetrominoes by the given number of milliseconds. Returns
+    /// `false` if a tetromino locked and the next one could not be spawned, or `true` otherwise.
+    fn advance_falling_time(&mut self, t: u32) -> bool {
+
         let mut remaining = t;
 
         while remaining > 0 {
+            let fall_interval = self.fall_interval();
             match self.time_state.action {
                 TimeStateAction::Falling{ ref mut time_to_fall } => {
                     if remaining < *time_to_fall {
@@ -547,7 +1244,7 @@ impl TimedGameState {
                         remaining -= *time_to_fall;
 
                         // Reset time_to_fall for next fall cycle
-                        *time_to_fall = FALL_INTERVAL;
+                        *time_to_fall = fall_interval;
                     }
                 },
                 TimeStateAction::Locking => {
@@ -562,7 +1259,7 @@ impl TimedGameState {
                         // Handle remaining time in next loop
                         remaining -= self.time_state.time_to_lock;
 
-                        if !success {
+                        if !self.handle_lock(success) {
                             self.time_state.time_to_lock = 0;
                             return false;
                         }
@@ -579,6 +1276,92 @@ impl TimedGameState {
         true
     }
 
+    /// Updates the score and the end of the game after a tetromino was locked. `spawned`
+    /// indicates whether the next tetromino could be spawned. Returns `true` if the game goes on,
+    /// or `false` if it ended.
+    fn handle_lock(&mut self, spawned: bool) -> bool {
+        let mut garbage_fits = true;
+
+        if let Some(clear) = self.game_state.last_clear().cloned() {
+            self.send_attack(attack::lines_sent(&clear));
+
+            // Lines are scored on the level they were cleared on, before any level up they caused.
+            let level = self.level_at(self.game_state.lines_cleared() - clear.lines);
+
+            let lines = clear.lines;
+            let perfect_clear = clear.perfect_clear;
+
+            let score = scoring::line_clear_score(&clear, level);
+            self.score += score;
+            self.events.push(GameEvent::LineClear { clear, score });
+
+            if perfect_clear {
+                let bonus = scoring::perfect_clear_score(lines, level);
+                self.score += bonus;
+                self.events.push(GameEvent::PerfectClear { lines, score: bonus });
+            }
+        } else if spawned {
+            garbage_fits = self.raise_incoming_garbage();
+        }
+
+        if self.lines_remaining() == Some(0) {
+            self.end = Some(GameEnd::Completed);
+        } else if !spawned || !garbage_fits {
+            self.end = Some(GameEnd::ToppedOut);
+        }
+
+        self.end.is_none()
+    }
+
+    /// Uses the given lines of attack to cancel incoming garbage, oldest first, and sends the rest
+    /// to the opponent.
+    fn send_attack(&mut self, lines: u32) {
+        let mut attack = lines;
+        while attack > 0 {
+            let garbage = match self.incoming_garbage.front_mut() {
+                Some(garbage) => garbage,
+                None => break
+            };
+            let cancelled = attack.min(garbage.lines);
+            garbage.lines -= cancelled;
+            attack -= cancelled;
+            if garbage.lines == 0 {
+                self.incoming_garbage.pop_front();
+            }
+        }
+        self.outgoing_attack += attack;
+    }
+
+    /// Raises the incoming garbage whose delay has passed into the matrix. Each attack rises with
+    /// its hole in a single column. Returns `false` if the garbage caused a game over, or `true`
+    /// otherwise.
+    fn raise_incoming_garbage(&mut self) -> bool {
+        let mut success = true;
+        while self.incoming_garbage.front().is_some_and(|garbage| garbage.time_to_rise == 0) {
+            if let Some(garbage) = self.incoming_garbage.pop_front() {
+                let holes = self.incoming_garbage_generator.next_holes();
+                for _ in 0..garbage.lines {
+                    success &= self.game_state.add_garbage_row(&holes);
+                }
+            }
+        }
+        self.update_time_state();
+        success
+    }
+
+    /// Returns the level the game is on after the given number of lines have been cleared.
+    fn level_at(&self, lines_cleared: usize) -> u32 {
+        match self.mode {
+            GameMode::Marathon { start_level, .. } => {
+                // A start level out of range would skip the cap or overflow the score.
+                let start_level = start_level.clamp(1, MARATHON_MAX_LEVEL);
+                let level_ups = (lines_cleared / MARATHON_LINES_PER_LEVEL).min(MARATHON_MAX_LEVEL as usize) as u32;
+                (start_level + level_ups).min(MARATHON_MAX_LEVEL)
+            },
+            _ => 1,
+        }
+    }
+
     /// Adjusts the time state after a change in the game state.
     ///
     /// The time state action must be TimeStateAction::Falling if the current tetromino is off
@@ -590,7 +1373,7 @@ impl TimedGameState {
             if let TimeStateAction::Locking = self.time_state.action {
                 // Start a new cycle of falling
                 self.time_state.action = TimeStateAction::Falling {
-                    time_to_fall: FALL_INTERVAL
+                    time_to_fall: self.fall_interval()
                 };
             }
         } else {
@@ -599,3 +1382,320 @@ impl TimedGameState {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use ::builder::{tetris_ready, GameStateBuilder};
+    use ::config::MIN_MATRIX_HEIGHT;
+
+    #[test]
+    fn holds_once_until_the_tetromino_locks() {
+        let mut gs = GameStateBuilder::new().falling("T").queue("IOSZ").build().unwrap();
+
+        gs.hold();
+        assert_eq!(gs.held(), &Some(::TetrominoType::T));
+        assert_eq!(gs.falling_tetromino().ttype(), &::TetrominoType::I);
+        assert!(!gs.can_hold());
+
+        // The I came from the preview rather than the hold, but it cannot be held either.
+        let before = gs.clone();
+        gs.hold();
+        assert_eq!(gs.held(), before.held());
+        assert_eq!(gs.falling_tetromino(), before.falling_tetromino());
+        assert_eq!(gs.next_preview(), before.next_preview());
+
+        assert!(gs.hard_drop());
+        assert!(gs.can_hold());
+        gs.hold();
+        assert_eq!(gs.held(), &Some(::TetrominoType::O));
+        assert_eq!(gs.falling_tetromino().ttype(), &::TetrominoType::T);
+    }
+
+    #[test]
+    fn sends_attack_when_no_garbage_is_incoming() {
+        let mut tgs = TimedGameState::with_game_state(tetris_ready(), GameMode::Endless);
+        tgs.hard_drop();
+        assert_eq!(tgs.take_attack(), 4);
+        assert_eq!(tgs.take_attack(), 0);
+    }
+
+    #[test]
+    fn cancels_incoming_garbage_oldest_first() {
+        let mut tgs = TimedGameState::with_game_state(tetris_ready(), GameMode::Endless);
+        tgs.receive_garbage(2);
+        tgs.receive_garbage(3);
+        tgs.hard_drop();
+        assert_eq!(tgs.pending_garbage(), 1);
+        assert_eq!(tgs.take_attack(), 0);
+    }
+
+    #[test]
+    fn sends_what_is_left_after_cancelling() {
+        let mut tgs = TimedGameState::with_game_state(tetris_ready(), GameMode::Endless);
+        tgs.receive_garbage(3);
+        tgs.hard_drop();
+        assert_eq!(tgs.pending_garbage(), 0);
+        assert_eq!(tgs.take_attack(), 1);
+    }
+
+    #[test]
+    fn ignores_actions_after_the_game_ended() {
+        let mut tgs = TimedGameState::with_game_state(tetris_ready(), GameMode::Ultra { time_limit: 100 });
+        assert!(!tgs.advance_time(100));
+        assert_eq!(tgs.end(), Some(&GameEnd::TimeUp));
+
+        let falling = tgs.falling_tetromino().clone();
+        assert!(!tgs.move_left());
+        assert!(!tgs.move_right());
+        assert!(!tgs.rotate_left());
+        assert!(!tgs.rotate_right());
+        assert!(!tgs.soft_drop());
+        tgs.hold();
+        assert!(!tgs.apply_input(Input::HardDrop));
+        assert!(!tgs.hard_drop());
+
+        assert_eq!(tgs.falling_tetromino(), &falling);
+        assert_eq!(tgs.held(), &None);
+        assert_eq!(tgs.score(), 0);
+        assert_eq!(tgs.game_state().lines_cleared(), 0);
+        assert_eq!(tgs.end(), Some(&GameEnd::TimeUp));
+    }
+
+    #[test]
+    fn soft_drops_to_the_ground_on_a_soft_drop_input() {
+        let game = || {
+            let gs = GameStateBuilder::new().board("GGGG..GGGG").falling("T").queue("IOSZ").build().unwrap();
+            let mut tgs = TimedGameState::with_game_state(gs, GameMode::Endless);
+            assert!(tgs.advance_time(tgs.fall_interval() / 2));
+            tgs
+        };
+        let mut by_input = game();
+        let mut by_soft_drops = game();
+
+        assert!(by_input.apply_input(Input::SoftDrop));
+        while by_soft_drops.soft_drop() {}
+        assert_eq!(by_input.falling_tetromino(), by_soft_drops.falling_tetromino());
+        assert_eq!(by_input.falling_tetromino().center.1, 1);
+        assert_eq!(by_input.time_state().time_to_lock, by_soft_drops.time_state().time_to_lock);
+        assert!(!by_input.soft_drop());
+    }
+
+    /// A game of the given mode where each of the three I tetrominoes in a row can clear four
+    /// lines in the well on the right.
+    fn three_tetrises_ready(mode: GameMode) -> TimedGameState {
+        let gs = GameStateBuilder::new()
+            .board(&format!("G.........\n{}", "GGGGGGGGG.\n".repeat(12)))
+            .falling("I")
+            .queue("IIIT")
+            .build()
+            .unwrap();
+        TimedGameState::with_game_state(gs, mode)
+    }
+
+    /// Turns the falling I upright, moves it into the well on the right and drops it.
+    fn drop_i_into_the_well(tgs: &mut TimedGameState) -> bool {
+        tgs.rotate_right();
+        while tgs.move_right() {}
+        tgs.hard_drop()
+    }
+
+    #[test]
+    fn levels_up_every_ten_lines_up_to_the_highest_level() {
+        let tgs = TimedGameState::with_mode(GameMode::marathon(3));
+        assert_eq!(tgs.level(), 3);
+        assert_eq!(tgs.level_at(9), 3);
+        assert_eq!(tgs.level_at(10), 4);
+        assert_eq!(tgs.level_at(25), 5);
+        assert_eq!(tgs.level_at(1000), MARATHON_MAX_LEVEL);
+    }
+
+    #[test]
+    fn keeps_the_start_level_within_the_marathon_levels() {
+        let tgs = TimedGameState::with_mode(GameMode::marathon(50_000_000));
+        assert_eq!(tgs.level(), MARATHON_MAX_LEVEL);
+        assert_eq!(tgs.level_at(usize::MAX), MARATHON_MAX_LEVEL);
+        assert_eq!(TimedGameState::with_mode(GameMode::marathon(0)).level(), 1);
+    }
+
+    #[test]
+    fn scores_lines_on_the_level_they_were_cleared_on() {
+        let mut tgs = three_tetrises_ready(GameMode::marathon(1));
+        for _ in 0..3 {
+            assert!(drop_i_into_the_well(&mut tgs));
+        }
+
+        // The third tetris reached 12 lines and level 2, but was cleared on level 1. The second
+        // and third ones are back-to-back, and continue the combo.
+        assert_eq!(tgs.game_state().lines_cleared(), 12);
+        assert_eq!(tgs.level(), 2);
+        assert_eq!(tgs.score(), 800 + (1200 + scoring::COMBO_SCORE) + (1200 + 2 * scoring::COMBO_SCORE));
+        assert_eq!(tgs.fall_interval(), level_fall_interval(2));
+    }
+
+    #[test]
+    fn completes_a_marathon_at_the_line_goal() {
+        let mut tgs = three_tetrises_ready(GameMode::Marathon { start_level: 1, line_goal: Some(8) });
+        assert!(drop_i_into_the_well(&mut tgs));
+        assert_eq!(tgs.lines_remaining(), Some(4));
+        assert!(!drop_i_into_the_well(&mut tgs));
+        assert_eq!(tgs.lines_remaining(), Some(0));
+        assert_eq!(tgs.end(), Some(&GameEnd::Completed));
+    }
+
+    #[test]
+    fn follows_the_marathon_gravity_curve() {
+        assert_eq!(level_fall_interval(0), 1000);
+        assert_eq!(level_fall_interval(1), 1000);
+        assert_eq!(level_fall_interval(2), 793);
+        assert_eq!(level_fall_interval(10), 64);
+        assert_eq!(level_fall_interval(MARATHON_MAX_LEVEL), 7);
+        assert_eq!(TimedGameState::with_mode(GameMode::marathon(2)).fall_interval(), 793);
+        assert_eq!(TimedGameState::with_mode(GameMode::Endless).fall_interval(), FALL_INTERVAL);
+    }
+
+    #[test]
+    fn ends_an_ultra_game_exactly_at_the_time_limit() {
+        let mut tgs = TimedGameState::with_mode(GameMode::Ultra { time_limit: 1500 });
+        assert!(tgs.advance_time(1000));
+        assert_eq!(tgs.time_remaining(), Some(500));
+        assert_eq!(tgs.end(), None);
+
+        assert!(!tgs.advance_time(10_000));
+        assert_eq!(tgs.elapsed(), 1500);
+        assert_eq!(tgs.time_remaining(), Some(0));
+        assert_eq!(tgs.end(), Some(&GameEnd::TimeUp));
+        assert_eq!(tgs.result().map(|result| result.time), Some(1500));
+    }
+
+    #[test]
+    fn writes_a_game_result() {
+        let result = GameResult {
+            end: GameEnd::TimeUp,
+            score: 12_300,
+            level: 1,
+            lines_cleared: 40,
+            pieces_placed: 102,
+            time: 120_045
+        };
+        assert_eq!(result.to_string(), "\
+            TIME UP\n\
+            score:  12300\n\
+            level:  1\n\
+            lines:  40\n\
+            pieces: 102\n\
+            time:   120.045 s");
+    }
+
+    fn survival(garbage_interval: u32, min_garbage_interval: u32) -> GameMode {
+        GameMode::Survival { garbage_interval, min_garbage_interval, messiness: 0.0 }
+    }
+
+    #[test]
+    fn raises_garbage_after_the_survival_interval() {
+        let mut tgs = TimedGameState::with_mode(survival(1000, 500));
+        assert!(tgs.advance_time(999));
+        assert_eq!(tgs.game_state().garbage_row_count(), 0);
+        assert!(tgs.advance_time(1));
+        assert_eq!(tgs.game_state().garbage_row_count(), 1);
+    }
+
+    #[test]
+    fn shortens_the_survival_interval_down_to_the_minimum() {
+        let mut tgs = TimedGameState::with_mode(survival(1000, 900));
+        let mut intervals = Vec::new();
+        for _ in 0..4 {
+            let interval = tgs.time_state().time_to_garbage.unwrap();
+            intervals.push(interval);
+            assert!(tgs.advance_time(interval));
+        }
+        assert_eq!(intervals, vec![1000, 950, 902, 900]);
+        assert_eq!(tgs.time_state().time_to_garbage, Some(900));
+        assert_eq!(tgs.game_state().garbage_row_count(), 4);
+    }
+
+    #[test]
+    fn raises_the_same_garbage_for_the_same_seed() {
+        let mode = GameMode::Survival { garbage_interval: 100, min_garbage_interval: 100, messiness: 0.5 };
+        let mut first = TimedGameState::with_game_state(GameState::with_seed(5), mode.clone());
+        let mut second = TimedGameState::with_game_state(GameState::with_seed(5), mode);
+        for _ in 0..8 {
+            first.advance_time(100);
+            second.advance_time(100);
+        }
+        assert_eq!(first.game_state().garbage_row_count(), 8);
+        assert_eq!(first.placed_squares(), second.placed_squares());
+    }
+
+    #[test]
+    fn tops_out_when_garbage_rises_into_a_full_matrix() {
+        // The T rests right where it spawned, and the garbage rises before it locks.
+        let gs = GameStateBuilder::new()
+            .board(&"G.GGGGGGGG\n".repeat(20))
+            .falling("T")
+            .build()
+            .unwrap();
+        let mut tgs = TimedGameState::with_game_state(gs, survival(500, 500));
+        assert!(!tgs.advance_time(LOCK_INTERVAL));
+        assert_eq!(tgs.elapsed(), 500);
+        assert_eq!(tgs.end(), Some(&GameEnd::ToppedOut));
+    }
+
+    #[test]
+    fn counts_the_garbage_rows_left_to_dig() {
+        let tgs = TimedGameState::with_mode(GameMode::Dig { garbage_rows: 5, holes: 1, messiness: 0.0 });
+        assert_eq!(tgs.game_state().garbage_row_count(), 5);
+        assert_eq!(tgs.lines_remaining(), Some(5));
+    }
+
+    #[test]
+    fn digs_through_the_same_garbage_for_the_same_seed() {
+        let dig = GameMode::Dig { garbage_rows: 10, holes: 1, messiness: 0.5 };
+        let first = TimedGameState::with_game_state(GameState::with_seed(3), dig.clone());
+        let second = TimedGameState::with_game_state(GameState::with_seed(3), dig);
+        assert_eq!(first.placed_squares(), second.placed_squares());
+    }
+
+    #[test]
+    fn starts_a_dig_game_with_at_least_one_garbage_row() {
+        let mut tgs = TimedGameState::with_game_state(
+            GameStateBuilder::new().falling("T").build().unwrap(),
+            GameMode::Dig { garbage_rows: 0, holes: 1, messiness: 0.0 }
+        );
+        assert_eq!(tgs.lines_remaining(), Some(1));
+        assert!(tgs.hard_drop());
+        assert_eq!(tgs.end(), None);
+
+        let config = GameConfig { matrix_height: MIN_MATRIX_HEIGHT, ..GameConfig::default() };
+        let mut tgs = TimedGameState::with_game_state(
+            GameStateBuilder::new().config(config).falling("T").build().unwrap(),
+            GameMode::dig()
+        );
+        assert_eq!(tgs.lines_remaining(), Some(1));
+        // The next tetromino has no room to spawn, but the garbage is still there to dig.
+        tgs.hard_drop();
+        assert_ne!(tgs.end(), Some(&GameEnd::Completed));
+    }
+
+    #[test]
+    fn completes_a_dig_game_once_the_last_garbage_row_is_cleared() {
+        let mut tgs = TimedGameState::with_game_state(
+            GameStateBuilder::new().falling("I").build().unwrap(),
+            GameMode::Dig { garbage_rows: 1, holes: 1, messiness: 0.0 }
+        );
+        let hole = (0..tgs.game_state().matrix_width())
+            .find(|&col| tgs.placed_squares()[col][0].is_empty())
+            .unwrap() as isize;
+
+        // Turn the I upright and drop it into the hole.
+        tgs.rotate_right();
+        while tgs.falling_tetromino().center().0 > hole && tgs.move_left() {}
+        while tgs.falling_tetromino().center().0 < hole && tgs.move_right() {}
+        assert!(!tgs.hard_drop());
+
+        assert_eq!(tgs.game_state().garbage_row_count(), 0);
+        assert_eq!(tgs.lines_remaining(), Some(0));
+        assert_eq!(tgs.end(), Some(&GameEnd::Completed));
+    }
+}
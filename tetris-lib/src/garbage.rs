@@ -0,0 +1,107 @@
+extern crate rand;
+use self::rand::{FromEntropy, Rng, SeedableRng};
+use self::rand::rngs::StdRng;
+
+use ::game_state::MATRIX_WIDTH;
+
+/// An object that randomly decides where the holes are in consecutive rows of garbage.
+#[derive(Debug, Clone)]
+pub struct GarbageGenerator {
+    /// The number of columns of the matrix.
+    width: usize,
+    /// The number of holes in each row.
+    holes: usize,
+    /// The chance, from 0 to 1, that the holes move to other columns between one row and the next.
+    messiness: f64,
+    /// The columns of the holes in the most recently generated row.
+    previous_holes: Vec<usize>,
+    /// The source of randomness for the columns of the holes.
+    rng: StdRng
+}
+
+impl GarbageGenerator {
+
+    /// Creates a new `GarbageGenerator` giving rows with `holes` holes each, where `messiness`
+    /// is the chance, from 0 to 1, that the holes move between one row and the next.
+    pub fn new(holes: usize, messiness: f64) -> Self {
+        GarbageGenerator::with_width(MATRIX_WIDTH, holes, messiness, None)
+    }
+
+    /// Creates a new `GarbageGenerator` like `new`, for a matrix with the given number of
+    /// columns. With a seed, the holes are always in the same columns for the same seed.
+    pub fn with_width(width: usize, holes: usize, messiness: f64, seed: Option<u64>) -> Self {
+        let rng = match seed {
+            Some(seed) => StdRng::seed_from_u64(seed),
+            None => StdRng::from_entropy(),
+        };
+        GarbageGenerator {
+            width,
+            holes: holes.clamp(1, width - 1),
+            messiness,
+            previous_holes: Vec::new(),
+            rng
+        }
+    }
+
+    /// Returns the columns of the holes in the next row of garbage.
+    pub fn next_holes(&mut self) -> Vec<usize> {
+        if self.previous_holes.is_empty() || self.rng.gen::<f64>() < self.messiness {
+            // A moved hole lands in a different column than before, unless there are too many
+            // holes for that to be possible.
+            let avoid_previous = self.holes * 2 <= self.width;
+            let mut holes = Vec::with_capacity(self.holes);
+            while holes.len() < self.holes {
+                let col = self.rng.gen_range(0, self.width);
+                if !(holes.contains(&col) || avoid_previous && self.previous_holes.contains(&col)) {
+                    holes.push(col);
+                }
+            }
+            holes.sort();
+            self.previous_holes = holes;
+        }
+
+        self.previous_holes.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keeps_at_least_one_hole_and_one_square_per_row() {
+        assert_eq!(GarbageGenerator::new(0, 1.0).next_holes().len(), 1);
+        assert_eq!(GarbageGenerator::new(15, 1.0).next_holes().len(), MATRIX_WIDTH - 1);
+        assert_eq!(GarbageGenerator::with_width(4, 3, 1.0, None).next_holes().len(), 3);
+    }
+
+    #[test]
+    fn keeps_the_holes_in_place_without_messiness() {
+        let mut generator = GarbageGenerator::new(2, 0.0);
+        let first = generator.next_holes();
+        assert_eq!(first.len(), 2);
+        for _ in 0..20 {
+            assert_eq!(generator.next_holes(), first);
+        }
+    }
+
+    #[test]
+    fn moves_the_holes_with_full_messiness() {
+        let mut generator = GarbageGenerator::new(1, 1.0);
+        let mut previous = generator.next_holes();
+        for _ in 0..20 {
+            let holes = generator.next_holes();
+            assert_ne!(holes, previous);
+            previous = holes;
+        }
+    }
+
+    #[test]
+    fn the_same_seed_gives_the_same_holes() {
+        let mut first = GarbageGenerator::with_width(MATRIX_WIDTH, 2, 0.5, Some(7));
+        let mut second = GarbageGenerator::with_width(MATRIX_WIDTH, 2, 0.5, Some(7));
+        for _ in 0..50 {
+            assert_eq!(first.next_holes(), second.next_holes());
+        }
+    }
+}
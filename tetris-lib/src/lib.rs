@@ -1,8 +1,92 @@
+#[macro_use] extern crate serde;
+extern crate serde_json;
+
 mod bag;
 
+pub mod attack;
+pub mod bot;
+pub mod builder;
+pub mod config;
+pub mod fumen;
+pub mod game_mode;
+pub mod garbage;
 pub mod game_state;
+pub mod pc_solver;
+pub mod placement;
+pub mod scoring;
+pub mod tbp;
 pub mod tetromino_data;
+pub mod versus;
+pub mod view;
 
 /// Identifies one of the 7 types of tetrominoes
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub enum TetrominoType { I, O, T, J, L, S, Z, }
+
+impl TetrominoType {
+
+    /// Returns the tetromino type named by the given letter, which may be upper or lower case, or
+    /// `None` if it names none.
+    pub fn from_char(c: char) -> Option<TetrominoType> {
+        match c.to_ascii_uppercase() {
+            'I' => Some(TetrominoType::I),
+            'O' => Some(TetrominoType::O),
+            'T' => Some(TetrominoType::T),
+            'J' => Some(TetrominoType::J),
+            'L' => Some(TetrominoType::L),
+            'S' => Some(TetrominoType::S),
+            'Z' => Some(TetrominoType::Z),
+            _ => None,
+        }
+    }
+
+    /// Returns the upper case letter naming this tetromino type.
+    pub fn to_char(&self) -> char {
+        match *self {
+            TetrominoType::I => 'I',
+            TetrominoType::O => 'O',
+            TetrominoType::T => 'T',
+            TetrominoType::J => 'J',
+            TetrominoType::L => 'L',
+            TetrominoType::S => 'S',
+            TetrominoType::Z => 'Z',
+        }
+    }
+}
+
+/// Describes what occupies a single square of the matrix.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub enum Cell {
+    /// Nothing has been placed on the square.
+    Empty,
+    /// The square is part of a locked tetromino of the given type.
+    Piece(TetrominoType),
+    /// The square is part of a row of garbage.
+    Garbage,
+    /// The square is part of a locked tetromino of the given type, but is not shown to the
+    /// player.
+    Invisible(TetrominoType),
+    /// The square holds an item block.
+    Item,
+}
+
+impl Cell {
+
+    /// Returns `true` if nothing occupies the square.
+    pub fn is_empty(&self) -> bool {
+        *self == Cell::Empty
+    }
+
+    /// Returns `true` if the square should be shown to the player as occupied.
+    pub fn is_visible(&self) -> bool {
+        !matches!(*self, Cell::Empty | Cell::Invisible(_))
+    }
+
+    /// Returns the type of the tetromino this square came from, if any.
+    pub fn tetromino_type(&self) -> Option<&TetrominoType> {
+        match *self {
+            Cell::Piece(ref tt) | Cell::Invisible(ref tt) => Some(tt),
+            _ => None,
+        }
+    }
+}
@@ -1,216 +1,231 @@
-extern crate tetris;
+#[macro_use] extern crate crossterm;
 #[macro_use] extern crate prettytable;
+extern crate tetris;
 
-// use colored::*;
-use std::io::BufRead;
+mod cli;
 
-// fn tetromino_type_to_color(tt: &tetris::TetrominoType) -> colored::Color {
-//     match *tt {
-//         tetris::TetrominoType::I => colored::Color::Cyan,
-//         tetris::TetrominoType::J => colored::Color::Blue,
-//         tetris::TetrominoType::L => colored::Color::BrightRed,
-//         tetris::TetrominoType::O => colored::Color::Yellow,
-//         tetris::TetrominoType::S => colored::Color::Green,
-//         tetris::TetrominoType::T => colored::Color::Magenta,
-//         tetris::TetrominoType::Z => colored::Color::Red,
-//     }
-// }
-
-
-//fn print_game_state(gs: &tetris::game_state::GameState) {
-//
-//    let hold_display = render_hold_display(gs);
-//    let matrix_display = render_matrix_display(gs);
-//    let piece_queue_display = render_next_preview(gs);
-//
-//    let mut table = table![
-//        [hold_display, matrix_display, piece_queue_display]
-//    ];
-//
-//    table.set_format(*prettytable::format::consts::FORMAT_CLEAN);
-//
-//    table.printstd();
-//}
-
-fn print_timed_game_state(tgs: &tetris::game_state::TimedGameState) {
+use std::fs;
+use std::io::BufRead;
 
+use cli::color::ColorMode;
+use cli::render;
 
-    let gs = tgs.game_state();
-    let hold_display = render_hold_display(gs);
-    let matrix_display = render_matrix_display(gs);
-    let piece_queue_display = render_next_preview(gs);
-    let time_display = render_time_display(tgs);
+fn print_timed_game_state(tgs: &tetris::game_state::TimedGameState, colors: ColorMode) {
+    render::render_timed_game_state(tgs, colors).printstd();
+}
 
+fn print_help(step: u32) {
+    println!("Available commands: ");
     let mut table = table![
-        [hold_display, matrix_display, piece_queue_display, time_display]
+        ["?", "print this help"],
+        ["[empty]", format!("advance time by {} ms", step)],
+        ["l", "move left"],
+        ["r", "move right"],
+        ["rl", "rotate left"],
+        ["rr", "rotate right"],
+        ["hd", "hard drop"],
+        ["h", "hold"],
+        ["pc", "search for a perfect clear"]
     ];
-
     table.set_format(*prettytable::format::consts::FORMAT_CLEAN);
-
     table.printstd();
 }
 
-fn render_time_display(tgs: &tetris::game_state::TimedGameState) -> prettytable::Table {
-
-    let time_state = tgs.time_state();
-    let mut table = table![
-        ["time to lock", format!("{} ms", time_state.time_to_lock)],
-        ["time to fall", match time_state.action {
-            tetris::game_state::TimeStateAction::Falling{time_to_fall} => format!("{} ms", time_to_fall),
-            tetris::game_state::TimeStateAction::Locking => format!("---")
-        }]
-    ];
-
-    table.set_format(*prettytable::format::consts::FORMAT_CLEAN);
-
-    table
+fn print_events(tgs: &mut tetris::game_state::TimedGameState) {
+    for event in tgs.take_events() {
+        println!("{}", render::render_event(&event));
+    }
 }
 
-fn render_hold_display(gs: &tetris::game_state::GameState) -> prettytable::Table {
-    let mut hold_table = table![
-        ["HOLD:"],
-        [table![[
-            if let Some(held) = gs.held() {
-                render_tetromino(&held)
-            } else {
-                String::from("        \n        \n")
+fn print_perfect_clear(gs: &tetris::game_state::GameState) {
+    match tetris::pc_solver::solve(gs, tetris::pc_solver::DEFAULT_MAX_LINES) {
+        Some(steps) => {
+            println!("perfect clear in {} pieces:", steps.len());
+            for step in steps {
+                let t = step.tetromino;
+                println!("{}{} at {:?}, orientation {}: {:?}",
+                         if step.hold { "hold, then " } else { "" },
+                         t.ttype().to_char(), t.center(), t.orientation(), step.inputs);
             }
-        ]]]
-    ];
-
-    hold_table.set_format(*prettytable::format::consts::FORMAT_CLEAN);
-
-    hold_table
+        },
+        None => println!("no perfect clear found"),
+    }
 }
 
-fn render_matrix_display(gs: &tetris::game_state::GameState) -> prettytable::Table {
-    let mut squares_to_print = gs.placed_squares().clone();
+fn load_fumen(fumen: &str, config: tetris::config::GameConfig) -> Result<tetris::game_state::GameState, String> {
+    let pages = tetris::fumen::decode(fumen).map_err(|e| e.to_string())?;
+    let page = pages.first().ok_or_else(|| String::from("fumen has no pages"))?;
+    tetris::builder::GameStateBuilder::new()
+        .config(config)
+        .fumen_page(page)
+        .build()
+        .map_err(|e| e.to_string())
+}
 
-    let falling_tetromino = gs.falling_tetromino();
+/// Lets the bot play the game until it ends, printing the game state after each tetromino.
+fn autoplay(tgs: &mut tetris::game_state::TimedGameState, colors: ColorMode) {
+    let bot = tetris::bot::Bot::default();
 
-    for (mino_col, mino_row) in falling_tetromino.minoes().iter() {
-        squares_to_print[*mino_col as usize][*mino_row as usize]
-            = Some(falling_tetromino.ttype().clone());
-    }
+    loop {
+        let inputs = match bot.choose(tgs.game_state()) {
+            Some(decision) => decision.to_inputs(),
+            // Every placement tops out, so any will do.
+            None => vec![tetris::placement::Input::HardDrop]
+        };
 
-    let mut matrix_display: String = String::new();
+        let continue_game = inputs.into_iter().all(|input| tgs.apply_input(input));
+        print_events(tgs);
+        print_timed_game_state(tgs, colors);
 
-    for ii in 0..tetris::game_state::MATRIX_HEIGHT {
-        let i = tetris::game_state::MATRIX_HEIGHT - 1 - ii;
-        matrix_display.push_str(" ");
-        for j in 0..tetris::game_state::MATRIX_WIDTH {
-            let c = if let Some(/*ref tt*/_) = squares_to_print[j][i] {
-                format!("{}", "▣ "/*.color(tetromino_type_to_color(&tt))*/)
-            } else { format!("{}",  "· "/*.white()*/) };
-            matrix_display.push_str(&c);
+        if !continue_game {
+            if let Some(result) = tgs.result() {
+                println!("{}", result);
+            }
+            break;
         }
-        matrix_display.push_str("\n");
+        println!();
+        std::thread::sleep(std::time::Duration::from_millis(250));
     }
-
-    table![[matrix_display]]
 }
 
-fn render_next_preview(gs: &tetris::game_state::GameState) -> prettytable::Table {
-
-    let next_preview = gs.next_preview();
+/// Runs the script at the given path against the game, then prints the final board. Returns the
+/// status code to exit with: 0 if every assertion held, 1 if one failed or the game ended before
+/// the script did, or 2 if the script could not be read or parsed.
+fn run_script(tgs: &mut tetris::game_state::TimedGameState, path: &str) -> i32 {
+    let script = match fs::read_to_string(path) {
+        Ok(script) => script,
+        Err(e) => {
+            eprintln!("could not read {}: {}", path, e);
+            return 2;
+        }
+    };
+    let steps = match cli::script::parse(&script) {
+        Ok(steps) => steps,
+        Err(e) => {
+            eprintln!("{}: {}", path, e);
+            return 2;
+        }
+    };
 
-    let next = next_preview[0].clone();
+    let status = match cli::script::run(tgs, &steps) {
+        Ok(()) => 0,
+        Err(e) => {
+            eprintln!("{}: {}", path, e);
+            1
+        }
+    };
 
-    let mut subsequent_table = prettytable::Table::new();
-    for tt in next_preview.iter().skip(1) {
-        subsequent_table.add_row(row![render_tetromino(&tt)]);
+    let gs = tgs.game_state();
+    print!("{}", gs.to_ascii());
+    println!("hold: {}", gs.held().as_ref().map_or('-', |tt| tt.to_char()));
+    println!("lines: {}", gs.lines_cleared());
+    println!("score: {}", tgs.score());
+    if let Some(result) = tgs.result() {
+        println!("{}", result);
     }
+    status
+}
 
-    let mut next_preview_table = table![
-        [table![[render_tetromino(&next)]]],
-        [subsequent_table]
-    ];
+fn main() {
 
-    next_preview_table.set_format(*prettytable::format::consts::FORMAT_NO_BORDER);
+    let options = match cli::args::parse(std::env::args().skip(1)) {
+        Ok(options) => options,
+        Err(e) => {
+            eprintln!("{}\n\n{}", e, cli::args::USAGE);
+            std::process::exit(2);
+        }
+    };
 
-    let mut next_preview_table_with_label = table![
-        ["NEXT:"],
-        [next_preview_table]
-    ];
+    if options.help {
+        println!("{}", cli::args::USAGE);
+        return;
+    }
 
-    next_preview_table_with_label.set_format(*prettytable::format::consts::FORMAT_CLEAN);
+    let colors = options.colors;
 
-    next_preview_table_with_label
-}
+    // Scripts play the same game every time they are run, unless a seed is given.
+    let mut config = options.config.clone();
+    if options.script.is_some() && config.seed.is_none() {
+        config.seed = Some(0);
+    }
 
-fn render_tetromino(tt: &tetris::TetrominoType) -> String {
+    // A fumen can be given as an argument to start from the board it describes.
+    let gs = match options.fumen {
+        Some(ref fumen) => match load_fumen(fumen, config.clone()) {
+            Ok(gs) => gs,
+            Err(e) => {
+                eprintln!("could not load fumen: {}", e);
+                std::process::exit(1);
+            }
+        },
+        None => tetris::game_state::GameState::with_config(config)
+    };
+    let mut tgs = tetris::game_state::TimedGameState::with_game_state(gs, options.mode.clone());
 
-    let mut squares_to_print: Vec<Vec<bool>> = vec![vec![false; 2]; 4];
-    for (offset_col, offset_row) in tetris::tetromino_data::tetromino_shape_from(tt) {
-        squares_to_print[(*offset_col + 1) as usize][*offset_row as usize] = true;
+    if let Some(ref path) = options.script {
+        std::process::exit(run_script(&mut tgs, path));
     }
 
-    let mut tetromino_display = String::new();
+    if options.autoplay {
+        autoplay(&mut tgs, colors);
+        return;
+    }
 
-    for ii in 0..2 {
-        let i = 1 - ii;
-        for j in 0..4 {
-            let c = if squares_to_print[j][i] {
-                format!("{}", "▣ "/*.color(tetromino_type_to_color(&tt))*/)
-            } else { format!("{}",  "  "/*.white()*/) };
-            tetromino_display.push_str(&c);
+    if options.real_time {
+        if let Err(e) = cli::tui::run(&mut tgs, colors) {
+            eprintln!("terminal error: {}", e);
+            std::process::exit(1);
         }
-
-        tetromino_display.push_str("\n");
+        if let Some(result) = tgs.result() {
+            println!("{}", result);
+        }
+        return;
     }
 
-    tetromino_display
-}
-
-fn print_help() {
-    println!("Available commands: ");
-    let mut table = table![
-        ["?", "print this help"],
-        ["[empty]", "advance time by 150 ms"],
-        ["l", "move left"],
-        ["r", "move right"],
-        ["rl", "rotate left"],
-        ["rr", "rotate right"],
-        ["hd", "hard drop"],
-        ["h", "hold"]
-    ];
-    table.set_format(*prettytable::format::consts::FORMAT_CLEAN);
-    table.printstd();
-}
-
-fn main() {
-
     let stdin = std::io::stdin();
-    let mut tgs = tetris::game_state::TimedGameState::new();
 
-    print_help();
+    print_help(options.step);
 
-    print_timed_game_state(&tgs);
+    print_timed_game_state(&tgs, colors);
     println!("");
-    // TODO: proper error handling
-    for line in stdin.lock().lines().map(|l| l.unwrap()) {
+    for line in stdin.lock().lines() {
+        let line = match line {
+            Ok(line) => line,
+            Err(e) => {
+                eprintln!("could not read command: {}", e);
+                std::process::exit(1);
+            }
+        };
+
         let mut should_print_help = false;
+        let mut should_print_perfect_clear = false;
         let continue_game = match line.as_str() {
             "?" => { should_print_help = true; true }
-            "" => { tgs.advance_time(150) }
+            "" => { tgs.advance_time(options.step) }
             "l" => { tgs.move_left(); true }
             "r" => { tgs.move_right(); true }
             "rl" => { tgs.rotate_left(); true }
             "rr" => { tgs.rotate_right(); true }
             "hd" => { tgs.hard_drop() }
             "h" => { tgs.hold(); true }
+            "pc" => { should_print_perfect_clear = true; true }
             _ => { println!("unknown command"); true }
         };
         if should_print_help {
-            print_help();
+            print_help(options.step);
+        } else if should_print_perfect_clear {
+            print_perfect_clear(tgs.game_state());
         } else {
-            print_timed_game_state(&tgs);
+            print_events(&mut tgs);
+            print_timed_game_state(&tgs, colors);
         }
 
         if !continue_game {
-            println!("GAME OVER");
+            if let Some(result) = tgs.result() {
+                println!("{}", result);
+            }
             break;
         }
         println!("");
     }
-}
\ No newline at end of file
+}
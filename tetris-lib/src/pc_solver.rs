@@ -0,0 +1,273 @@
+//! Searching for sequences of placements that leave the matrix empty, known as perfect clears.
+//!
+//! The search only uses tetrominoes that are known in advance: the falling one, the held one and
+//! the ones in the next preview. Every placement that can be reached from where the tetromino
+//! spawns is tried, including tucks and spins.
+
+use std::collections::HashSet;
+
+use ::game_state::{GameState, Tetromino};
+use ::placement::{self, Input};
+
+/// The number of lines a perfect clear is searched within, unless another limit is chosen.
+pub const DEFAULT_MAX_LINES: usize = 4;
+
+/// One placement of a perfect clear solution.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Step {
+    /// Whether the falling tetromino is held before placing, so that the held (or next) one is
+    /// placed instead.
+    pub hold: bool,
+    /// The tetromino as it is locked on the matrix.
+    pub tetromino: Tetromino,
+    /// The inputs that bring the tetromino from where it spawns to where it is locked.
+    pub inputs: Vec<Input>
+}
+
+/// Searches for a sequence of placements that clears every square of the matrix within
+/// `max_lines` lines, using only the tetrominoes known from the given `GameState`. Returns the
+/// steps of the first solution found, preferring ones that clear fewer lines, or `None` if there
+/// is no solution. Matrices wider than 64 columns are not searched.
+pub fn solve(gs: &GameState, max_lines: usize) -> Option<Vec<Step>> {
+    let filled = gs.placed_squares().iter()
+        .map(|column| column.iter().filter(|square| !square.is_empty()).count())
+        .sum::<usize>();
+    let (width, height) = (gs.matrix_width(), gs.matrix_height());
+    // Rows are remembered as the bits of a `u64`.
+    if width > 64 {
+        return None;
+    }
+
+    let highest_filled_row = (0..height).rev()
+        .find(|&row| (0..width).any(|col| !gs.placed_squares()[col][row].is_empty()));
+    let known = 1 + gs.next_preview().len() + gs.held().iter().count();
+
+    for lines in 1..=max_lines.min(height) {
+        if highest_filled_row.is_some_and(|row| row >= lines) {
+            continue;
+        }
+
+        let empty = lines * width - filled;
+        if empty == 0 || empty % 4 != 0 || empty / 4 > known || !empty_regions_fit(gs, lines) {
+            continue;
+        }
+
+        let mut search = Search { failed: HashSet::new() };
+        let mut steps = Vec::new();
+        if search.run(gs, lines, gs.next_preview().len(), &mut steps) {
+            return Some(steps);
+        }
+    }
+
+    None
+}
+
+/// What identifies a position of the search: the occupied squares of each row below the height
+/// limit, the falling and held tetromino types, the number of known tetrominoes left in the
+/// preview, and the height limit.
+type SearchKey = (Vec<u64>, ::TetrominoType, Option<::TetrominoType>, usize, usize);
+
+/// The state of a depth-first search for a perfect clear.
+struct Search {
+    /// Positions from which no perfect clear could be found.
+    failed: HashSet<SearchKey>
+}
+
+impl Search {
+
+    /// Searches for placements that empty the matrix from `gs`, without filling squares at or
+    /// above row `lines`. `known` is the number of tetrominoes at the front of the preview that
+    /// were known when the search started. Pushes the placements found onto `steps` and returns
+    /// `true` if there is a solution.
+    fn run(&mut self, gs: &GameState, lines: usize, known: usize, steps: &mut Vec<Step>) -> bool {
+        let key = (
+            rows(gs, lines),
+            gs.falling_tetromino().ttype().clone(),
+            gs.held().clone(),
+            known,
+            lines
+        );
+        if self.failed.contains(&key) {
+            return false;
+        }
+
+        for &hold in &[false, true] {
+            let mut start = gs.clone();
+            let mut start_known = known;
+            if hold {
+                if !gs.can_hold() {
+                    continue;
+                }
+                match *gs.held() {
+                    Some(ref held) if held == gs.falling_tetromino().ttype() => continue,
+                    Some(_) => {},
+                    // Holding into an empty hold brings out the next tetromino, which must be
+                    // known.
+                    None if known == 0 => continue,
+                    None => start_known -= 1,
+                }
+                start.hold();
+            }
+
+            for placement in placement::placements(&start) {
+                let placement::Placement { tetromino, inputs } = placement;
+                if tetromino.minoes().iter().any(|&(_, row)| row as usize >= lines) {
+                    continue;
+                }
+
+                let mut next = start.clone();
+                for &input in &inputs {
+                    next.apply_input(input);
+                }
+                let spawned = next.lock_piece();
+                let cleared = next.last_lines_cleared();
+                steps.push(Step { hold, tetromino, inputs });
+
+                if next.is_matrix_empty() {
+                    return true;
+                }
+
+                // Going on requires the tetromino that just spawned to be known.
+                if spawned && start_known > 0 && empty_regions_fit(&next, lines - cleared)
+                    && self.run(&next, lines - cleared, start_known - 1, steps) {
+                    return true;
+                }
+
+                steps.pop();
+            }
+        }
+
+        self.failed.insert(key);
+        false
+    }
+}
+
+/// Returns the occupied squares of each row below `lines`, as bits from the leftmost column up.
+fn rows(gs: &GameState, lines: usize) -> Vec<u64> {
+    (0..lines).map(|row| {
+        (0..gs.matrix_width())
+            .filter(|&col| !gs.placed_squares()[col][row].is_empty())
+            .fold(0, |bits, col| bits | 1 << col)
+    }).collect()
+}
+
+/// Returns `false` if the empty squares below row `lines` form a region whose size is not a
+/// multiple of 4, which no set of tetrominoes could fill exactly.
+fn empty_regions_fit(gs: &GameState, lines: usize) -> bool {
+    let width = gs.matrix_width();
+    let mut visited = vec![vec![false; lines]; width];
+
+    for col in 0..width {
+        for row in 0..lines {
+            if visited[col][row] || !gs.placed_squares()[col][row].is_empty() {
+                continue;
+            }
+
+            let mut size: usize = 0;
+            let mut stack = vec![(col, row)];
+            visited[col][row] = true;
+            while let Some((c, r)) = stack.pop() {
+                size += 1;
+                let neighbours = [
+                    (c.wrapping_sub(1), r), (c + 1, r), (c, r.wrapping_sub(1)), (c, r + 1)
+                ];
+                for &(nc, nr) in &neighbours {
+                    if nc < width && nr < lines && !visited[nc][nr]
+                        && gs.placed_squares()[nc][nr].is_empty() {
+                        visited[nc][nr] = true;
+                        stack.push((nc, nr));
+                    }
+                }
+            }
+
+            if size % 4 != 0 {
+                return false;
+            }
+        }
+    }
+
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use ::builder::GameStateBuilder;
+
+    /// A well one column wide and four rows deep, on the right of the matrix.
+    const WELL: &str = "\
+        GGGGGGGGG.
+        GGGGGGGGG.
+        GGGGGGGGG.
+        GGGGGGGGG.";
+
+    /// Checks that making the steps of a solution leaves the matrix empty.
+    fn assert_solves(gs: &GameState, steps: &[Step]) {
+        let mut gs = gs.clone();
+        for step in steps {
+            if step.hold {
+                gs.hold();
+            }
+            for &input in &step.inputs {
+                gs.apply_input(input);
+            }
+            assert_eq!(gs.falling_tetromino(), &step.tetromino);
+            gs.hard_drop();
+        }
+        assert!(gs.is_matrix_empty(), "{}", gs.to_ascii());
+    }
+
+    #[test]
+    fn solves_a_four_line_perfect_clear() {
+        // The four columns on the right can be filled by an I standing against the wall, an O at the
+        // bottom and two Ls around the rest.
+        let gs = GameStateBuilder::new()
+            .board("\
+                GGGGGG....
+                GGGGGG....
+                GGGGGG....
+                GGGGGG....")
+            .queue("IOLL")
+            .build()
+            .unwrap();
+
+        let steps = solve(&gs, DEFAULT_MAX_LINES).expect("the perfect clear is found");
+        assert_eq!(steps.len(), 4);
+        assert_solves(&gs, &steps);
+    }
+
+    #[test]
+    fn rejects_empty_regions_no_tetrominoes_can_fill() {
+        // 8 empty squares, but split into regions of 5 and 3.
+        let gs = GameStateBuilder::new()
+            .board("\
+                ...GGGGG.G
+                ..GGGGGG..")
+            .queue("IOTLJSZ")
+            .build()
+            .unwrap();
+
+        assert_eq!(solve(&gs, DEFAULT_MAX_LINES), None);
+    }
+
+    #[test]
+    fn holds_to_use_the_tetromino_that_fits() {
+        let gs = GameStateBuilder::new().board(WELL).falling("O").hold("I").build().unwrap();
+
+        let steps = solve(&gs, DEFAULT_MAX_LINES).expect("holding the O brings out the I");
+        assert_eq!(steps.len(), 1);
+        assert!(steps[0].hold);
+        assert_solves(&gs, &steps);
+    }
+
+    #[test]
+    fn does_not_hold_twice_before_locking() {
+        let mut gs = GameStateBuilder::new().board(WELL).falling("I").queue("OOOOO").build().unwrap();
+        gs.hold();
+        assert!(!gs.can_hold());
+
+        // The I is in the hold, and cannot come back out until the O locks.
+        assert_eq!(solve(&gs, DEFAULT_MAX_LINES), None);
+    }
+}
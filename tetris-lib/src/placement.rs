@@ -0,0 +1,263 @@
+//! Finding every position the falling tetromino can come to rest in, and the inputs that bring
+//! it there.
+//!
+//! The positions are found with a breadth-first search over the inputs a player can make, using
+//! the same collision and kick logic as the `GameState` itself, so tucks and spins are included.
+
+use std::collections::{HashMap, HashSet, VecDeque};
+
+use ::game_state::{GameState, Tetromino};
+
+/// An input a player can make on the falling tetromino.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Input {
+    MoveLeft,
+    MoveRight,
+    RotateLeft,
+    RotateRight,
+    /// Moves the tetromino down as far as it can go, without locking it.
+    SoftDrop,
+    /// Drops the tetromino as far as it can go and locks it.
+    HardDrop,
+    Hold
+}
+
+/// The inputs that move the tetromino without locking or holding it, in the order they are tried
+/// when searching for placements.
+const MOVEMENT_INPUTS: [Input; 5] = [
+    Input::MoveLeft,
+    Input::MoveRight,
+    Input::RotateLeft,
+    Input::RotateRight,
+    Input::SoftDrop
+];
+
+/// A position the falling tetromino can come to rest in.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Placement {
+    /// The tetromino in its resting position.
+    pub tetromino: Tetromino,
+    /// The shortest sequence of inputs that brings the falling tetromino from where it is to its
+    /// resting position.
+    pub inputs: Vec<Input>
+}
+
+/// Returns every distinct position the falling tetromino of `gs` can come to rest in, each with
+/// the shortest sequence of inputs that reaches it. Positions that cover the same squares are
+/// only listed once, except that a T reached with a rotation as the last input is listed apart
+/// from one reached without, since only the former can be a T-spin.
+pub fn placements(gs: &GameState) -> Vec<Placement> {
+    let start = (gs.falling_tetromino().clone(), false);
+
+    // The input that first reached each search state, and the state it was made from.
+    let mut came_from: HashMap<SearchState, Option<(SearchState, Input)>> = HashMap::new();
+    came_from.insert(start.clone(), None);
+
+    let mut queue = VecDeque::new();
+    queue.push_back(start);
+
+    let mut seen: HashSet<(Vec<(isize, isize)>, bool)> = HashSet::new();
+    let mut result = Vec::new();
+
+    while let Some(state) = queue.pop_front() {
+        let (ref t, rotated) = state;
+        let resting = !gs.tetromino_fits(&moved(t, 0, -1));
+        if resting {
+            let mut minoes = t.minoes();
+            minoes.sort();
+            if seen.insert((minoes, rotated)) {
+                result.push(Placement { tetromino: t.clone(), inputs: path_to(&came_from, &state) });
+            }
+        }
+
+        for &input in &MOVEMENT_INPUTS {
+            if let Some(next) = after_input(gs, t, input) {
+                let spun = is_rotation(input) && *t.ttype() == ::TetrominoType::T;
+                let next = (next, spun);
+                if !came_from.contains_key(&next) {
+                    came_from.insert(next.clone(), Some((state.clone(), input)));
+                    queue.push_back(next);
+                }
+            }
+        }
+    }
+
+    result
+}
+
+/// A state of the search for placements: where the tetromino is, and whether it is a T whose last
+/// input was a rotation.
+type SearchState = (Tetromino, bool);
+
+/// Returns `true` if the given input rotates the tetromino.
+fn is_rotation(input: Input) -> bool {
+    input == Input::RotateLeft || input == Input::RotateRight
+}
+
+impl GameState {
+
+    /// Makes the given input on the falling tetromino. For inputs that move the tetromino, returns
+    /// `true` if it moved, or `false` otherwise. For `Input::HardDrop`, returns the same as
+    /// `hard_drop`, and for `Input::Hold`, always returns `true`.
+    pub fn apply_input(&mut self, input: Input) -> bool {
+        match input {
+            Input::MoveLeft => self.move_left(),
+            Input::MoveRight => self.move_right(),
+            Input::RotateLeft => self.rotate_left(),
+            Input::RotateRight => self.rotate_right(),
+            Input::SoftDrop => {
+                let mut moved = false;
+                while self.apply_gravity() {
+                    moved = true;
+                }
+                moved
+            },
+            Input::HardDrop => self.hard_drop(),
+            Input::Hold => {
+                self.hold();
+                true
+            }
+        }
+    }
+}
+
+/// Returns where the given input would bring the tetromino `t` on the matrix of `gs`, or `None` if
+/// the input would not move it.
+fn after_input(gs: &GameState, t: &Tetromino, input: Input) -> Option<Tetromino> {
+    let candidate = match input {
+        Input::MoveLeft => moved(t, -1, 0),
+        Input::MoveRight => moved(t, 1, 0),
+        Input::RotateLeft =>
+            return gs.rotated_tetromino(t, (t.orientation() + 3) % 4).map(|(rotated, _)| rotated),
+        Input::RotateRight =>
+            return gs.rotated_tetromino(t, (t.orientation() + 1) % 4).map(|(rotated, _)| rotated),
+        Input::SoftDrop => {
+            let mut dropped = t.clone();
+            while gs.tetromino_fits(&moved(&dropped, 0, -1)) {
+                dropped = moved(&dropped, 0, -1);
+            }
+            if dropped == *t {
+                return None;
+            }
+            return Some(dropped);
+        },
+        Input::HardDrop | Input::Hold => return None
+    };
+
+    if gs.tetromino_fits(&candidate) {
+        Some(candidate)
+    } else {
+        None
+    }
+}
+
+/// Returns the tetromino `t` moved by the given number of columns and rows.
+fn moved(t: &Tetromino, cols: isize, rows: isize) -> Tetromino {
+    let center = t.center();
+    Tetromino::with_position(t.ttype().clone(), (center.0 + cols, center.1 + rows), t.orientation())
+}
+
+/// Returns the inputs that lead from the start of the search to the given state.
+fn path_to(came_from: &HashMap<SearchState, Option<(SearchState, Input)>>, state: &SearchState) -> Vec<Input> {
+    let mut inputs = Vec::new();
+    let mut current = state;
+    while let Some(&Some((ref previous, input))) = came_from.get(current) {
+        inputs.push(input);
+        current = previous;
+    }
+    inputs.reverse();
+    inputs
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use ::builder::GameStateBuilder;
+
+    fn find<'a>(placements: &'a [Placement], minoes: &[(isize, isize)]) -> Option<&'a Placement> {
+        placements.iter().find(|p| p.tetromino.sorted_minoes() == minoes)
+    }
+
+    /// Checks that making the inputs of every placement brings the falling tetromino exactly to
+    /// it.
+    fn assert_paths_replay(gs: &GameState, placements: &[Placement]) {
+        for placement in placements {
+            let mut replayed = gs.clone();
+            for &input in &placement.inputs {
+                assert!(replayed.apply_input(input), "{:?} did nothing in {:?}", input, placement.inputs);
+            }
+            assert_eq!(replayed.falling_tetromino(), &placement.tetromino, "{:?}", placement.inputs);
+        }
+    }
+
+    #[test]
+    fn finds_a_t_spin_double_slot() {
+        let gs = GameStateBuilder::new()
+            .board("\
+                G..GGGGGGG
+                ...GGGGGGG
+                G.GGGGGGGG")
+            .queue("T")
+            .build()
+            .unwrap();
+
+        let placements = placements(&gs);
+        let slot = find(&placements, &[(0, 1), (1, 0), (1, 1), (2, 1)]).expect("the slot is reachable");
+        match slot.inputs.last() {
+            Some(&Input::RotateLeft) | Some(&Input::RotateRight) => {},
+            last => panic!("expected the path to end with a rotation, found {:?}", last),
+        }
+        assert_paths_replay(&gs, &placements);
+    }
+
+    #[test]
+    fn finds_a_tuck_under_an_overhang() {
+        let gs = GameStateBuilder::new()
+            .board("\
+                GGGG......
+                G.........
+                G.........")
+            .queue("O")
+            .build()
+            .unwrap();
+
+        let placements = placements(&gs);
+        let tuck = find(&placements, &[(1, 0), (1, 1), (2, 0), (2, 1)]).expect("the tuck is reachable");
+        assert_eq!(tuck.inputs.last(), Some(&Input::MoveLeft));
+        assert!(tuck.inputs.contains(&Input::SoftDrop));
+        assert_paths_replay(&gs, &placements);
+    }
+
+    #[test]
+    fn lists_every_resting_position_once() {
+        let gs = GameStateBuilder::new().queue("I").build().unwrap();
+
+        // A flat I rests in 7 columns and a standing one in all 10.
+        let placements = placements(&gs);
+        assert_eq!(placements.len(), 17);
+        assert_paths_replay(&gs, &placements);
+    }
+
+    #[test]
+    fn lists_a_t_spun_into_place_apart_from_one_slid_into_place() {
+        // The T pointing up can slide left into the slot under the overhang, or stand up in the
+        // hole and turn into it. Only the second is a T-spin.
+        let gs = GameStateBuilder::new()
+            .board("\
+                G.........
+                ....GGGGGG
+                G.GGGGGGGG")
+            .queue("T")
+            .build()
+            .unwrap();
+
+        let slot = [(0, 1), (1, 1), (1, 2), (2, 1)];
+        let placements = placements(&gs);
+        let in_slot: Vec<&Placement> = placements.iter().filter(|p| p.tetromino.sorted_minoes() == slot).collect();
+        assert_eq!(in_slot.len(), 2);
+        assert_eq!(in_slot[0].inputs.last(), Some(&Input::MoveLeft));
+        assert_eq!(in_slot[1].inputs.last(), Some(&Input::RotateLeft));
+        assert_paths_replay(&gs, &placements);
+    }
+}
@@ -0,0 +1,84 @@
+use ::game_state::{LineClear, TSpin};
+
+/// Points awarded, per level, for each combo step: each tetromino in a row before a line clear
+/// that also cleared lines.
+pub const COMBO_SCORE: u32 = 50;
+
+/// Returns the number of points awarded for the given line clear on the given level, following
+/// the guideline scoring table. T-spins score more than plain line clears, a line clear that keeps
+/// a back-to-back chain going scores half as much again, and combos add `COMBO_SCORE` per step.
+pub fn line_clear_score(clear: &LineClear, level: u32) -> u32 {
+    let base = match (clear.t_spin, clear.lines) {
+        (_, 0) => 0,
+        (TSpin::None, 1) => 100,
+        (TSpin::None, 2) => 300,
+        (TSpin::None, 3) => 500,
+        (TSpin::None, _) => 800,
+        (TSpin::Mini, 1) => 200,
+        (TSpin::Mini, _) => 400,
+        (TSpin::Full, 1) => 800,
+        (TSpin::Full, 2) => 1200,
+        (TSpin::Full, _) => 1600,
+    };
+    let base = if clear.back_to_back { base * 3 / 2 } else { base };
+    (base + COMBO_SCORE * clear.combo) * level
+}
+
+/// Returns the number of bonus points awarded for a perfect clear of `lines` lines on the given
+/// level, on top of the points for clearing the lines.
+pub fn perfect_clear_score(lines: usize, level: u32) -> u32 {
+    let base = match lines {
+        0 => 0,
+        1 => 800,
+        2 => 1200,
+        3 => 1800,
+        _ => 2000,
+    };
+    base * level
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn clear(lines: usize, t_spin: TSpin) -> LineClear {
+        LineClear { lines, perfect_clear: false, t_spin, combo: 0, back_to_back: false }
+    }
+
+    #[test]
+    fn scores_the_guideline_table() {
+        for &(lines, t_spin, score) in &[
+            (1, TSpin::None, 100),
+            (2, TSpin::None, 300),
+            (3, TSpin::None, 500),
+            (4, TSpin::None, 800),
+            (1, TSpin::Mini, 200),
+            (2, TSpin::Mini, 400),
+            (1, TSpin::Full, 800),
+            (2, TSpin::Full, 1200),
+            (3, TSpin::Full, 1600),
+        ] {
+            assert_eq!(line_clear_score(&clear(lines, t_spin), 1), score, "{} lines, {:?}", lines, t_spin);
+        }
+    }
+
+    #[test]
+    fn multiplies_by_the_level() {
+        assert_eq!(line_clear_score(&clear(2, TSpin::Full), 3), 3600);
+    }
+
+    #[test]
+    fn adds_half_for_back_to_back() {
+        let tetris = LineClear { back_to_back: true, ..clear(4, TSpin::None) };
+        assert_eq!(line_clear_score(&tetris, 1), 1200);
+        let t_spin_double = LineClear { back_to_back: true, ..clear(2, TSpin::Full) };
+        assert_eq!(line_clear_score(&t_spin_double, 2), 3600);
+    }
+
+    #[test]
+    fn adds_the_combo_bonus() {
+        let single = LineClear { combo: 3, ..clear(1, TSpin::None) };
+        assert_eq!(line_clear_score(&single, 1), 100 + 3 * COMBO_SCORE);
+        assert_eq!(line_clear_score(&single, 2), 2 * (100 + 3 * COMBO_SCORE));
+    }
+}
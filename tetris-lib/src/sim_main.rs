@@ -0,0 +1,259 @@
+//! Plays many games without rendering them, to benchmark a player, such as
+//! `tetris_sim --games 1000 --seed 7` for the built-in bot or `tetris_sim --tbp cold-clear` for a
+//! bot speaking the Tetris Bot Protocol. Game `i` uses seed `seed + i`, so runs with the same
+//! arguments see the same tetrominoes. Prints statistics over all the games as JSON or CSV.
+
+#[macro_use] extern crate serde;
+extern crate serde_json;
+extern crate tetris;
+
+use std::time::Instant;
+
+use tetris::bot::Bot;
+use tetris::game_mode::GameMode;
+use tetris::game_state::{GameState, TimedGameState};
+use tetris::placement::Input;
+use tetris::tbp::{self, BotProcess, TbpError};
+
+const USAGE: &str = "usage: tetris_sim [--games N] [--seed S] [--max-pieces P] \
+                     [--format json|csv] [--tbp <bot command> [bot arguments...]]";
+
+/// Something that plays games, one tetromino at a time.
+trait Player {
+    /// Called before the first tetromino of every game.
+    fn start(&mut self, tgs: &TimedGameState) -> Result<(), TbpError>;
+
+    /// Makes the inputs for the falling tetromino. Returns `false` if the game has ended, or
+    /// `true` otherwise.
+    fn play_piece(&mut self, tgs: &mut TimedGameState) -> Result<bool, TbpError>;
+
+    /// Called after the last tetromino of every game.
+    fn finish(&mut self) -> Result<(), TbpError>;
+}
+
+/// The bot of this crate.
+struct BuiltInPlayer {
+    bot: Bot
+}
+
+impl Player for BuiltInPlayer {
+    fn start(&mut self, _tgs: &TimedGameState) -> Result<(), TbpError> {
+        Ok(())
+    }
+
+    fn play_piece(&mut self, tgs: &mut TimedGameState) -> Result<bool, TbpError> {
+        let inputs = match self.bot.choose(tgs.game_state()) {
+            Some(decision) => decision.to_inputs(),
+            // Every placement tops out, so any will do.
+            None => vec![Input::HardDrop]
+        };
+        Ok(inputs.into_iter().all(|input| tgs.apply_input(input)))
+    }
+
+    fn finish(&mut self) -> Result<(), TbpError> {
+        Ok(())
+    }
+}
+
+/// A bot running as a child process, speaking the Tetris Bot Protocol. The process outlives the
+/// player, so that it can be told to quit even when a game fails.
+struct TbpPlayer<'a> {
+    bot: &'a mut BotProcess
+}
+
+impl<'a> Player for TbpPlayer<'a> {
+    fn start(&mut self, tgs: &TimedGameState) -> Result<(), TbpError> {
+        self.bot.send(&tbp::start_message(tgs.game_state()))
+    }
+
+    fn play_piece(&mut self, tgs: &mut TimedGameState) -> Result<bool, TbpError> {
+        let (mv, inputs) = self.bot.suggest(tgs.game_state())?;
+        let revealed = tbp::revealed_count(tgs.game_state(), &inputs);
+
+        if !inputs.into_iter().all(|input| tgs.apply_input(input)) {
+            return Ok(false);
+        }
+
+        let next_preview = tgs.next_preview();
+        self.bot.play(mv, &next_preview[next_preview.len().saturating_sub(revealed)..])?;
+        Ok(true)
+    }
+
+    fn finish(&mut self) -> Result<(), TbpError> {
+        self.bot.send(&tbp::FrontendMessage::Stop)
+    }
+}
+
+/// What happened in a single game.
+#[derive(Serialize)]
+struct GameStats {
+    seed: u64,
+    lines: usize,
+    pieces: usize,
+    attack: u32,
+    survived: bool,
+    seconds: f64
+}
+
+/// What happened over all the games.
+#[derive(Serialize)]
+struct Summary {
+    games: usize,
+    max_pieces: usize,
+    mean_lines: f64,
+    mean_pieces: f64,
+    mean_attack: f64,
+    survival_rate: f64,
+    pps: f64,
+    results: Vec<GameStats>
+}
+
+impl Summary {
+    fn new(max_pieces: usize, results: Vec<GameStats>) -> Self {
+        let games = results.len();
+        let mean = |total: f64| if games == 0 { 0.0 } else { total / games as f64 };
+        let pieces: usize = results.iter().map(|r| r.pieces).sum();
+        let seconds: f64 = results.iter().map(|r| r.seconds).sum();
+
+        Summary {
+            games,
+            max_pieces,
+            mean_lines: mean(results.iter().map(|r| r.lines).sum::<usize>() as f64),
+            mean_pieces: mean(pieces as f64),
+            mean_attack: mean(f64::from(results.iter().map(|r| r.attack).sum::<u32>())),
+            survival_rate: mean(results.iter().filter(|r| r.survived).count() as f64),
+            pps: if seconds > 0.0 { pieces as f64 / seconds } else { 0.0 },
+            results
+        }
+    }
+
+    fn to_csv(&self) -> String {
+        format!(
+            "games,max_pieces,mean_lines,mean_pieces,mean_attack,survival_rate,pps\n{},{},{},{},{},{},{}\n",
+            self.games, self.max_pieces, self.mean_lines, self.mean_pieces, self.mean_attack,
+            self.survival_rate, self.pps)
+    }
+}
+
+/// Plays a game with tetrominoes from the given seed, until the player tops out or has locked
+/// `max_pieces` tetrominoes. Time never advances, so the player can take as long as it needs.
+fn play_game(player: &mut dyn Player, seed: u64, max_pieces: usize) -> Result<GameStats, TbpError> {
+    let mut tgs = TimedGameState::with_game_state(GameState::with_seed(seed), GameMode::Endless);
+    let mut attack = 0;
+    let start = Instant::now();
+
+    player.start(&tgs)?;
+    let mut alive = true;
+    while alive && tgs.game_state().pieces_placed() < max_pieces {
+        alive = player.play_piece(&mut tgs)?;
+        attack += tgs.take_attack();
+    }
+    player.finish()?;
+
+    let elapsed = start.elapsed();
+    Ok(GameStats {
+        seed,
+        lines: tgs.game_state().lines_cleared(),
+        pieces: tgs.game_state().pieces_placed(),
+        attack,
+        survived: alive,
+        seconds: elapsed.as_secs() as f64 + f64::from(elapsed.subsec_nanos()) / 1e9
+    })
+}
+
+enum Format {
+    Json,
+    Csv
+}
+
+struct Options {
+    games: u64,
+    seed: u64,
+    max_pieces: usize,
+    format: Format,
+    tbp_command: Option<Vec<String>>
+}
+
+fn parse_args(args: &[String]) -> Result<Options, String> {
+    let mut options = Options {
+        games: 100,
+        seed: 0,
+        max_pieces: 1000,
+        format: Format::Json,
+        tbp_command: None
+    };
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().ok_or_else(|| format!("{} needs a value", arg));
+        match arg.as_str() {
+            "--games" => options.games = value()?.parse().map_err(|_| "invalid --games")?,
+            "--seed" => options.seed = value()?.parse().map_err(|_| "invalid --seed")?,
+            "--max-pieces" => options.max_pieces = value()?.parse().map_err(|_| "invalid --max-pieces")?,
+            "--format" => options.format = match value()?.as_str() {
+                "json" => Format::Json,
+                "csv" => Format::Csv,
+                format => return Err(format!("unknown format {}", format)),
+            },
+            "--tbp" => {
+                let command: Vec<String> = args.by_ref().cloned().collect();
+                if command.is_empty() {
+                    return Err(String::from("--tbp needs a bot command"));
+                }
+                options.tbp_command = Some(command);
+            },
+            _ => return Err(format!("unknown argument {}", arg)),
+        }
+    }
+
+    Ok(options)
+}
+
+/// Plays all the games with the given player.
+fn play_games(player: &mut dyn Player, options: &Options) -> Result<Vec<GameStats>, TbpError> {
+    (0..options.games)
+        .map(|i| play_game(player, options.seed.wrapping_add(i), options.max_pieces))
+        .collect()
+}
+
+fn run(options: &Options) -> Result<Summary, TbpError> {
+    let results = match options.tbp_command {
+        Some(ref command) => {
+            let mut bot = BotProcess::start(&command[0], &command[1..])?;
+            let results = bot.handshake().and_then(|_| play_games(&mut TbpPlayer { bot: &mut bot }, options));
+            // Stop the bot whether or not the games went well, reporting the first thing that
+            // failed.
+            let quit = bot.quit();
+            let results = results?;
+            quit?;
+            results
+        },
+        None => play_games(&mut BuiltInPlayer { bot: Bot::default() }, options)?,
+    };
+
+    Ok(Summary::new(options.max_pieces, results))
+}
+
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let options = match parse_args(&args) {
+        Ok(options) => options,
+        Err(e) => {
+            eprintln!("{}\n{}", e, USAGE);
+            std::process::exit(2);
+        }
+    };
+
+    let summary = match run(&options) {
+        Ok(summary) => summary,
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+    };
+
+    match options.format {
+        Format::Json => println!("{}", serde_json::to_string_pretty(&summary).expect("summary is serializable")),
+        Format::Csv => print!("{}", summary.to_csv()),
+    }
+}
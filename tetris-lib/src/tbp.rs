@@ -0,0 +1,488 @@
+//! Messages of the Tetris Bot Protocol (TBP), which lets a frontend and a bot talk to each other
+//! by exchanging JSON messages, one per line.
+//!
+//! This module covers the frontend's side of the protocol: the messages a frontend sends, the
+//! messages it receives, converting between the protocol's descriptions of tetrominoes and
+//! matrices and the ones of this crate, and running a bot as a child process. See
+//! <https://github.com/tetris-bot-protocol/tbp-spec>.
+
+use std::error;
+use std::fmt;
+use std::io::{self, BufRead, BufReader, Write};
+use std::process::{Child, ChildStdin, ChildStdout, Command, ExitStatus, Stdio};
+
+use serde_json;
+
+use ::game_state::{GameState, Tetromino};
+use ::placement::{self, Input};
+
+/// The number of rows of the board in TBP messages, which is more than a matrix of the default
+/// size has.
+pub const BOARD_HEIGHT: usize = 40;
+
+/// A message sent from the frontend to the bot.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum FrontendMessage {
+    /// Tells the bot the rules of the game, to which it replies with `Ready` or `Error`.
+    Rules,
+    /// Tells the bot the position to start thinking about.
+    Start {
+        hold: Option<::TetrominoType>,
+        queue: Vec<::TetrominoType>,
+        combo: u32,
+        back_to_back: bool,
+        board: Vec<Vec<Option<char>>>
+    },
+    /// Asks the bot for the moves it suggests.
+    Suggest,
+    /// Tells the bot that the given move was made.
+    Play {
+        #[serde(rename = "move")]
+        mv: Move
+    },
+    /// Tells the bot about a tetromino that was added to the end of the queue.
+    NewPiece { piece: ::TetrominoType },
+    /// Tells the bot to stop thinking about the current position.
+    Stop,
+    /// Tells the bot to exit.
+    Quit
+}
+
+/// A message sent from the bot to the frontend.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum BotMessage {
+    /// Describes the bot. This is the first message a bot sends.
+    Info {
+        name: String,
+        version: String,
+        author: String,
+        features: Vec<String>
+    },
+    /// The bot accepts the rules and is ready to start.
+    Ready,
+    /// The moves the bot suggests, from the most to the least preferred.
+    Suggestion { moves: Vec<Move> },
+    /// The bot cannot play by the rules it was given.
+    Error { reason: String }
+}
+
+/// Which way a tetromino is turned, relative to its spawn orientation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Orientation {
+    North,
+    East,
+    South,
+    West
+}
+
+/// Whether a move is a spin, and what kind.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Spin {
+    None,
+    Mini,
+    Full
+}
+
+/// Where a tetromino is, with `x` and `y` being the column and row of its center counted from the
+/// bottom left, as for a `Tetromino`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PieceLocation {
+    #[serde(rename = "type")]
+    pub ttype: ::TetrominoType,
+    pub orientation: Orientation,
+    pub x: isize,
+    pub y: isize
+}
+
+/// A move suggested by a bot: where the tetromino is locked, and how it got there.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Move {
+    pub location: PieceLocation,
+    pub spin: Spin
+}
+
+impl PieceLocation {
+
+    /// Returns the location of the given tetromino.
+    pub fn from_tetromino(t: &Tetromino) -> Self {
+        PieceLocation {
+            ttype: t.ttype().clone(),
+            orientation: match t.orientation() {
+                0 => Orientation::North,
+                1 => Orientation::East,
+                2 => Orientation::South,
+                _ => Orientation::West,
+            },
+            x: t.center().0,
+            y: t.center().1
+        }
+    }
+
+    /// Returns a tetromino at this location.
+    pub fn to_tetromino(&self) -> Tetromino {
+        let orientation = match self.orientation {
+            Orientation::North => 0,
+            Orientation::East => 1,
+            Orientation::South => 2,
+            Orientation::West => 3,
+        };
+        Tetromino::with_position(self.ttype.clone(), (self.x, self.y), orientation)
+    }
+}
+
+/// Returns the message that starts a bot thinking about the position of `gs`.
+pub fn start_message(gs: &GameState) -> FrontendMessage {
+    let mut queue = vec![gs.falling_tetromino().ttype().clone()];
+    queue.extend(gs.next_preview().iter().cloned());
+
+    FrontendMessage::Start {
+        hold: gs.held().clone(),
+        queue,
+        combo: 0,
+        back_to_back: false,
+        board: board(gs)
+    }
+}
+
+/// Returns the squares of the matrix of `gs` as a TBP board: rows from the bottom up, each with a
+/// square per column that is either `None` or the letter of the tetromino it came from, or `G`.
+/// There are `BOARD_HEIGHT` rows, or more if the matrix is taller.
+pub fn board(gs: &GameState) -> Vec<Vec<Option<char>>> {
+    (0..BOARD_HEIGHT.max(gs.matrix_height())).map(|row| {
+        (0..gs.matrix_width()).map(|col| {
+            if row >= gs.matrix_height() {
+                return None;
+            }
+            let square = &gs.placed_squares()[col][row];
+            match square.tetromino_type() {
+                Some(tt) => Some(tt.to_char()),
+                None if square.is_empty() => None,
+                None => Some('G'),
+            }
+        }).collect()
+    }).collect()
+}
+
+/// Returns the inputs that carry out the given move from the position of `gs`, holding first if
+/// the move is for the held (or next) tetromino, and ending with a hard drop. Returns `None` if
+/// the tetromino cannot reach the location of the move.
+pub fn inputs_for_move(gs: &GameState, mv: &Move) -> Option<Vec<Input>> {
+    let target = mv.location.to_tetromino();
+    let mut target_minoes = target.minoes();
+    target_minoes.sort();
+
+    let mut start = gs.clone();
+    let mut inputs = Vec::new();
+    if gs.falling_tetromino().ttype() != target.ttype() {
+        if !gs.can_hold() {
+            return None;
+        }
+        start.hold();
+        inputs.push(Input::Hold);
+        if start.falling_tetromino().ttype() != target.ttype() {
+            return None;
+        }
+    }
+
+    // Placements that cover the same squares are listed once whatever their center and
+    // orientation, so compare squares. A T can be listed twice, with and without a rotation at
+    // the end of its path, and only the first can spin, so pick the one that matches the move.
+    let spin = mv.spin != Spin::None;
+    let mut placements: Vec<_> = placement::placements(&start).into_iter().filter(|placement| {
+        let mut minoes = placement.tetromino.minoes();
+        minoes.sort();
+        minoes == target_minoes
+    }).collect();
+    placements.sort_by_key(|placement| ends_with_rotation(&placement.inputs) != spin);
+    let placement = placements.into_iter().next()?;
+
+    inputs.extend(placement.inputs);
+    inputs.push(Input::HardDrop);
+    Some(inputs)
+}
+
+/// Returns `true` if the last of the given inputs rotates the tetromino.
+fn ends_with_rotation(inputs: &[Input]) -> bool {
+    matches!(inputs.last(), Some(&Input::RotateLeft) | Some(&Input::RotateRight))
+}
+
+/// Describes why talking to a bot failed.
+#[derive(Debug)]
+pub enum TbpError {
+    /// The bot could not be started, or reading from or writing to it failed.
+    Io(io::Error),
+    /// A message could not be written, or a message from the bot could not be understood.
+    Json(serde_json::Error),
+    /// The bot exited.
+    BotExited,
+    /// The bot exited with a status that reports a failure.
+    ExitFailure(ExitStatus),
+    /// The bot sent a message that does not fit at this point of the protocol.
+    UnexpectedMessage(BotMessage),
+    /// The bot reported an error, such as rules it cannot play by.
+    Bot(String),
+    /// None of the moves the bot suggested can be made.
+    NoValidMove,
+}
+
+impl fmt::Display for TbpError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            TbpError::Io(ref e) => write!(f, "could not talk to bot: {}", e),
+            TbpError::Json(ref e) => write!(f, "invalid message: {}", e),
+            TbpError::BotExited => write!(f, "bot exited"),
+            TbpError::ExitFailure(status) => write!(f, "bot exited with {}", status),
+            TbpError::UnexpectedMessage(ref message) => write!(f, "unexpected message from bot: {:?}", message),
+            TbpError::Bot(ref reason) => write!(f, "bot error: {}", reason),
+            TbpError::NoValidMove => write!(f, "bot suggested no move that can be made"),
+        }
+    }
+}
+
+impl error::Error for TbpError {}
+
+impl From<io::Error> for TbpError {
+    fn from(e: io::Error) -> Self {
+        TbpError::Io(e)
+    }
+}
+
+impl From<serde_json::Error> for TbpError {
+    fn from(e: serde_json::Error) -> Self {
+        TbpError::Json(e)
+    }
+}
+
+/// A bot running as a child process, talking TBP over its stdin and stdout.
+pub struct BotProcess {
+    child: Child,
+    stdin: ChildStdin,
+    stdout: BufReader<ChildStdout>
+}
+
+impl BotProcess {
+
+    /// Starts the bot with the given command and arguments.
+    pub fn start(command: &str, args: &[String]) -> Result<Self, TbpError> {
+        let mut child = Command::new(command)
+            .args(args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()?;
+        let stdin = child.stdin.take().ok_or(TbpError::BotExited)?;
+        let stdout = child.stdout.take().ok_or(TbpError::BotExited)?;
+        Ok(BotProcess { child, stdin, stdout: BufReader::new(stdout) })
+    }
+
+    /// Sends a message to the bot.
+    pub fn send(&mut self, message: &FrontendMessage) -> Result<(), TbpError> {
+        let json = serde_json::to_string(message)?;
+        writeln!(self.stdin, "{}", json)?;
+        self.stdin.flush()?;
+        Ok(())
+    }
+
+    /// Waits for the next message from the bot.
+    pub fn receive(&mut self) -> Result<BotMessage, TbpError> {
+        let mut line = String::new();
+        if self.stdout.read_line(&mut line)? == 0 {
+            return Err(TbpError::BotExited);
+        }
+        Ok(serde_json::from_str(&line)?)
+    }
+
+    /// Waits for the bot to describe itself, then tells it the rules and waits for it to be
+    /// ready. Returns the bot's `Info` message.
+    pub fn handshake(&mut self) -> Result<BotMessage, TbpError> {
+        let info = match self.receive()? {
+            info @ BotMessage::Info { .. } => info,
+            message => return Err(TbpError::UnexpectedMessage(message)),
+        };
+
+        self.send(&FrontendMessage::Rules)?;
+        match self.receive()? {
+            BotMessage::Ready => Ok(info),
+            BotMessage::Error { reason } => Err(TbpError::Bot(reason)),
+            message => Err(TbpError::UnexpectedMessage(message)),
+        }
+    }
+
+    /// Asks the bot for a move in the position of `gs`, and returns the first suggested move that
+    /// can be made, with the inputs that make it.
+    pub fn suggest(&mut self, gs: &GameState) -> Result<(Move, Vec<Input>), TbpError> {
+        self.send(&FrontendMessage::Suggest)?;
+        let moves = match self.receive()? {
+            BotMessage::Suggestion { moves } => moves,
+            BotMessage::Error { reason } => return Err(TbpError::Bot(reason)),
+            message => return Err(TbpError::UnexpectedMessage(message)),
+        };
+
+        moves.into_iter()
+            .filter_map(|mv| inputs_for_move(gs, &mv).map(|inputs| (mv, inputs)))
+            .next()
+            .ok_or(TbpError::NoValidMove)
+    }
+
+    /// Tells the bot that the given move was made, and about the tetrominoes it revealed at the
+    /// end of the queue.
+    pub fn play(&mut self, mv: Move, revealed: &[::TetrominoType]) -> Result<(), TbpError> {
+        self.send(&FrontendMessage::Play { mv })?;
+        for piece in revealed {
+            self.send(&FrontendMessage::NewPiece { piece: piece.clone() })?;
+        }
+        Ok(())
+    }
+
+    /// Tells the bot to exit, and waits for it to do so. Fails if the bot exits with a status
+    /// that reports a failure.
+    pub fn quit(mut self) -> Result<(), TbpError> {
+        let sent = self.send(&FrontendMessage::Quit);
+        // Closing its stdin also stops a bot that can no longer read messages.
+        drop(self.stdin);
+        let status = self.child.wait()?;
+        sent?;
+        if !status.success() {
+            return Err(TbpError::ExitFailure(status));
+        }
+        Ok(())
+    }
+}
+
+/// Returns the number of tetrominoes taken from the next preview by making the given inputs from
+/// the position of `gs`, which is the number of new ones revealed at its end.
+pub fn revealed_count(gs: &GameState, inputs: &[Input]) -> usize {
+    let locked = inputs.iter().filter(|&&input| input == Input::HardDrop).count();
+    let held_from_preview = gs.held().is_none() && inputs.contains(&Input::Hold);
+    locked + held_from_preview as usize
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use ::builder::GameStateBuilder;
+
+    fn location(ttype: ::TetrominoType, orientation: Orientation, x: isize, y: isize) -> PieceLocation {
+        PieceLocation { ttype, orientation, x, y }
+    }
+
+    #[test]
+    fn writes_frontend_messages() {
+        assert_eq!(serde_json::to_string(&FrontendMessage::Rules).unwrap(), r#"{"type":"rules"}"#);
+        assert_eq!(
+            serde_json::to_string(&FrontendMessage::NewPiece { piece: ::TetrominoType::S }).unwrap(),
+            r#"{"type":"new_piece","piece":"S"}"#
+        );
+        assert_eq!(
+            serde_json::to_string(&FrontendMessage::Play {
+                mv: Move { location: location(::TetrominoType::T, Orientation::South, 4, 1), spin: Spin::Full }
+            }).unwrap(),
+            r#"{"type":"play","move":{"location":{"type":"T","orientation":"south","x":4,"y":1},"spin":"full"}}"#
+        );
+    }
+
+    #[test]
+    fn writes_the_start_message() {
+        let gs = GameStateBuilder::new()
+            .board("GT........")
+            .falling("J")
+            .queue("IOTSZ")
+            .hold("L")
+            .build()
+            .unwrap();
+        let json: serde_json::Value = serde_json::to_value(start_message(&gs)).unwrap();
+
+        assert_eq!(json["type"], "start");
+        assert_eq!(json["hold"], "L");
+        assert_eq!(json["queue"], serde_json::to_value(vec!["J", "I", "O", "T", "S", "Z"]).unwrap());
+        assert_eq!(json["combo"], 0);
+        assert_eq!(json["back_to_back"], false);
+
+        let board = json["board"].as_array().unwrap();
+        assert_eq!(board.len(), BOARD_HEIGHT);
+        assert_eq!(board[0][0], "G");
+        assert_eq!(board[0][1], "T");
+        assert!(board[0][2].is_null());
+        assert!(board[1].as_array().unwrap().iter().all(|square| square.is_null()));
+    }
+
+    #[test]
+    fn reads_a_suggestion() {
+        let message: BotMessage = serde_json::from_str(r#"{
+            "type": "suggestion",
+            "moves": [{"location": {"type": "I", "orientation": "west", "x": 0, "y": 1}, "spin": "none"}],
+            "move_info": {"nodes": 1000}
+        }"#).unwrap();
+        assert_eq!(message, BotMessage::Suggestion {
+            moves: vec![Move { location: location(::TetrominoType::I, Orientation::West, 0, 1), spin: Spin::None }]
+        });
+    }
+
+    #[test]
+    fn places_the_center_of_an_i_and_an_o_like_tbp() {
+        // The center of the I is the second mino from the left when it points north, and the
+        // center of the O is its bottom left mino when it points north.
+        assert_eq!(location(::TetrominoType::I, Orientation::North, 4, 0).to_tetromino().sorted_minoes(),
+                   vec![(3, 0), (4, 0), (5, 0), (6, 0)]);
+        assert_eq!(location(::TetrominoType::I, Orientation::East, 4, 2).to_tetromino().sorted_minoes(),
+                   vec![(4, 0), (4, 1), (4, 2), (4, 3)]);
+        assert_eq!(location(::TetrominoType::I, Orientation::South, 4, 0).to_tetromino().sorted_minoes(),
+                   vec![(2, 0), (3, 0), (4, 0), (5, 0)]);
+        assert_eq!(location(::TetrominoType::I, Orientation::West, 4, 1).to_tetromino().sorted_minoes(),
+                   vec![(4, 0), (4, 1), (4, 2), (4, 3)]);
+        assert_eq!(location(::TetrominoType::O, Orientation::North, 4, 0).to_tetromino().sorted_minoes(),
+                   vec![(4, 0), (4, 1), (5, 0), (5, 1)]);
+        assert_eq!(location(::TetrominoType::O, Orientation::South, 5, 1).to_tetromino().sorted_minoes(),
+                   vec![(4, 0), (4, 1), (5, 0), (5, 1)]);
+
+        for ttype in &[::TetrominoType::I, ::TetrominoType::O] {
+            for &orientation in &[Orientation::North, Orientation::East, Orientation::South, Orientation::West] {
+                let location = location(ttype.clone(), orientation, 4, 5);
+                assert_eq!(PieceLocation::from_tetromino(&location.to_tetromino()), location);
+            }
+        }
+    }
+
+    #[test]
+    fn holds_to_reach_a_move_for_the_next_tetromino() {
+        let gs = GameStateBuilder::new().falling("T").queue("IOSZ").build().unwrap();
+        let mv = Move { location: location(::TetrominoType::I, Orientation::North, 4, 0), spin: Spin::None };
+
+        let inputs = inputs_for_move(&gs, &mv).unwrap();
+        assert_eq!(inputs.first(), Some(&Input::Hold));
+        assert_eq!(inputs.last(), Some(&Input::HardDrop));
+
+        let mut played = gs.clone();
+        for &input in &inputs[..inputs.len() - 1] {
+            played.apply_input(input);
+        }
+        assert_eq!(played.falling_tetromino(), &mv.location.to_tetromino());
+    }
+
+    #[test]
+    fn finds_no_inputs_for_an_unreachable_move() {
+        let gs = GameStateBuilder::new().falling("T").queue("IOSZ").build().unwrap();
+
+        // In the air, where the T cannot be locked.
+        let floating = Move { location: location(::TetrominoType::T, Orientation::North, 4, 5), spin: Spin::None };
+        assert_eq!(inputs_for_move(&gs, &floating), None);
+
+        // Neither the falling tetromino nor the one a hold would bring.
+        let other_type = Move { location: location(::TetrominoType::Z, Orientation::North, 4, 0), spin: Spin::None };
+        assert_eq!(inputs_for_move(&gs, &other_type), None);
+    }
+
+    #[test]
+    fn counts_the_tetrominoes_revealed_by_a_move() {
+        let gs = GameStateBuilder::new().falling("T").queue("IOSZ").build().unwrap();
+        assert_eq!(revealed_count(&gs, &[Input::MoveLeft, Input::HardDrop]), 1);
+        assert_eq!(revealed_count(&gs, &[Input::Hold, Input::HardDrop]), 2);
+
+        let gs = GameStateBuilder::new().falling("T").queue("IOSZ").hold("L").build().unwrap();
+        assert_eq!(revealed_count(&gs, &[Input::Hold, Input::HardDrop]), 1);
+    }
+}
@@ -0,0 +1,63 @@
+//! A frontend that lets a bot speaking the Tetris Bot Protocol play a game, such as
+//! `tetris_tbp cold-clear`. The bot is started with the given command and arguments, and talks to
+//! this frontend over its stdin and stdout.
+
+extern crate tetris;
+
+use tetris::game_state::TimedGameState;
+use tetris::tbp::{self, BotMessage, BotProcess, TbpError};
+
+fn play(bot: &mut BotProcess) -> Result<(), TbpError> {
+
+    if let BotMessage::Info { name, version, author, .. } = bot.handshake()? {
+        println!("playing with {} {} by {}", name, version, author);
+    }
+
+    let mut tgs = TimedGameState::new();
+    bot.send(&tbp::start_message(tgs.game_state()))?;
+
+    loop {
+        let (mv, inputs) = bot.suggest(tgs.game_state())?;
+        let revealed = tbp::revealed_count(tgs.game_state(), &inputs);
+
+        let continue_game = inputs.into_iter().all(|input| tgs.apply_input(input));
+
+        print!("{}", tgs.game_state().to_ascii());
+        println!("score: {}, lines: {}", tgs.score(), tgs.game_state().lines_cleared());
+        println!();
+
+        if !continue_game {
+            if let Some(result) = tgs.result() {
+                println!("{}", result);
+            }
+            return Ok(());
+        }
+
+        let next_preview = tgs.next_preview();
+        bot.play(mv, &next_preview[next_preview.len().saturating_sub(revealed)..])?;
+    }
+}
+
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if args.is_empty() {
+        eprintln!("usage: tetris_tbp <bot command> [bot arguments...]");
+        std::process::exit(2);
+    }
+
+    let mut bot = match BotProcess::start(&args[0], &args[1..]) {
+        Ok(bot) => bot,
+        Err(e) => {
+            eprintln!("could not start {}: {}", args[0], e);
+            std::process::exit(1);
+        }
+    };
+
+    let result = play(&mut bot);
+    let _ = bot.quit();
+
+    if let Err(e) = result {
+        eprintln!("{}", e);
+        std::process::exit(1);
+    }
+}
@@ -0,0 +1,194 @@
+//! Two-player versus matches, where the lines each player clears are sent to the other as
+//! garbage, and the last player standing wins.
+
+use ::game_mode::GameMode;
+use ::game_state::{GameEvent, GameState, TimedGameState};
+use ::placement::Input;
+
+/// Describes how a versus match ended.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VersusResult {
+    /// The player with the given index, 0 or 1, was the last one standing.
+    Winner(usize),
+    /// Both players topped out at the same time.
+    Draw
+}
+
+/// A match between two players, each playing their own game. The games advance in lockstep, and
+/// the attack of each player is sent to the other after every action.
+pub struct VersusMatch {
+    players: [TimedGameState; 2],
+    result: Option<VersusResult>
+}
+
+impl Default for VersusMatch {
+    fn default() -> Self {
+        VersusMatch::new()
+    }
+}
+
+impl VersusMatch {
+
+    /// Creates a new match where both players start with an empty matrix.
+    pub fn new() -> Self {
+        VersusMatch::with_game_states(GameState::new(), GameState::new())
+    }
+
+    /// Creates a new match where the players continue from the given `GameState`s.
+    pub fn with_game_states(first: GameState, second: GameState) -> Self {
+        VersusMatch {
+            players: [
+                TimedGameState::with_game_state(first, GameMode::Endless),
+                TimedGameState::with_game_state(second, GameMode::Endless)
+            ],
+            result: None
+        }
+    }
+
+    /// Returns the game of the player with the given index, 0 or 1.
+    pub fn player(&self, index: usize) -> &TimedGameState {
+        &self.players[index]
+    }
+
+    /// Removes and returns the events that happened in the game of the player with the given
+    /// index, 0 or 1, since this was last called for that player.
+    pub fn take_events(&mut self, index: usize) -> Vec<GameEvent> {
+        self.players[index].take_events()
+    }
+
+    /// Returns how the match ended, or `None` if it is still going on.
+    pub fn result(&self) -> Option<VersusResult> {
+        self.result
+    }
+
+    /// Makes the given input for the player with the given index, 0 or 1. Returns `false` if the
+    /// match has ended, or `true` otherwise.
+    pub fn apply_input(&mut self, index: usize, input: Input) -> bool {
+        self.update_player(index, |player| player.apply_input(input)).is_some() && self.result.is_none()
+    }
+
+    /// Changes the game of the player with the given index, 0 or 1, with the given function, such
+    /// as to soft drop by one row, then exchanges attacks like `apply_input`. Returns what the
+    /// function returns, or `None` without calling it if the match has ended.
+    pub fn update_player<R, F: FnOnce(&mut TimedGameState) -> R>(&mut self, index: usize, f: F) -> Option<R> {
+        if self.result.is_some() {
+            return None;
+        }
+
+        let r = f(&mut self.players[index]);
+        self.exchange_attacks();
+        self.update_result();
+        Some(r)
+    }
+
+    /// Advances both games by the given number of milliseconds. Returns `false` if the match has
+    /// ended, or `true` otherwise.
+    pub fn advance_time(&mut self, t: u32) -> bool {
+        if self.result.is_some() {
+            return false;
+        }
+
+        for player in self.players.iter_mut() {
+            player.advance_time(t);
+        }
+        self.exchange_attacks();
+        self.update_result()
+    }
+
+    /// Sends the attack of each player to the other.
+    fn exchange_attacks(&mut self) {
+        let attacks = [self.players[0].take_attack(), self.players[1].take_attack()];
+        self.players[1].receive_garbage(attacks[0]);
+        self.players[0].receive_garbage(attacks[1]);
+    }
+
+    /// Decides the result once a player has topped out. Returns `false` if the match has ended, or
+    /// `true` otherwise.
+    fn update_result(&mut self) -> bool {
+        let topped_out = [self.players[0].end().is_some(), self.players[1].end().is_some()];
+        self.result = match topped_out {
+            [true, true] => Some(VersusResult::Draw),
+            [true, false] => Some(VersusResult::Winner(1)),
+            [false, true] => Some(VersusResult::Winner(0)),
+            [false, false] => None,
+        };
+        self.result.is_none()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use ::attack::GARBAGE_DELAY;
+    use ::builder::{tetris_ready, GameStateBuilder};
+    use ::game_state::LOCK_INTERVAL;
+
+    /// A game whose falling T rests right where it spawned, so that the next tetromino has no
+    /// space to spawn once it locks.
+    fn about_to_top_out() -> GameState {
+        GameStateBuilder::new()
+            .board(&"G.GGGGGGGG\n".repeat(20))
+            .falling("T")
+            .queue("TTTTT")
+            .build()
+            .unwrap()
+    }
+
+    #[test]
+    fn sends_a_tetris_to_the_opponent() {
+        let mut versus_match = VersusMatch::with_game_states(tetris_ready(), GameState::new());
+        assert!(versus_match.apply_input(0, Input::HardDrop));
+        assert_eq!(versus_match.player(1).pending_garbage(), 4);
+        assert_eq!(versus_match.player(0).pending_garbage(), 0);
+    }
+
+    #[test]
+    fn sends_the_attack_of_a_player_updated_directly() {
+        let mut versus_match = VersusMatch::with_game_states(tetris_ready(), GameState::new());
+        assert_eq!(versus_match.update_player(0, |player| player.hard_drop()), Some(true));
+        assert_eq!(versus_match.player(1).pending_garbage(), 4);
+    }
+
+    #[test]
+    fn replays_the_same_way_for_the_same_seeds_and_inputs() {
+        let play = || {
+            let mut versus_match = VersusMatch::with_game_states(tetris_ready(), GameState::with_seed(9));
+            versus_match.apply_input(0, Input::HardDrop);
+            versus_match.advance_time(GARBAGE_DELAY);
+            versus_match.apply_input(1, Input::HardDrop);
+            versus_match
+        };
+        let (first, second) = (play(), play());
+        assert_eq!(first.player(1).game_state().garbage_row_count(), 4);
+        assert_eq!(first.player(1).placed_squares(), second.player(1).placed_squares());
+    }
+
+    #[test]
+    fn the_other_player_wins_when_one_tops_out() {
+        let mut versus_match = VersusMatch::with_game_states(about_to_top_out(), GameState::new());
+        assert!(!versus_match.apply_input(0, Input::HardDrop));
+        assert_eq!(versus_match.result(), Some(VersusResult::Winner(1)));
+    }
+
+    #[test]
+    fn is_a_draw_when_both_top_out_at_once() {
+        let mut versus_match = VersusMatch::with_game_states(about_to_top_out(), about_to_top_out());
+        assert!(!versus_match.advance_time(LOCK_INTERVAL));
+        assert_eq!(versus_match.result(), Some(VersusResult::Draw));
+    }
+
+    #[test]
+    fn ignores_inputs_and_time_once_there_is_a_result() {
+        let mut versus_match = VersusMatch::with_game_states(about_to_top_out(), GameState::new());
+        versus_match.apply_input(0, Input::HardDrop);
+
+        let falling = versus_match.player(1).falling_tetromino().clone();
+        assert!(!versus_match.apply_input(1, Input::MoveLeft));
+        assert_eq!(versus_match.update_player(1, |player| player.move_left()), None);
+        assert!(!versus_match.advance_time(LOCK_INTERVAL));
+        assert_eq!(versus_match.player(1).falling_tetromino(), &falling);
+        assert_eq!(versus_match.player(1).elapsed(), 0);
+        assert_eq!(versus_match.result(), Some(VersusResult::Winner(1)));
+    }
+}
@@ -0,0 +1,378 @@
+//! A snapshot of everything a frontend draws, so that each one renders the same picture of the
+//! game without working out how the matrix, the falling tetromino and the boxes around it combine.
+
+use ::game_state::{TimedGameState, TimeStateAction};
+use ::tetromino_data;
+
+/// What to draw on one square of the matrix.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub enum Square {
+    /// Nothing, including squares of invisible tetrominoes.
+    Empty,
+    /// A square that has been locked on the matrix. This is never `Cell::Empty` or
+    /// `Cell::Invisible`.
+    Locked(::Cell),
+    /// A square of the falling tetromino.
+    Falling(::TetrominoType),
+    /// A square of where the falling tetromino would land if it were hard dropped.
+    Ghost(::TetrominoType)
+}
+
+/// A tetromino in spawn orientation, shown outside the matrix, as in the next preview and the
+/// hold box.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct PieceView {
+    /// The type of the tetromino.
+    pub ttype: ::TetrominoType,
+    /// The column and row of each of its squares, with (0, 0) at the bottom left of the smallest
+    /// box that contains them.
+    pub minoes: Vec<(usize, usize)>,
+    /// The number of columns of that box.
+    pub width: usize,
+    /// The number of rows of that box.
+    pub height: usize
+}
+
+impl PieceView {
+
+    /// Creates the view of a tetromino of the given type in spawn orientation.
+    pub fn new(tt: &::TetrominoType) -> Self {
+        let shape = tetromino_data::tetromino_shape_from(tt);
+        let min_col = shape.iter().map(|&(col, _)| col).min().unwrap_or(0);
+        let min_row = shape.iter().map(|&(_, row)| row).min().unwrap_or(0);
+        let minoes: Vec<(usize, usize)> = shape.iter()
+            .map(|&(col, row)| ((col - min_col) as usize, (row - min_row) as usize))
+            .collect();
+
+        PieceView {
+            ttype: tt.clone(),
+            width: minoes.iter().map(|&(col, _)| col + 1).max().unwrap_or(0),
+            height: minoes.iter().map(|&(_, row)| row + 1).max().unwrap_or(0),
+            minoes
+        }
+    }
+}
+
+/// The numbers shown next to the matrix.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Timers {
+    /// The number of points scored so far.
+    pub score: u32,
+    /// The current level.
+    pub level: u32,
+    /// The total number of lines cleared so far.
+    pub lines_cleared: usize,
+    /// The total number of tetrominoes locked on the matrix so far.
+    pub pieces_placed: usize,
+    /// The number of milliseconds that have passed since the game started.
+    pub elapsed: u32,
+    /// The remaining number of milliseconds the falling tetromino can spend on the ground before
+    /// it is forced to lock.
+    pub time_to_lock: u32,
+    /// The number of milliseconds until the falling tetromino goes down by 1 space, or `None` if
+    /// it is on the ground.
+    pub time_to_fall: Option<u32>,
+    /// The number of milliseconds left before the time limit runs out, if there is one.
+    pub time_remaining: Option<u32>,
+    /// The number of milliseconds until the next row of garbage rises, if garbage rises.
+    pub time_to_garbage: Option<u32>,
+    /// The number of lines left to clear before the goal is reached, if there is one.
+    pub lines_remaining: Option<usize>,
+    /// The number of lines of garbage received from an opponent that have not risen yet.
+    pub pending_garbage: u32
+}
+
+impl Timers {
+
+    /// Returns the average number of tetrominoes locked per second so far.
+    pub fn pieces_per_second(&self) -> f64 {
+        if self.elapsed == 0 {
+            0.0
+        } else {
+            self.pieces_placed as f64 * 1000.0 / f64::from(self.elapsed)
+        }
+    }
+}
+
+/// Everything a frontend draws of a game at one moment.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ViewModel {
+    /// The number of columns of the matrix.
+    pub width: usize,
+    /// The number of rows of the matrix, both visible and hidden.
+    pub height: usize,
+    /// The number of rows at the bottom of the matrix that are visible. The rows from this one
+    /// up are hidden.
+    pub visible_height: usize,
+    /// The squares of the matrix, indexed by column and then row, with row 0 at the bottom. The
+    /// falling tetromino is drawn over its ghost, which is drawn over empty squares.
+    pub squares: Vec<Vec<Square>>,
+    /// The tetrominoes that come next, the first being the next one.
+    pub preview: Vec<PieceView>,
+    /// The held tetromino, if any.
+    pub hold: Option<PieceView>,
+    /// Whether the falling tetromino can be held. Frontends gray out the held tetromino if not.
+    pub hold_available: bool,
+    /// The numbers shown next to the matrix.
+    pub timers: Timers
+}
+
+impl ViewModel {
+
+    /// Takes a snapshot of the given game.
+    pub fn new(tgs: &TimedGameState) -> Self {
+        let gs = tgs.game_state();
+
+        let mut squares: Vec<Vec<Square>> = gs.placed_squares().iter().map(|column| {
+            column.iter().map(|cell| {
+                if cell.is_visible() { Square::Locked(cell.clone()) } else { Square::Empty }
+            }).collect()
+        }).collect();
+
+        let falling_tetromino = gs.falling_tetromino();
+        for (col, row) in gs.ghost_tetromino().minoes() {
+            squares[col as usize][row as usize] = Square::Ghost(falling_tetromino.ttype().clone());
+        }
+        for (col, row) in falling_tetromino.minoes() {
+            squares[col as usize][row as usize] = Square::Falling(falling_tetromino.ttype().clone());
+        }
+
+        let time_state = tgs.time_state();
+
+        ViewModel {
+            width: gs.matrix_width(),
+            height: gs.matrix_height(),
+            visible_height: gs.config().visible_height(),
+            squares,
+            preview: gs.next_preview().iter().map(PieceView::new).collect(),
+            hold: gs.held().as_ref().map(PieceView::new),
+            hold_available: gs.can_hold(),
+            timers: Timers {
+                score: tgs.score(),
+                level: tgs.level(),
+                lines_cleared: gs.lines_cleared(),
+                pieces_placed: gs.pieces_placed(),
+                elapsed: tgs.elapsed(),
+                time_to_lock: time_state.time_to_lock,
+                time_to_fall: match time_state.action {
+                    TimeStateAction::Falling { time_to_fall } => Some(time_to_fall),
+                    TimeStateAction::Locking => None,
+                },
+                time_remaining: tgs.time_remaining(),
+                time_to_garbage: time_state.time_to_garbage,
+                lines_remaining: tgs.lines_remaining(),
+                pending_garbage: tgs.pending_garbage()
+            }
+        }
+    }
+
+    /// Returns what to draw on the square at the given column and row.
+    pub fn square(&self, col: usize, row: usize) -> &Square {
+        &self.squares[col][row]
+    }
+}
+
+/// The differences between two snapshots of a game, so that a frontend that keeps what it drew
+/// can update only what changed.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct FrameDiff {
+    /// The column, row and new contents of each square of the matrix that changed.
+    pub squares: Vec<(usize, usize, Square)>,
+    /// The squares of the falling tetromino, if it moved, rotated or was replaced.
+    pub falling: Option<Vec<(usize, usize)>>,
+    /// The new next preview, if it changed.
+    pub preview: Option<Vec<PieceView>>,
+    /// The new held tetromino, if it changed.
+    pub hold: Option<Option<PieceView>>,
+    /// Whether the falling tetromino can be held, if that changed.
+    pub hold_available: Option<bool>,
+    /// The new numbers shown next to the matrix, if any of them changed.
+    pub timers: Option<Timers>
+}
+
+impl FrameDiff {
+
+    /// Returns `true` if nothing changed.
+    pub fn is_empty(&self) -> bool {
+        self.squares.is_empty() && self.falling.is_none() && self.preview.is_none()
+            && self.hold.is_none() && self.hold_available.is_none() && self.timers.is_none()
+    }
+}
+
+impl ViewModel {
+
+    /// Returns what changed between the given earlier snapshot and this one. Everything counts
+    /// as changed if there is no earlier snapshot, or if the matrix changed size.
+    pub fn changes_since(&self, previous: Option<&ViewModel>) -> FrameDiff {
+        let previous = previous.filter(|previous| {
+            previous.width == self.width && previous.height == self.height
+        });
+
+        let previous = match previous {
+            Some(previous) => previous,
+            None => return FrameDiff {
+                squares: self.squares.iter().enumerate().flat_map(|(col, column)| {
+                    column.iter().enumerate().map(move |(row, square)| (col, row, square.clone()))
+                }).collect(),
+                falling: Some(self.falling_squares()),
+                preview: Some(self.preview.clone()),
+                hold: Some(self.hold.clone()),
+                hold_available: Some(self.hold_available),
+                timers: Some(self.timers.clone())
+            },
+        };
+
+        let mut squares = Vec::new();
+        for (col, column) in self.squares.iter().enumerate() {
+            for (row, square) in column.iter().enumerate() {
+                if previous.square(col, row) != square {
+                    squares.push((col, row, square.clone()));
+                }
+            }
+        }
+
+        let falling = self.falling_squares();
+
+        FrameDiff {
+            squares,
+            falling: if falling != previous.falling_squares() { Some(falling) } else { None },
+            preview: if self.preview != previous.preview { Some(self.preview.clone()) } else { None },
+            hold: if self.hold != previous.hold { Some(self.hold.clone()) } else { None },
+            hold_available: if self.hold_available != previous.hold_available { Some(self.hold_available) } else { None },
+            timers: if self.timers != previous.timers { Some(self.timers.clone()) } else { None }
+        }
+    }
+
+    /// Returns the column and row of each square of the falling tetromino.
+    pub fn falling_squares(&self) -> Vec<(usize, usize)> {
+        let mut falling = Vec::new();
+        for (col, column) in self.squares.iter().enumerate() {
+            for (row, square) in column.iter().enumerate() {
+                if let Square::Falling(_) = *square {
+                    falling.push((col, row));
+                }
+            }
+        }
+        falling
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use ::bag::Bag;
+    use ::builder::GameStateBuilder;
+    use ::config::GameConfig;
+    use ::game_mode::GameMode;
+    use ::game_state::{GameState, Tetromino};
+    use ::TetrominoType::{O, T};
+
+    fn timed(gs: GameState) -> TimedGameState {
+        TimedGameState::with_game_state(gs, GameMode::Endless)
+    }
+
+    fn timed_from(diagram: &str) -> TimedGameState {
+        timed(GameStateBuilder::new().board(diagram).queue("OOOOO").build().unwrap())
+    }
+
+    /// A T in the air above an empty floor, with a locked square in the corner.
+    const FLOATING_T: &str = "\
+        ....t.....
+        ...ttt....
+        ..........
+        ..........
+        G.........";
+
+    #[test]
+    fn draws_the_falling_tetromino_over_its_ghost() {
+        let view = ViewModel::new(&timed_from(FLOATING_T));
+
+        for &(col, row) in &[(3, 3), (4, 3), (5, 3), (4, 4)] {
+            assert_eq!(view.square(col, row), &Square::Falling(T));
+        }
+        for &(col, row) in &[(3, 0), (4, 0), (5, 0), (4, 1)] {
+            assert_eq!(view.square(col, row), &Square::Ghost(T));
+        }
+        assert_eq!(view.square(0, 0), &Square::Locked(::Cell::Garbage));
+        assert_eq!(view.square(9, 0), &Square::Empty);
+        assert_eq!(view.falling_squares(), vec![(3, 3), (4, 3), (4, 4), (5, 3)]);
+    }
+
+    #[test]
+    fn hides_the_ghost_under_a_tetromino_on_the_ground() {
+        let view = ViewModel::new(&timed_from("....t.....\n...ttt...."));
+
+        let ghosts = view.squares.iter().flatten().filter(|square| matches!(**square, Square::Ghost(_))).count();
+        assert_eq!(ghosts, 0);
+        assert_eq!(view.falling_squares().len(), 4);
+    }
+
+    #[test]
+    fn draws_invisible_squares_as_empty() {
+        let config = GameConfig::default();
+        let mut placed_squares = vec![vec![::Cell::Empty; config.matrix_height]; config.matrix_width];
+        placed_squares[0][0] = ::Cell::Invisible(T);
+        placed_squares[1][0] = ::Cell::Piece(T);
+        let falling = Tetromino::spawned(O, &config);
+        let bag = Bag::with_config(&config);
+        let gs = GameState::from_parts(config, placed_squares, falling, bag, vec![O; 5], None);
+
+        let view = ViewModel::new(&timed(gs));
+        assert_eq!(view.square(0, 0), &Square::Empty);
+        assert_eq!(view.square(1, 0), &Square::Locked(::Cell::Piece(T)));
+    }
+
+    #[test]
+    fn changes_everything_without_an_earlier_snapshot() {
+        let view = ViewModel::new(&timed_from(FLOATING_T));
+        let diff = view.changes_since(None);
+
+        assert_eq!(diff.squares.len(), view.width * view.height);
+        assert_eq!(diff.falling, Some(view.falling_squares()));
+        assert_eq!(diff.preview, Some(view.preview.clone()));
+        assert_eq!(diff.hold, Some(None));
+        assert_eq!(diff.hold_available, Some(true));
+        assert_eq!(diff.timers, Some(view.timers.clone()));
+    }
+
+    #[test]
+    fn changes_everything_when_the_matrix_changes_size() {
+        let narrow = GameConfig { matrix_width: 8, ..GameConfig::default() };
+        let previous = ViewModel::new(&timed(GameState::with_config(narrow)));
+        let view = ViewModel::new(&timed_from(FLOATING_T));
+
+        assert_eq!(view.changes_since(Some(&previous)).squares.len(), view.width * view.height);
+    }
+
+    #[test]
+    fn changes_only_the_squares_that_moved() {
+        let mut tgs = timed_from(FLOATING_T);
+        let previous = ViewModel::new(&tgs);
+        assert!(tgs.move_left());
+        let view = ViewModel::new(&tgs);
+
+        let mut squares = view.changes_since(Some(&previous)).squares;
+        squares.sort_by_key(|&(col, row, _)| (col, row));
+        assert_eq!(squares, vec![
+            (2, 0, Square::Ghost(T)),
+            (2, 3, Square::Falling(T)),
+            (3, 1, Square::Ghost(T)),
+            (3, 4, Square::Falling(T)),
+            (4, 1, Square::Empty),
+            (4, 4, Square::Empty),
+            (5, 0, Square::Empty),
+            (5, 3, Square::Empty),
+        ]);
+
+        let diff = view.changes_since(Some(&previous));
+        assert_eq!(diff.falling, Some(view.falling_squares()));
+        assert_eq!((diff.preview, diff.hold, diff.hold_available), (None, None, None));
+    }
+
+    #[test]
+    fn changes_nothing_between_equal_snapshots() {
+        let view = ViewModel::new(&timed_from(FLOATING_T));
+        assert!(view.changes_since(Some(&view.clone())).is_empty());
+    }
+}